@@ -0,0 +1,167 @@
+//! Aggregation of proofs over several files tagged with the same PoR key.
+//!
+//! BLS tags are homomorphic, so the answers `(σ_f, μ_f)` to challenges over files
+//! `f = 1..F` add up to a single `(σ, μ)` with `σ = ∏ σ_f` and `μ_j = Σ_f μ_{f,j}`.
+//! The verifier checks it with one pairing equation
+//! `e(σ, g) == e(∏_f ∏_i H(name_f || i || version_i)^{ν_i} · ∏ u_j^{μ_j}, v)`.
+//! File names must be distinct so the hashed indices of different files never collide.
+
+use crate::por::PorPublicKey;
+use crate::proof::{challenge_hash_sum, multiplication_sum, prove, Challenge, Proof, ProofError, StoredFile};
+use crate::verification::{verify_proof, ProofInstance};
+use crate::wire::WireProof;
+use bls12_381::{G1Affine, G1Projective, Scalar};
+use std::collections::HashSet;
+
+/// Combines proofs produced with the same public key into a single proof.
+pub fn aggregate(proofs: &[Proof]) -> Result<Proof, ProofError> {
+    let sectors_per_block = proofs
+        .first()
+        .map(|proof| proof.mu.len())
+        .ok_or_else(|| ProofError::InvalidAggregation("no proof to aggregate".to_string()))?;
+
+    let mut sigma = G1Projective::identity();
+    let mut mu = vec![Scalar::zero(); sectors_per_block];
+    for proof in proofs {
+        if proof.mu.len() != sectors_per_block {
+            return Err(ProofError::SectorCountMismatch {
+                expected: sectors_per_block,
+                actual: proof.mu.len(),
+            });
+        }
+
+        sigma += proof.sigma;
+        for (mu_j, proof_mu_j) in mu.iter_mut().zip(&proof.mu) {
+            *mu_j += proof_mu_j;
+        }
+    }
+
+    Ok(Proof {
+        sigma: G1Affine::from(sigma),
+        mu,
+    })
+}
+
+/// Answers the challenges over several files with a single aggregated proof.
+pub fn prove_aggregated(
+    public: &PorPublicKey,
+    challenges: &[(Challenge, &dyn StoredFile)],
+) -> Result<Proof, ProofError> {
+    check_distinct_files(challenges.iter().map(|(challenge, _)| challenge))?;
+
+    let proofs = challenges
+        .iter()
+        .map(|(challenge, file)| prove(public, challenge, *file))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    aggregate(&proofs)
+}
+
+/// Builds the pairing check of an aggregated proof.
+pub fn aggregated_proof_instance(
+    public: &PorPublicKey,
+    challenges: &[Challenge],
+    proof: &Proof,
+) -> Result<ProofInstance, ProofError> {
+    check_distinct_files(challenges.iter())?;
+
+    let hash_sum = challenges
+        .iter()
+        .fold(G1Projective::identity(), |sum, challenge| sum + challenge_hash_sum(challenge));
+
+    Ok(ProofInstance {
+        g: public.g,
+        v: public.v,
+        sigma: proof.sigma,
+        multiplication_sum: multiplication_sum(public, hash_sum, &proof.mu)?,
+    })
+}
+
+/// Checks an aggregated proof against the challenges it answers.
+pub fn verify_aggregated(public: &PorPublicKey, challenges: &[Challenge], proof: &Proof) -> bool {
+    aggregated_proof_instance(public, challenges, proof)
+        .map(|instance| verify_proof(&instance))
+        .unwrap_or(false)
+}
+
+fn check_distinct_files<'a>(challenges: impl Iterator<Item = &'a Challenge>) -> Result<(), ProofError> {
+    let mut file_names = HashSet::new();
+    for challenge in challenges {
+        if !file_names.insert(challenge.file_name.as_slice()) {
+            return Err(ProofError::InvalidAggregation(format!(
+                "file {} is challenged more than once",
+                String::from_utf8_lossy(&challenge.file_name)
+            )));
+        }
+    }
+
+    if file_names.is_empty() {
+        return Err(ProofError::InvalidAggregation("no challenge to aggregate".to_string()));
+    }
+    Ok(())
+}
+
+/// Encodes a proof as the `sigma` and `mu` arguments of the `submit_aggregated_proof` instruction.
+pub fn submit_aggregated_proof_args(proof: &Proof) -> ([u8; 48], Vec<[u8; 32]>) {
+    let wire = WireProof::from(proof);
+    (wire.sigma, wire.mu)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::test_support::{challenge, keys, tagged_file};
+
+    #[test]
+    fn aggregated_proof_verifies_over_every_file() {
+        let keys = keys(1);
+        let photos = tagged_file(&keys, b"photos", 4, 1);
+        let mail = tagged_file(&keys, b"mail", 4, 100);
+        let challenges = [challenge(b"photos", [0, 2]), challenge(b"mail", [1, 3])];
+
+        let proof = prove_aggregated(
+            &keys.public,
+            &[(challenges[0].clone(), &photos as &dyn StoredFile), (challenges[1].clone(), &mail)],
+        )
+        .unwrap();
+        assert!(verify_aggregated(&keys.public, &challenges, &proof));
+        // The proof answers both challenges, not one of them
+        assert!(!verify_aggregated(&keys.public, &challenges[..1], &proof));
+    }
+
+    #[test]
+    fn a_lost_file_fails_the_whole_aggregate() {
+        let keys = keys(2);
+        let photos = tagged_file(&keys, b"photos", 4, 1);
+        let mut mail = tagged_file(&keys, b"mail", 4, 100);
+        mail.blocks[1][0] ^= 1;
+        let challenges = [challenge(b"photos", [0]), challenge(b"mail", [1])];
+
+        let proof = prove_aggregated(
+            &keys.public,
+            &[(challenges[0].clone(), &photos as &dyn StoredFile), (challenges[1].clone(), &mail)],
+        )
+        .unwrap();
+        assert!(!verify_aggregated(&keys.public, &challenges, &proof));
+    }
+
+    #[test]
+    fn rejects_what_cannot_be_aggregated() {
+        let keys = keys(3);
+        let file = tagged_file(&keys, b"photos", 4, 1);
+        let repeated = [challenge(b"photos", [0]), challenge(b"photos", [1])];
+
+        assert!(matches!(aggregate(&[]), Err(ProofError::InvalidAggregation(_))));
+        assert!(matches!(
+            prove_aggregated(&keys.public, &[(repeated[0].clone(), &file as &dyn StoredFile), (repeated[1].clone(), &file)]),
+            Err(ProofError::InvalidAggregation(_))
+        ));
+
+        let short = Proof { sigma: G1Affine::identity(), mu: vec![Scalar::one()] };
+        let long = Proof { sigma: G1Affine::identity(), mu: vec![Scalar::one(); 2] };
+        assert!(matches!(
+            aggregate(&[long, short]),
+            Err(ProofError::SectorCountMismatch { expected: 2, actual: 1 })
+        ));
+    }
+}
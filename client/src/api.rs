@@ -0,0 +1,263 @@
+//! Errors of the HTTP API, answered as JSON `{code, message, details}`.
+//!
+//! `code` is stable and meant to be matched on by API clients. Errors of the mediator program
+//! use the snake_case name of their `ErrorCode` variant (`not_server_turn`,
+//! `insufficient_funds`...), so clients never have to parse messages or error numbers.
+
+use crate::signer::SignerError;
+use crate::transactions::TransactionError as SendError;
+use anchor_client::solana_client::client_error::{ClientError as RpcError, ClientErrorKind as RpcErrorKind};
+use anchor_client::solana_sdk::instruction::InstructionError;
+use anchor_client::solana_sdk::transaction::TransactionError;
+use anchor_lang::error::ERROR_CODE_OFFSET;
+use serde::Serialize;
+use serde_json::{json, Value};
+use solana_smart_contract::ErrorCode;
+use std::fmt;
+use utoipa::ToSchema;
+use warp::http::StatusCode;
+use warp::reject::Reject;
+
+/// Every error of the program, in declaration order: the error number of `PROGRAM_ERRORS[i]`
+/// is `ERROR_CODE_OFFSET + i`, which `program_error` relies on. New variants must be appended
+/// here too.
+const PROGRAM_ERRORS: [ErrorCode; 23] = [
+    ErrorCode::InvalidCurvePoints,
+    ErrorCode::CurvePointsAlreadySet,
+    ErrorCode::InsufficientFunds,
+    ErrorCode::SubscriptionDoesntStart,
+    ErrorCode::NotClientTurn,
+    ErrorCode::NotServerTurn,
+    ErrorCode::SubscriptionEnded,
+    ErrorCode::NotSubscriptionClient,
+    ErrorCode::StaleFileVersion,
+    ErrorCode::ProofKindMismatch,
+    ErrorCode::InvalidMerkleCommitment,
+    ErrorCode::InvalidChallengeSize,
+    ErrorCode::NoPendingChallenge,
+    ErrorCode::ChallengePending,
+    ErrorCode::InvalidMerkleProof,
+    ErrorCode::NoProgramContexts,
+    ErrorCode::PublicKeyMismatch,
+    ErrorCode::DuplicateProgramContext,
+    ErrorCode::MalformedProofOfPossession,
+    ErrorCode::InvalidPorParameters,
+    ErrorCode::SectorCountMismatch,
+    ErrorCode::NotSubscriptionServer,
+    ErrorCode::EmptyFileState,
+];
+
+/// Finds the program error behind a custom instruction error number.
+pub fn program_error(number: u32) -> Option<ErrorCode> {
+    let index = number.checked_sub(ERROR_CODE_OFFSET)?;
+    PROGRAM_ERRORS.get(index as usize).copied()
+}
+
+#[derive(Debug)]
+pub enum ApiError {
+    /// The request body or parameters are malformed.
+    InvalidInput(String),
+    /// A curve point doesn't decompress, or a proof of possession doesn't verify.
+    InvalidCurvePoint(String),
+    /// No signer or account matches the reference.
+    UnknownSigner(String),
+    NotFound(String),
+    /// The program rejected instruction `instruction` of the transaction.
+    Program { error: ErrorCode, instruction: u8 },
+    /// The fee payer can't pay for the transaction or the accounts it creates.
+    InsufficientFunds(String),
+    /// The transaction failed for another reason, e.g. an error of the Solana runtime.
+    Transaction(TransactionError),
+    /// The remote signer failed.
+    Signer(String),
+    Rpc(String),
+    Timeout(String),
+    Internal(String),
+}
+
+/// JSON body of every error response.
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<Value>,
+}
+
+impl ApiError {
+    pub fn code(&self) -> String {
+        match self {
+            ApiError::InvalidInput(_) => "invalid_input".to_string(),
+            ApiError::InvalidCurvePoint(_) => "invalid_curve_point".to_string(),
+            ApiError::UnknownSigner(_) => "unknown_signer".to_string(),
+            ApiError::NotFound(_) => "not_found".to_string(),
+            ApiError::Program { error, .. } => snake_case(&error.name()),
+            ApiError::InsufficientFunds(_) => "insufficient_funds".to_string(),
+            ApiError::Transaction(_) => "transaction_failed".to_string(),
+            ApiError::Signer(_) => "signer_error".to_string(),
+            ApiError::Rpc(_) => "rpc_error".to_string(),
+            ApiError::Timeout(_) => "timeout".to_string(),
+            ApiError::Internal(_) => "internal_error".to_string(),
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::InvalidInput(_) | ApiError::InvalidCurvePoint(_) => StatusCode::BAD_REQUEST,
+            ApiError::UnknownSigner(_) | ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Program { error: ErrorCode::InsufficientFunds, .. } | ApiError::InsufficientFunds(_) => {
+                StatusCode::PAYMENT_REQUIRED
+            }
+            ApiError::Program { .. } | ApiError::Transaction(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Signer(_) | ApiError::Rpc(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn details(&self) -> Option<Value> {
+        match self {
+            ApiError::Program { error, instruction } => Some(json!({
+                "error_number": u32::from(*error),
+                "error_name": error.name(),
+                "instruction": instruction,
+            })),
+            ApiError::Transaction(err) => Some(json!({ "transaction_error": format!("{:?}", err) })),
+            _ => None,
+        }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        ErrorBody {
+            code: self.code(),
+            message: self.to_string(),
+            details: self.details(),
+        }
+    }
+
+    fn from_transaction_error(err: TransactionError) -> Self {
+        match err {
+            TransactionError::InstructionError(instruction, InstructionError::Custom(number)) => match program_error(number) {
+                Some(error) => ApiError::Program { error, instruction },
+                None => ApiError::Transaction(TransactionError::InstructionError(instruction, InstructionError::Custom(number))),
+            },
+            TransactionError::InsufficientFundsForFee | TransactionError::InsufficientFundsForRent { .. } => {
+                ApiError::InsufficientFunds(err.to_string())
+            }
+            err => ApiError::Transaction(err),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::InvalidInput(message)
+            | ApiError::InvalidCurvePoint(message)
+            | ApiError::NotFound(message)
+            | ApiError::InsufficientFunds(message)
+            | ApiError::Internal(message) => write!(f, "{}", message),
+            ApiError::UnknownSigner(reference) => write!(f, "Unknown signer or account {}", reference),
+            ApiError::Program { error, .. } => write!(f, "{}", error),
+            ApiError::Transaction(err) => write!(f, "Transaction failed: {}", err),
+            ApiError::Signer(message) => write!(f, "Signer error: {}", message),
+            ApiError::Rpc(message) => write!(f, "RPC error: {}", message),
+            ApiError::Timeout(message) => write!(f, "Timed out: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl Reject for ApiError {}
+
+impl From<RpcError> for ApiError {
+    fn from(err: RpcError) -> Self {
+        if let Some(transaction_error) = err.get_transaction_error() {
+            return ApiError::from_transaction_error(transaction_error);
+        }
+
+        match err.kind() {
+            RpcErrorKind::Reqwest(reqwest_error) if reqwest_error.is_timeout() => ApiError::Timeout(err.to_string()),
+            _ => ApiError::Rpc(err.to_string()),
+        }
+    }
+}
+
+impl From<SignerError> for ApiError {
+    fn from(err: SignerError) -> Self {
+        match err {
+            SignerError::UnknownSigner(reference) => ApiError::UnknownSigner(reference),
+            SignerError::InvalidId(_) => ApiError::InvalidInput(err.to_string()),
+            SignerError::Remote(_) => ApiError::Signer(err.to_string()),
+            SignerError::InvalidKeystore(_) | SignerError::Io(_) => ApiError::Internal(err.to_string()),
+        }
+    }
+}
+
+impl From<SendError> for ApiError {
+    fn from(err: SendError) -> Self {
+        match err {
+            SendError::Rpc(err) => ApiError::from(*err),
+            SendError::Signer(err) => ApiError::from(err),
+            SendError::InvalidAccount(_) | SendError::InvalidMediator(_) => ApiError::NotFound(err.to_string()),
+            SendError::InvalidTransaction(_) => ApiError::Rpc(err.to_string()),
+            SendError::Timeout(_) | SendError::Expired(_) => ApiError::Timeout(err.to_string()),
+        }
+    }
+}
+
+/// Rejects a warp request with `err`, answered by the API's rejection handler.
+pub fn reject(err: impl Into<ApiError>) -> warp::Rejection {
+    warp::reject::custom(err.into())
+}
+
+// "NotServerTurn" -> "not_server_turn"
+fn snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (index, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if index > 0 {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_program_error_is_found_by_its_number() {
+        for (index, error) in PROGRAM_ERRORS.iter().enumerate() {
+            let number = ERROR_CODE_OFFSET + index as u32;
+            assert_eq!(u32::from(*error), number, "{} is out of declaration order", error.name());
+            assert_eq!(program_error(number).map(|found| found.name()), Some(error.name()));
+        }
+    }
+
+    #[test]
+    fn numbers_outside_the_program_errors_are_not_program_errors() {
+        assert!(program_error(ERROR_CODE_OFFSET + PROGRAM_ERRORS.len() as u32).is_none());
+        assert!(program_error(ERROR_CODE_OFFSET - 1).is_none());
+        assert!(program_error(0).is_none());
+    }
+
+    #[test]
+    fn program_errors_are_answered_with_their_snake_case_name() {
+        let error = ApiError::from_transaction_error(TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(u32::from(ErrorCode::NotSubscriptionServer)),
+        ));
+
+        assert_eq!(error.code(), "not_subscription_server");
+        assert_eq!(error.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error.details().unwrap()["instruction"], 1);
+    }
+}
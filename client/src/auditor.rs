@@ -0,0 +1,490 @@
+//! Auditor daemon: drives the protocol from the client side.
+//!
+//! Every `interval_secs`, each configured subscription is challenged in turn: a fresh challenge
+//! is sampled and sent to the storage server's `/challenge` endpoint in the binary wire format
+//! (signed with the client keypair, see `auth`). The proofs returned in the round are then
+//! verified together against the local PoR public key with one batch check, which falls back to
+//! per-proof checks only when it fails. Every audit is appended to a JSON-lines log; a log that
+//! can't be written is reported and the daemon carries on.
+//!
+//! Settlement follows the program's turn-based flow. After a valid proof, once the server has
+//! retrieved its payment (the client's turn), the auditor pays the next period with
+//! `extend_subscription`. An invalid or missing proof leaves the period unpaid, and after
+//! `max_failures` consecutive ones the auditor gives up on the subscription for good, recording
+//! it in `<audit_log>.abandoned.json` so a restart doesn't resume paying. It never sends
+//! `end_subscription`: the program would then let the server retrieve the whole escrow.
+//!
+//! There are no timeout claims. The program has no notion of time, so a challenge the server
+//! leaves unanswered past `response_timeout_secs` can't be proven on-chain; the auditor counts it
+//! as a failed audit and withholds the next payment instead.
+//!
+//! Only statically tagged files are audited. Once the client committed a new file state
+//! (`file_version > 0`), challenges need the version of every block, which only the client
+//! keeps, so such subscriptions are skipped.
+
+use crate::auth::SignedRequest;
+use crate::challenge::{random_seed, sample_challenge, ChallengePolicy};
+use crate::config::{ClusterConfig, ClusterSettings};
+use crate::pop::verify_program_context;
+use crate::por::PorKeyPair;
+use crate::proof::{proof_instance, Proof};
+use crate::verification::{verify_batch, BatchVerification, ProofInstance};
+use crate::wire::{WireChallenge, WireMessage, WireProof};
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::solana_sdk::signature::{read_keypair_file, Keypair, Signature};
+use anchor_client::solana_sdk::signer::Signer;
+use anchor_client::solana_sdk::system_program;
+use anchor_client::{Client, ClientError, Cluster, Program};
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use solana_smart_contract::{accounts, instruction as ix, ProgramContext};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug)]
+pub enum AuditorError {
+    Config(String),
+    Io(io::Error),
+    Client(Box<ClientError>),
+}
+
+impl fmt::Display for AuditorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditorError::Config(reason) => write!(f, "Invalid auditor configuration: {}", reason),
+            AuditorError::Io(err) => write!(f, "I/O error: {}", err),
+            AuditorError::Client(err) => write!(f, "Solana client error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for AuditorError {}
+
+impl From<io::Error> for AuditorError {
+    fn from(err: io::Error) -> Self {
+        AuditorError::Io(err)
+    }
+}
+
+impl From<ClientError> for AuditorError {
+    fn from(err: ClientError) -> Self {
+        AuditorError::Client(Box::new(err))
+    }
+}
+
+/// Auditor configuration, read from a JSON file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditorConfig {
+    /// Cluster and program, overridden by the environment and the command line like those of the API.
+    #[serde(flatten)]
+    pub cluster: ClusterSettings,
+    /// Solana keypair file of the client, which pays for the subscriptions.
+    pub keypair_path: String,
+    pub interval_secs: u64,
+    /// How long the server has to answer a challenge; a late answer counts as no answer, since
+    /// the program can't tell how long a challenge went unanswered.
+    pub response_timeout_secs: u64,
+    /// Consecutive failed audits after which the auditor stops paying for the subscription.
+    pub max_failures: u32,
+    /// JSON-lines file every audit is appended to. The subscriptions given up on are kept
+    /// next to it, in `<audit_log>.abandoned.json`.
+    pub audit_log: String,
+    pub subscriptions: Vec<AuditedSubscription>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditedSubscription {
+    pub program_context: String,
+    pub mediator: String,
+    pub file_name: String,
+    /// Number of blocks of the statically tagged file.
+    pub block_count: u64,
+    /// Base URL of the storage server, e.g. `http://127.0.0.1:3031`.
+    pub server_url: String,
+    pub epsilon: f64,
+    pub delta: f64,
+}
+
+impl AuditorConfig {
+    pub fn load(path: &str) -> Result<Self, AuditorError> {
+        serde_json::from_slice(&fs::read(path)?).map_err(|err| AuditorError::Config(err.to_string()))
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    /// The server answered with a valid proof.
+    Valid,
+    /// The server answered with a proof that doesn't verify.
+    Invalid,
+    /// The server didn't answer in time, or with garbage.
+    NoResponse,
+    /// The subscription couldn't be audited (ended, given up, dynamic, misconfigured, RPC failure...).
+    Skipped,
+}
+
+/// One line of the audit log.
+#[derive(Serialize, Debug, Clone)]
+pub struct AuditRecord {
+    pub timestamp: u64,
+    pub program_context: String,
+    pub file_name: String,
+    pub outcome: AuditOutcome,
+    pub sample_size: usize,
+    pub confidence: f64,
+    pub detail: Option<String>,
+    /// Signature of the settlement transaction sent after this audit, if any.
+    pub settlement: Option<String>,
+}
+
+/// A subscription challenged this round, waiting for the verification of its answer.
+struct PendingAudit {
+    record: AuditRecord,
+    program_context: ProgramContext,
+    program_context_pubkey: Pubkey,
+    mediator_pubkey: Pubkey,
+    /// Pairing check of the returned proof, or why there is none.
+    answer: Result<ProofInstance, String>,
+}
+
+pub struct Auditor {
+    config: AuditorConfig,
+    keys: Arc<PorKeyPair>,
+    payer: Arc<Keypair>,
+    program: Program<Arc<Keypair>>,
+    http: reqwest::Client,
+    failures: HashMap<String, u32>,
+    /// Subscriptions given up after `max_failures` consecutive failed audits.
+    abandoned: HashSet<String>,
+}
+
+impl Auditor {
+    pub fn new(config: AuditorConfig, cluster: ClusterConfig, keys: Arc<PorKeyPair>) -> Result<Self, AuditorError> {
+        let payer = Arc::new(
+            read_keypair_file(&config.keypair_path).map_err(|err| AuditorError::Config(err.to_string()))?,
+        );
+        let client = Client::new_with_options(
+            Cluster::Custom(cluster.rpc_url, cluster.ws_url),
+            Arc::clone(&payer),
+            cluster.commitment,
+        );
+        let program = client.program(cluster.program_id)?;
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.response_timeout_secs))
+            .build()
+            .map_err(|err| AuditorError::Config(err.to_string()))?;
+        let abandoned = load_abandoned(&abandoned_path(&config.audit_log))?;
+
+        Ok(Auditor {
+            config,
+            keys,
+            payer,
+            program,
+            http,
+            failures: HashMap::new(),
+            abandoned,
+        })
+    }
+
+    /// Audits every subscription once per interval, forever.
+    pub async fn run(mut self) -> Result<(), AuditorError> {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.interval_secs));
+        loop {
+            interval.tick().await;
+
+            for record in self.audit_all().await {
+                println!(
+                    "Audit of {} ({}): {:?}{}",
+                    record.program_context,
+                    record.file_name,
+                    record.outcome,
+                    record.detail.as_ref().map(|detail| format!(" - {}", detail)).unwrap_or_default()
+                );
+                // A full disk must not stop the audits, nor the payments they decide
+                if let Err(err) = self.append_record(&record) {
+                    eprintln!("Failed to append the audit of {} to {}: {}", record.program_context, self.config.audit_log, err);
+                }
+            }
+        }
+    }
+
+    /// Challenges the server of every subscription once, verifies the proofs in one batch and
+    /// settles each subscription according to its result.
+    pub async fn audit_all(&mut self) -> Vec<AuditRecord> {
+        let mut records = Vec::new();
+        let mut pending = Vec::new();
+        for subscription in self.config.subscriptions.clone() {
+            match self.challenge(&subscription).await {
+                Ok(audit) => pending.push(audit),
+                Err(record) => records.push(record),
+            }
+        }
+
+        let (indices, instances): (Vec<usize>, Vec<ProofInstance>) = pending
+            .iter()
+            .enumerate()
+            .filter_map(|(index, audit)| Some((index, *audit.answer.as_ref().ok()?)))
+            .unzip();
+        let invalid = match verify_batch(&instances, &mut OsRng) {
+            BatchVerification::AllValid => HashSet::new(),
+            BatchVerification::Invalid(invalid) => invalid.into_iter().map(|position| indices[position]).collect(),
+        };
+
+        for (index, audit) in pending.into_iter().enumerate() {
+            let PendingAudit {
+                mut record,
+                program_context,
+                program_context_pubkey,
+                mediator_pubkey,
+                answer,
+            } = audit;
+            record.outcome = match answer {
+                Ok(_) if invalid.contains(&index) => AuditOutcome::Invalid,
+                Ok(_) => AuditOutcome::Valid,
+                Err(reason) => {
+                    record.detail = Some(reason);
+                    AuditOutcome::NoResponse
+                }
+            };
+
+            let settlement = self
+                .settle(&record.program_context, &program_context, program_context_pubkey, mediator_pubkey, record.outcome)
+                .await;
+            match settlement {
+                Ok(signature) => record.settlement = signature.map(|signature| signature.to_string()),
+                Err(err) => record.detail = Some(format!("settlement failed: {}", err)),
+            }
+            records.push(record);
+        }
+
+        records
+    }
+
+    /// Challenges the server of `subscription` once, or returns the record of a skipped audit.
+    async fn challenge(&self, subscription: &AuditedSubscription) -> Result<PendingAudit, AuditRecord> {
+        let mut record = AuditRecord {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0),
+            program_context: subscription.program_context.clone(),
+            file_name: subscription.file_name.clone(),
+            outcome: AuditOutcome::Skipped,
+            sample_size: 0,
+            confidence: 0.0,
+            detail: None,
+            settlement: None,
+        };
+
+        if self.abandoned.contains(&subscription.program_context) {
+            record.detail = Some(format!("given up after {} consecutive failed audits", self.config.max_failures));
+            return Err(record);
+        }
+
+        let (program_context_pubkey, mediator_pubkey) = match (
+            Pubkey::from_str(&subscription.program_context),
+            Pubkey::from_str(&subscription.mediator),
+        ) {
+            (Ok(program_context), Ok(mediator)) => (program_context, mediator),
+            _ => {
+                record.detail = Some("invalid program context or mediator pubkey".to_string());
+                return Err(record);
+            }
+        };
+
+        let program_context = match self.program.account::<ProgramContext>(program_context_pubkey).await {
+            Ok(program_context) => program_context,
+            Err(err) => {
+                record.detail = Some(format!("failed to fetch the program context: {}", err));
+                return Err(record);
+            }
+        };
+        if let Err(reason) = self.check_program_context(&program_context) {
+            record.detail = Some(reason);
+            return Err(record);
+        }
+
+        let policy = ChallengePolicy::Detection {
+            epsilon: subscription.epsilon,
+            delta: subscription.delta,
+        };
+        let sampled = match sample_challenge(&random_seed(), subscription.file_name.as_bytes(), subscription.block_count, &policy) {
+            Ok(sampled) => sampled,
+            Err(err) => {
+                record.detail = Some(err.to_string());
+                return Err(record);
+            }
+        };
+        record.sample_size = sampled.challenge.items.len();
+        record.confidence = sampled.confidence;
+
+        let answer = self
+            .request_proof(&subscription.server_url, &WireChallenge::from(&sampled.challenge))
+            .await
+            .and_then(|proof| proof_instance(&self.keys.public, &sampled.challenge, &proof).map_err(|err| err.to_string()));
+
+        Ok(PendingAudit {
+            record,
+            program_context,
+            program_context_pubkey,
+            mediator_pubkey,
+            answer,
+        })
+    }
+
+    /// Only audit live subscriptions registered with our key and a valid proof of possession.
+    fn check_program_context(&self, program_context: &ProgramContext) -> Result<(), String> {
+        if program_context.is_subscription_ended {
+            return Err("the subscription has ended".to_string());
+        }
+        if program_context.g_norm != self.keys.public.g.to_compressed()
+            || program_context.v_norm != self.keys.public.v.to_compressed()
+        {
+            return Err("the subscription uses another PoR key".to_string());
+        }
+        if !verify_program_context(program_context) {
+            return Err("invalid proof of possession".to_string());
+        }
+        // Sampling at version 0 would challenge the stale tags of every updated block
+        if program_context.file_version > 0 {
+            return Err(format!(
+                "the file was updated on-chain (version {}) and the auditor doesn't track block versions",
+                program_context.file_version
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn request_proof(&self, server_url: &str, challenge: &WireChallenge) -> Result<Proof, String> {
+        // The server only answers, and submits on-chain, the challenges signed by the client
+        let body = challenge.to_wire();
+        let mut request = self
+            .http
+            .post(format!("{}/challenge", server_url.trim_end_matches('/')))
+            .header("content-type", "application/octet-stream");
+        for (header, value) in SignedRequest::sign_now(&self.payer, "POST", "/challenge", &body).headers() {
+            request = request.header(header, value);
+        }
+        let response = request
+            .body(body)
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("the server answered {}", response.status()));
+        }
+
+        let body = response.bytes().await.map_err(|err| err.to_string())?;
+        let proof = WireProof::from_wire(&body).map_err(|err| err.to_string())?;
+        Proof::try_from(&proof).map_err(|err| err.to_string())
+    }
+
+    async fn settle(
+        &mut self,
+        key: &str,
+        program_context: &ProgramContext,
+        program_context_pubkey: Pubkey,
+        mediator_pubkey: Pubkey,
+        outcome: AuditOutcome,
+    ) -> Result<Option<Signature>, ClientError> {
+        match outcome {
+            AuditOutcome::Valid => {
+                self.failures.remove(key);
+
+                // The server was paid for the period; pay the next one
+                if program_context.is_server_turn {
+                    return Ok(None);
+                }
+                let signature = self
+                    .program
+                    .request()
+                    .accounts(accounts::ExtendSubscription {
+                        payer: self.payer.as_ref().pubkey(),
+                        mediator: mediator_pubkey,
+                        program_context: program_context_pubkey,
+                        system_program: system_program::ID,
+                    })
+                    .args(ix::ExtendSubscription)
+                    .send()
+                    .await?;
+                Ok(Some(signature))
+            }
+            AuditOutcome::Invalid | AuditOutcome::NoResponse => {
+                // Withholding `extend_subscription` is the penalty. Ending the subscription would
+                // let the server retrieve the whole escrow, as the program has no refund path
+                let failures = self.failures.entry(key.to_string()).or_insert(0);
+                *failures += 1;
+                if *failures >= self.config.max_failures {
+                    self.failures.remove(key);
+                    self.abandoned.insert(key.to_string());
+                    let path = abandoned_path(&self.config.audit_log);
+                    if let Err(err) = save_abandoned(&path, &self.abandoned) {
+                        eprintln!("Failed to record the abandoned subscriptions in {}: {}", path.display(), err);
+                    }
+                }
+                Ok(None)
+            }
+            AuditOutcome::Skipped => Ok(None),
+        }
+    }
+
+    fn append_record(&self, record: &AuditRecord) -> Result<(), AuditorError> {
+        let mut line = serde_json::to_vec(record).map_err(|err| AuditorError::Config(err.to_string()))?;
+        line.push(b'\n');
+
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.config.audit_log)?;
+        file.write_all(&line)?;
+        Ok(())
+    }
+}
+
+/// File listing the subscriptions given up on, next to the audit log.
+fn abandoned_path(audit_log: &str) -> PathBuf {
+    PathBuf::from(format!("{}.abandoned.json", audit_log))
+}
+
+fn load_abandoned(path: &Path) -> Result<HashSet<String>, AuditorError> {
+    match fs::read(path) {
+        Ok(contents) => serde_json::from_slice(&contents)
+            .map_err(|err| AuditorError::Config(format!("invalid {}: {}", path.display(), err))),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(HashSet::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn save_abandoned(path: &Path, abandoned: &HashSet<String>) -> io::Result<()> {
+    let mut program_contexts: Vec<&String> = abandoned.iter().collect();
+    program_contexts.sort();
+    let contents = serde_json::to_vec_pretty(&program_contexts)?;
+
+    // Write next to the target and rename, so a crash never leaves a truncated list
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, contents)?;
+    fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn abandoned_subscriptions_survive_a_restart() {
+        let audit_log = std::env::temp_dir().join(format!("audits-{}.jsonl", std::process::id()));
+        let path = abandoned_path(audit_log.to_str().unwrap());
+        let _ = fs::remove_file(&path);
+        assert!(load_abandoned(&path).unwrap().is_empty());
+
+        let abandoned: HashSet<String> = ["context-b".to_string(), "context-a".to_string()].into();
+        save_abandoned(&path, &abandoned).unwrap();
+        assert_eq!(load_abandoned(&path).unwrap(), abandoned);
+
+        fs::write(&path, b"not json").unwrap();
+        assert!(matches!(load_abandoned(&path), Err(AuditorError::Config(_))));
+        fs::remove_file(&path).unwrap();
+    }
+}
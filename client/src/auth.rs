@@ -0,0 +1,229 @@
+//! Signed requests to the storage server.
+//!
+//! The storage server only accepts uploads from the client of a subscription, and only answers
+//! and submits on-chain the challenges of that client or of its auditor. Requests carry three
+//! headers:
+//!
+//! - `x-storage-signer`: the base58 public key of the sender,
+//! - `x-storage-timestamp`: the unix time of the request, in seconds,
+//! - `x-storage-signature`: the base58 ed25519 signature of the request by that key.
+//!
+//! The signed message is `"<METHOD> <path> <timestamp>\n"` followed by the SHA-256 of the body,
+//! so a signature can't be moved to another endpoint, file or body. Requests older or newer
+//! than `MAX_CLOCK_SKEW_SECS` are rejected, and a `ReplayCache` remembers the requests accepted
+//! within that window so a captured one can't be sent again.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::solana_sdk::signature::{Keypair, Signature};
+use anchor_client::solana_sdk::signer::Signer;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const SIGNER_HEADER: &str = "x-storage-signer";
+pub const TIMESTAMP_HEADER: &str = "x-storage-timestamp";
+pub const SIGNATURE_HEADER: &str = "x-storage-signature";
+
+/// Largest accepted difference between the timestamp of a request and the server clock.
+pub const MAX_CLOCK_SKEW_SECS: u64 = 300;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AuthError {
+    MissingHeader(&'static str),
+    InvalidHeader(&'static str),
+    /// The timestamp is too far from the server clock.
+    Expired { timestamp: u64, now: u64 },
+    InvalidSignature,
+    /// The same signed request was already accepted.
+    Replayed,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::MissingHeader(header) => write!(f, "Missing {} header", header),
+            AuthError::InvalidHeader(header) => write!(f, "Invalid {} header", header),
+            AuthError::Expired { timestamp, now } => {
+                write!(f, "The request was signed at {}, the server time is {}", timestamp, now)
+            }
+            AuthError::InvalidSignature => write!(f, "Invalid request signature"),
+            AuthError::Replayed => write!(f, "The request was already received"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// Authentication of one request, as sent in its headers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedRequest {
+    pub signer: Pubkey,
+    pub timestamp: u64,
+    pub signature: Signature,
+}
+
+impl SignedRequest {
+    /// Signs `method path` with `body` at `timestamp`.
+    pub fn sign(keypair: &Keypair, method: &str, path: &str, timestamp: u64, body: &[u8]) -> Self {
+        SignedRequest {
+            signer: keypair.pubkey(),
+            timestamp,
+            signature: keypair.sign_message(&request_message(method, path, timestamp, body)),
+        }
+    }
+
+    /// Signs `method path` with `body` at the current time.
+    pub fn sign_now(keypair: &Keypair, method: &str, path: &str, body: &[u8]) -> Self {
+        Self::sign(keypair, method, path, unix_time(), body)
+    }
+
+    /// Parses the authentication headers, each `None` when the request doesn't carry it.
+    pub fn from_headers(signer: Option<&str>, timestamp: Option<&str>, signature: Option<&str>) -> Result<Self, AuthError> {
+        let signer = signer.ok_or(AuthError::MissingHeader(SIGNER_HEADER))?;
+        let timestamp = timestamp.ok_or(AuthError::MissingHeader(TIMESTAMP_HEADER))?;
+        let signature = signature.ok_or(AuthError::MissingHeader(SIGNATURE_HEADER))?;
+
+        Ok(SignedRequest {
+            signer: Pubkey::from_str(signer).map_err(|_| AuthError::InvalidHeader(SIGNER_HEADER))?,
+            timestamp: timestamp.parse().map_err(|_| AuthError::InvalidHeader(TIMESTAMP_HEADER))?,
+            signature: Signature::from_str(signature).map_err(|_| AuthError::InvalidHeader(SIGNATURE_HEADER))?,
+        })
+    }
+
+    /// The headers to send with the request.
+    pub fn headers(&self) -> [(&'static str, String); 3] {
+        [
+            (SIGNER_HEADER, self.signer.to_string()),
+            (TIMESTAMP_HEADER, self.timestamp.to_string()),
+            (SIGNATURE_HEADER, self.signature.to_string()),
+        ]
+    }
+
+    /// Checks that the request was signed by `signer` for `method path` with `body`, within
+    /// `MAX_CLOCK_SKEW_SECS` of `now`, and returns the signer.
+    pub fn verify(&self, method: &str, path: &str, body: &[u8], now: u64) -> Result<Pubkey, AuthError> {
+        if self.timestamp.abs_diff(now) > MAX_CLOCK_SKEW_SECS {
+            return Err(AuthError::Expired {
+                timestamp: self.timestamp,
+                now,
+            });
+        }
+        let message = request_message(method, path, self.timestamp, body);
+        if !self.signature.verify(self.signer.as_ref(), &message) {
+            return Err(AuthError::InvalidSignature);
+        }
+        Ok(self.signer)
+    }
+}
+
+/// Signed requests accepted within the last `MAX_CLOCK_SKEW_SECS`, to reject their replays.
+///
+/// Older requests are rejected as expired anyway, so they are forgotten.
+#[derive(Debug, Default)]
+pub struct ReplayCache {
+    seen: HashSet<(Pubkey, u64, Signature)>,
+}
+
+impl ReplayCache {
+    /// Records `request`, failing if it was already recorded. Call it after `verify`, so forged
+    /// requests never fill the cache.
+    pub fn check(&mut self, request: &SignedRequest, now: u64) -> Result<(), AuthError> {
+        self.seen.retain(|(_, timestamp, _)| timestamp.abs_diff(now) <= MAX_CLOCK_SKEW_SECS);
+        if !self.seen.insert((request.signer, request.timestamp, request.signature)) {
+            return Err(AuthError::Replayed);
+        }
+        Ok(())
+    }
+}
+
+/// Current unix time in seconds.
+pub fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0)
+}
+
+fn request_message(method: &str, path: &str, timestamp: u64, body: &[u8]) -> Vec<u8> {
+    let mut message = format!("{} {} {}\n", method, path, timestamp).into_bytes();
+    message.extend_from_slice(&Sha256::digest(body));
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[test]
+    fn a_signed_request_verifies_after_a_header_round_trip() {
+        let keypair = Keypair::new();
+        let request = SignedRequest::sign(&keypair, "POST", "/challenge", NOW, b"challenge");
+
+        let headers = request.headers();
+        let parsed = SignedRequest::from_headers(Some(&headers[0].1), Some(&headers[1].1), Some(&headers[2].1)).unwrap();
+
+        assert_eq!(parsed, request);
+        assert_eq!(parsed.verify("POST", "/challenge", b"challenge", NOW + 10), Ok(keypair.pubkey()));
+    }
+
+    #[test]
+    fn the_signature_covers_the_method_path_and_body() {
+        let request = SignedRequest::sign(&Keypair::new(), "PUT", "/files/61/blocks", NOW, b"blocks");
+
+        assert_eq!(request.verify("POST", "/files/61/blocks", b"blocks", NOW), Err(AuthError::InvalidSignature));
+        assert_eq!(request.verify("PUT", "/files/62/blocks", b"blocks", NOW), Err(AuthError::InvalidSignature));
+        assert_eq!(request.verify("PUT", "/files/61/blocks", b"other", NOW), Err(AuthError::InvalidSignature));
+
+        let mut forged = request.clone();
+        forged.signer = Keypair::new().pubkey();
+        assert_eq!(forged.verify("PUT", "/files/61/blocks", b"blocks", NOW), Err(AuthError::InvalidSignature));
+    }
+
+    #[test]
+    fn stale_requests_are_rejected() {
+        let request = SignedRequest::sign(&Keypair::new(), "POST", "/challenge", NOW, b"");
+
+        assert!(request.verify("POST", "/challenge", b"", NOW + MAX_CLOCK_SKEW_SECS).is_ok());
+        assert_eq!(
+            request.verify("POST", "/challenge", b"", NOW + MAX_CLOCK_SKEW_SECS + 1),
+            Err(AuthError::Expired {
+                timestamp: NOW,
+                now: NOW + MAX_CLOCK_SKEW_SECS + 1
+            })
+        );
+    }
+
+    #[test]
+    fn replayed_requests_are_rejected_until_they_expire() {
+        let keypair = Keypair::new();
+        let request = SignedRequest::sign(&keypair, "POST", "/challenge", NOW, b"challenge");
+        let other = SignedRequest::sign(&keypair, "POST", "/challenge", NOW, b"other challenge");
+        let mut cache = ReplayCache::default();
+
+        assert_eq!(cache.check(&request, NOW), Ok(()));
+        assert_eq!(cache.check(&other, NOW + 1), Ok(()));
+        assert_eq!(cache.check(&request, NOW + MAX_CLOCK_SKEW_SECS), Err(AuthError::Replayed));
+
+        // Past the window the request is forgotten, and `verify` rejects it as expired
+        assert_eq!(cache.check(&request, NOW + MAX_CLOCK_SKEW_SECS + 1), Ok(()));
+        assert_eq!(cache.seen.len(), 1);
+    }
+
+    #[test]
+    fn missing_or_malformed_headers_are_rejected() {
+        assert_eq!(
+            SignedRequest::from_headers(None, Some("1"), Some("1")),
+            Err(AuthError::MissingHeader(SIGNER_HEADER))
+        );
+        assert_eq!(
+            SignedRequest::from_headers(Some("not a key"), Some("1"), Some("1")),
+            Err(AuthError::InvalidHeader(SIGNER_HEADER))
+        );
+        let signer = Keypair::new().pubkey().to_string();
+        assert_eq!(
+            SignedRequest::from_headers(Some(&signer), Some("soon"), Some("1")),
+            Err(AuthError::InvalidHeader(TIMESTAMP_HEADER))
+        );
+    }
+}
@@ -0,0 +1,541 @@
+//! Storage server: stores files with their tags and answers challenges.
+//!
+//! Files are uploaded over HTTP (hex encoded file name in the path):
+//!
+//! - `PUT /files/<name>/params`: `WirePublicParams` of the key the file was tagged with,
+//! - `PUT /files/<name>/blocks`: the raw blocks, a whole number of blocks,
+//! - `PUT /files/<name>/tags`: `WireTags`, possibly in several chunks.
+//!
+//! `POST /challenge` takes a `WireChallenge` and answers with a `WireProof`, which is also
+//! submitted on-chain with `submit_aggregated_proof`. The program can't issue BLS challenges
+//! itself, but it does issue Merkle challenges: the linked subscriptions are polled and pending
+//! ones answered with `submit_merkle_proof`.
+//!
+//! Every request is signed (see `client::auth`) and can't be replayed. Only files linked to a
+//! subscription in the configuration are served: uploads must be signed by the client of the
+//! subscription, and challenges by its client or auditor. Every submission is paid by the
+//! server, so a proof is only submitted on-chain for the subscriptions of the signer that are
+//! waiting for one (the server's turn), and at most once per paid period.
+//!
+//! The Merkle tree of a file is built once per committed file version, by streaming its blocks,
+//! and answers read only the challenged leaves from disk.
+//!
+//! Usage: `storage_server [config.json]`, with the cluster flags and environment variables of the
+//! API (see `client::config`) overriding the cluster settings of the file.
+
+use anchor_client::solana_client::nonblocking::rpc_client::RpcClient;
+use anchor_client::solana_sdk::instruction::Instruction;
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::solana_sdk::signature::{read_keypair_file, Keypair, Signature};
+use anchor_client::solana_sdk::signer::Signer;
+use client::auth::{unix_time, ReplayCache, SignedRequest, SIGNATURE_HEADER, SIGNER_HEADER, TIMESTAMP_HEADER};
+use client::config::{ClusterConfig, ClusterSettings, CommandLine, ConfigLayer};
+use client::merkle::{prove_challenge_with, MerkleTree};
+use client::proof::{prove, Challenge, Proof};
+use client::storage::{FileStore, StorageError};
+use client::transactions::{
+    fetch_program_context, send_instruction, submit_aggregated_proof_instruction, submit_merkle_proof_instruction,
+    transaction_slot, TransactionError,
+};
+use client::wire::{WireChallenge, WireMessage, WireProof, WirePublicParams, WireTags};
+use serde::Deserialize;
+use solana_smart_contract::{ProgramContext, ProofKind};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use warp::filters::path::FullPath;
+use warp::http::{Method, StatusCode};
+use warp::hyper::body::Bytes;
+use warp::reply::Response;
+use warp::{Filter, Reply};
+
+#[derive(Deserialize, Debug, Clone)]
+struct ServerConfig {
+    bind: SocketAddr,
+    data_dir: String,
+    #[serde(flatten)]
+    cluster: ClusterSettings,
+    /// Solana keypair file of the server, which signs the proof submissions.
+    keypair_path: String,
+    poll_interval_secs: u64,
+    max_upload_bytes: u64,
+    #[serde(default)]
+    subscriptions: Vec<ServedSubscription>,
+}
+
+/// Links a stored file to the program context of its subscription.
+#[derive(Deserialize, Debug, Clone)]
+struct ServedSubscription {
+    file_name: String,
+    program_context: String,
+    /// Client of the subscription, allowed to upload the file and to challenge it.
+    client: String,
+    /// Auditor of the subscription, allowed to challenge the file.
+    #[serde(default)]
+    auditor: Option<String>,
+}
+
+/// A `ServedSubscription` with its keys parsed.
+#[derive(Debug, Clone)]
+struct LinkedSubscription {
+    program_context: Pubkey,
+    client: Pubkey,
+    auditor: Option<Pubkey>,
+}
+
+impl LinkedSubscription {
+    fn parse(subscription: &ServedSubscription) -> Result<Self, String> {
+        let parse_key = |name: &str, value: &str| {
+            Pubkey::from_str(value).map_err(|err| format!("Invalid {} {} of {}: {}", name, value, subscription.file_name, err))
+        };
+        Ok(LinkedSubscription {
+            program_context: parse_key("program context", &subscription.program_context)?,
+            client: parse_key("client", &subscription.client)?,
+            auditor: subscription
+                .auditor
+                .as_deref()
+                .map(|auditor| parse_key("auditor", auditor))
+                .transpose()?,
+        })
+    }
+
+    fn can_challenge(&self, signer: &Pubkey) -> bool {
+        self.client == *signer || self.auditor.as_ref() == Some(signer)
+    }
+}
+
+#[derive(Clone)]
+struct ServerState {
+    store: FileStore,
+    rpc: Arc<RpcClient>,
+    program_id: Pubkey,
+    keypair: Arc<Keypair>,
+    /// Subscriptions of every file name, as raw bytes.
+    subscriptions: Arc<HashMap<Vec<u8>, Vec<LinkedSubscription>>>,
+    replays: Arc<Mutex<ReplayCache>>,
+    /// Period (`subscription_duration`) of every subscription last proven on-chain.
+    proven_periods: Arc<Mutex<HashMap<Pubkey, u64>>>,
+    merkle_trees: Arc<Mutex<HashMap<Vec<u8>, CachedTree>>>,
+}
+
+/// Merkle tree of a stored file, with the committed file version it was built for.
+struct CachedTree {
+    file_version: u64,
+    tree: Arc<MerkleTree>,
+}
+
+impl ServerState {
+    fn linked_subscriptions(&self, file_name: &[u8]) -> &[LinkedSubscription] {
+        self.subscriptions.get(file_name).map(Vec::as_slice).unwrap_or_default()
+    }
+}
+
+struct ServerError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ServerError {
+    fn bad_request(message: String) -> Self {
+        ServerError {
+            status: StatusCode::BAD_REQUEST,
+            message,
+        }
+    }
+
+    fn forbidden(message: String) -> Self {
+        ServerError {
+            status: StatusCode::FORBIDDEN,
+            message,
+        }
+    }
+}
+
+impl From<StorageError> for ServerError {
+    fn from(err: StorageError) -> Self {
+        let status = match err {
+            StorageError::UnknownFile(_) => StatusCode::NOT_FOUND,
+            StorageError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        ServerError {
+            status,
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<tokio::task::JoinError> for ServerError {
+    fn from(err: tokio::task::JoinError) -> Self {
+        ServerError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: err.to_string(),
+        }
+    }
+}
+
+impl Reply for ServerError {
+    fn into_response(self) -> Response {
+        warp::reply::with_status(self.message, self.status).into_response()
+    }
+}
+
+fn to_reply(result: Result<Response, ServerError>) -> Result<Response, warp::Rejection> {
+    Ok(result.unwrap_or_else(|err| err.into_response()))
+}
+
+fn decode_file_name(hex_name: &str) -> Result<Vec<u8>, ServerError> {
+    hex::decode(hex_name).map_err(|err| ServerError::bad_request(format!("Invalid file name: {}", err)))
+}
+
+/// What a request signature covers, read from the request before its body.
+struct RequestAuth {
+    method: Method,
+    path: FullPath,
+    signer: Option<String>,
+    timestamp: Option<String>,
+    signature: Option<String>,
+}
+
+impl RequestAuth {
+    // Checks the signature of the request with `body` and that it wasn't received before, and returns its signer
+    fn signer(&self, body: &[u8], replays: &Mutex<ReplayCache>) -> Result<Pubkey, ServerError> {
+        let now = unix_time();
+        SignedRequest::from_headers(self.signer.as_deref(), self.timestamp.as_deref(), self.signature.as_deref())
+            .and_then(|request| {
+                let signer = request.verify(self.method.as_str(), self.path.as_str(), body, now)?;
+                replays.lock().unwrap().check(&request, now)?;
+                Ok(signer)
+            })
+            .map_err(|err| ServerError {
+                status: StatusCode::UNAUTHORIZED,
+                message: err.to_string(),
+            })
+    }
+}
+
+fn request_auth() -> impl Filter<Extract = (RequestAuth,), Error = warp::Rejection> + Clone {
+    warp::method()
+        .and(warp::path::full())
+        .and(warp::header::optional::<String>(SIGNER_HEADER))
+        .and(warp::header::optional::<String>(TIMESTAMP_HEADER))
+        .and(warp::header::optional::<String>(SIGNATURE_HEADER))
+        .map(|method, path, signer, timestamp, signature| RequestAuth {
+            method,
+            path,
+            signer,
+            timestamp,
+            signature,
+        })
+}
+
+// Decodes the file name of an upload, and checks that the client of one of its subscriptions signed it
+fn authorize_upload(state: &ServerState, hex_name: &str, auth: &RequestAuth, body: &[u8]) -> Result<Vec<u8>, ServerError> {
+    let file_name = decode_file_name(hex_name)?;
+    let signer = auth.signer(body, &state.replays)?;
+    if !state.linked_subscriptions(&file_name).iter().any(|subscription| subscription.client == signer) {
+        return Err(ServerError::forbidden(format!("{} is not the client of a subscription of this file", signer)));
+    }
+    Ok(file_name)
+}
+
+async fn put_params_handler(hex_name: String, auth: RequestAuth, body: Bytes, state: ServerState) -> Result<Response, warp::Rejection> {
+    let result = async {
+        let file_name = authorize_upload(&state, &hex_name, &auth, &body)?;
+        let params = WirePublicParams::from_wire(&body).map_err(|err| ServerError::bad_request(err.to_string()))?;
+
+        state.store.put_params(&file_name, &params)?;
+        Ok(StatusCode::NO_CONTENT.into_response())
+    };
+    to_reply(result.await)
+}
+
+async fn put_blocks_handler(hex_name: String, auth: RequestAuth, body: Bytes, state: ServerState) -> Result<Response, warp::Rejection> {
+    let result = async {
+        let file_name = authorize_upload(&state, &hex_name, &auth, &body)?;
+
+        let store = state.store.clone();
+        let stored_name = file_name.clone();
+        tokio::task::spawn_blocking(move || store.put_blocks(&stored_name, &body)).await??;
+        // The tree of the previous blocks may still be cached for the same file version
+        state.merkle_trees.lock().unwrap().remove(&file_name);
+        Ok(StatusCode::NO_CONTENT.into_response())
+    };
+    to_reply(result.await)
+}
+
+async fn put_tags_handler(hex_name: String, auth: RequestAuth, body: Bytes, state: ServerState) -> Result<Response, warp::Rejection> {
+    let result = async {
+        let file_name = authorize_upload(&state, &hex_name, &auth, &body)?;
+        let tags = WireTags::from_wire(&body).map_err(|err| ServerError::bad_request(err.to_string()))?;
+        if tags.file_name != file_name {
+            return Err(ServerError::bad_request("The tags belong to another file".to_string()));
+        }
+
+        state.store.put_tags(&tags)?;
+        Ok(StatusCode::NO_CONTENT.into_response())
+    };
+    to_reply(result.await)
+}
+
+async fn challenge_handler(auth: RequestAuth, body: Bytes, state: ServerState) -> Result<Response, warp::Rejection> {
+    to_reply(answer_challenge(auth, body, state).await)
+}
+
+async fn answer_challenge(auth: RequestAuth, body: Bytes, state: ServerState) -> Result<Response, ServerError> {
+    let signer = auth.signer(&body, &state.replays)?;
+    let challenge = WireChallenge::from_wire(&body)
+        .and_then(|challenge| Challenge::try_from(&challenge))
+        .map_err(|err| ServerError::bad_request(err.to_string()))?;
+
+    // Only the subscriptions of the signer are proven on-chain, at the server's expense
+    let program_contexts: Vec<Pubkey> = state
+        .linked_subscriptions(&challenge.file_name)
+        .iter()
+        .filter(|subscription| subscription.can_challenge(&signer))
+        .map(|subscription| subscription.program_context)
+        .collect();
+    if program_contexts.is_empty() {
+        return Err(ServerError::forbidden(format!("{} is not the client or auditor of a subscription of this file", signer)));
+    }
+
+    // Proving reads the challenged blocks from disk and does one scalar multiplication per block
+    let store = state.store.clone();
+    let proving_challenge = challenge.clone();
+    let proof = tokio::task::spawn_blocking(move || -> Result<Proof, ServerError> {
+        let public = store.public_key(&proving_challenge.file_name)?;
+        let file = store.file(&proving_challenge.file_name)?;
+        prove(&public, &proving_challenge, &file).map_err(|err| ServerError {
+            status: StatusCode::NOT_FOUND,
+            message: err.to_string(),
+        })
+    })
+    .await??;
+
+    println!(
+        "Answered a challenge of {} on {} blocks of {}",
+        signer,
+        challenge.items.len(),
+        String::from_utf8_lossy(&challenge.file_name)
+    );
+
+    let due = reserve_due_periods(&state, &program_contexts).await;
+    if due.is_empty() {
+        println!("No subscription of {} is waiting for a proof, nothing submitted on-chain", signer);
+    } else {
+        let due_program_contexts: Vec<Pubkey> = due.iter().map(|(program_context, _)| *program_context).collect();
+        let instruction = submit_aggregated_proof_instruction(&state.program_id, &state.keypair.pubkey(), &due_program_contexts, &proof);
+        tokio::spawn(async move {
+            if let Err(err) = send_server_instruction(&state, "Submit Aggregated Proof", instruction).await {
+                eprintln!("Failed to submit the proof on-chain: {}", err);
+                // Let a later challenge prove these periods
+                let mut proven_periods = state.proven_periods.lock().unwrap();
+                for (program_context, period) in &due {
+                    if proven_periods.get(program_context) == Some(period) {
+                        proven_periods.remove(program_context);
+                    }
+                }
+            }
+        });
+    }
+
+    Ok(warp::reply::with_header(WireProof::from(&proof).to_wire(), "content-type", "application/octet-stream").into_response())
+}
+
+// Keeps the subscriptions waiting for a proof of their current period that no earlier challenge
+// proved yet, and records them as proven, with that period
+async fn reserve_due_periods(state: &ServerState, program_contexts: &[Pubkey]) -> Vec<(Pubkey, u64)> {
+    let mut due = Vec::new();
+    for program_context_pubkey in program_contexts {
+        let program_context = match fetch_program_context(&state.rpc, &state.program_id, program_context_pubkey).await {
+            Ok(program_context) => program_context,
+            Err(err) => {
+                eprintln!("Failed to fetch the program context {}: {}", program_context_pubkey, err);
+                continue;
+            }
+        };
+        if !is_waiting_for_proof(state, &program_context) {
+            continue;
+        }
+
+        let mut proven_periods = state.proven_periods.lock().unwrap();
+        if proven_periods.get(program_context_pubkey) != Some(&program_context.subscription_duration) {
+            proven_periods.insert(*program_context_pubkey, program_context.subscription_duration);
+            due.push((*program_context_pubkey, program_context.subscription_duration));
+        }
+    }
+    due
+}
+
+// Whether the program would accept a BLS proof of this server for the subscription
+fn is_waiting_for_proof(state: &ServerState, program_context: &ProgramContext) -> bool {
+    program_context.proof_kind == ProofKind::Bls
+        && program_context.server == state.keypair.pubkey()
+        && program_context.is_server_turn
+        && !program_context.is_subscription_ended
+}
+
+// Signs and sends one instruction of the server, and waits for its confirmation
+async fn send_server_instruction(state: &ServerState, name: &str, instruction: Instruction) -> Result<Signature, TransactionError> {
+    let signature = send_instruction(&state.rpc, &state.keypair, instruction).await?;
+    let slot = transaction_slot(&state.rpc, &signature).await?;
+
+    println!("   {} Instruction Confirmed: {} (slot {})", name, signature, slot);
+    Ok(signature)
+}
+
+// Answers the pending Merkle challenges of the linked subscriptions, forever
+async fn poll_merkle_challenges(state: ServerState, poll_interval: Duration) {
+    let mut interval = tokio::time::interval(poll_interval);
+    loop {
+        interval.tick().await;
+
+        for (file_name, subscriptions) in state.subscriptions.iter() {
+            for subscription in subscriptions {
+                if let Err(err) = answer_merkle_challenge(&state, file_name, &subscription.program_context).await {
+                    eprintln!("Merkle challenge of {} failed: {}", subscription.program_context, err);
+                }
+            }
+        }
+    }
+}
+
+async fn answer_merkle_challenge(state: &ServerState, file_name: &[u8], program_context_pubkey: &Pubkey) -> Result<(), String> {
+    let program_context = fetch_program_context(&state.rpc, &state.program_id, program_context_pubkey)
+        .await
+        .map_err(|err| err.to_string())?;
+    if program_context.proof_kind != ProofKind::Merkle || !program_context.is_challenge_pending {
+        return Ok(());
+    }
+
+    let file = state.store.file(file_name).map_err(|err| err.to_string())?;
+    let tree = merkle_tree(state, file_name, program_context.file_version).await?;
+    if tree.root() != program_context.file_state_root {
+        return Err("the stored blocks don't match the committed root".to_string());
+    }
+
+    let (seed, size) = (program_context.challenge_seed, program_context.challenge_size);
+    let proofs = tokio::task::spawn_blocking(move || prove_challenge_with(&tree, &seed, size, |index| file.merkle_leaf(index).ok()))
+        .await
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| "a challenged leaf is missing".to_string())?;
+    let instruction = submit_merkle_proof_instruction(&state.program_id, &state.keypair.pubkey(), program_context_pubkey, proofs);
+    println!("Answering the Merkle challenge of {}", program_context_pubkey);
+    send_server_instruction(state, "Submit Merkle Proof", instruction)
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(())
+}
+
+// The Merkle tree of the stored file at `file_version`, built from the blocks on disk unless cached
+async fn merkle_tree(state: &ServerState, file_name: &[u8], file_version: u64) -> Result<Arc<MerkleTree>, String> {
+    if let Some(cached) = state.merkle_trees.lock().unwrap().get(file_name) {
+        if cached.file_version == file_version {
+            return Ok(Arc::clone(&cached.tree));
+        }
+    }
+
+    let file = state.store.file(file_name).map_err(|err| err.to_string())?;
+    let tree = tokio::task::spawn_blocking(move || file.merkle_tree())
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?;
+    let tree = Arc::new(tree);
+    let cached = CachedTree {
+        file_version,
+        tree: Arc::clone(&tree),
+    };
+    state.merkle_trees.lock().unwrap().insert(file_name.to_vec(), cached);
+    Ok(tree)
+}
+
+// Reports an invalid configuration and exits
+fn exit_with_error(message: String) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(2);
+}
+
+// Builds the server state described by the JSON configuration at `config_path`, the environment and the command line
+fn load_state(config_path: &str, command_line: &CommandLine) -> Result<(ServerConfig, ServerState), String> {
+    let config = std::fs::read(config_path).map_err(|err| format!("Failed to read {}: {}", config_path, err))?;
+    let config: ServerConfig = serde_json::from_slice(&config).map_err(|err| format!("Invalid {}: {}", config_path, err))?;
+    let cluster = ConfigLayer::from(config.cluster.clone())
+        .merge_env_and_flags(command_line)
+        .and_then(|layer| ClusterConfig::from_layer(&layer))
+        .map_err(|err| err.to_string())?;
+
+    let mut subscriptions: HashMap<Vec<u8>, Vec<LinkedSubscription>> = HashMap::new();
+    for subscription in &config.subscriptions {
+        subscriptions
+            .entry(subscription.file_name.as_bytes().to_vec())
+            .or_default()
+            .push(LinkedSubscription::parse(subscription)?);
+    }
+
+    let state = ServerState {
+        store: FileStore::open(Path::new(&config.data_dir))
+            .map_err(|err| format!("Failed to open the data directory {}: {}", config.data_dir, err))?,
+        rpc: Arc::new(RpcClient::new_with_commitment(cluster.rpc_url, cluster.commitment)),
+        program_id: cluster.program_id,
+        keypair: Arc::new(
+            read_keypair_file(&config.keypair_path)
+                .map_err(|err| format!("Failed to read the server keypair {}: {}", config.keypair_path, err))?,
+        ),
+        subscriptions: Arc::new(subscriptions),
+        replays: Arc::new(Mutex::new(ReplayCache::default())),
+        proven_periods: Arc::new(Mutex::new(HashMap::new())),
+        merkle_trees: Arc::new(Mutex::new(HashMap::new())),
+    };
+    Ok((config, state))
+}
+
+#[tokio::main]
+async fn main() {
+    let command_line = CommandLine::parse(std::env::args().skip(1)).unwrap_or_else(|err| exit_with_error(err.to_string()));
+    let config_path = command_line.positional.first().map(String::as_str).unwrap_or("storage_server.json");
+    let (config, state) = load_state(config_path, &command_line).unwrap_or_else(|err| exit_with_error(err));
+    println!("Storage server {} serving {}", state.keypair.pubkey(), config.data_dir);
+
+    let with_state = {
+        let state = state.clone();
+        warp::any().map(move || state.clone())
+    };
+    let upload_body = warp::body::content_length_limit(config.max_upload_bytes).and(warp::body::bytes());
+
+    let put_params = warp::path!("files" / String / "params")
+        .and(warp::put())
+        .and(request_auth())
+        .and(upload_body)
+        .and(with_state.clone())
+        .and_then(put_params_handler);
+
+    let put_blocks = warp::path!("files" / String / "blocks")
+        .and(warp::put())
+        .and(request_auth())
+        .and(upload_body)
+        .and(with_state.clone())
+        .and_then(put_blocks_handler);
+
+    let put_tags = warp::path!("files" / String / "tags")
+        .and(warp::put())
+        .and(request_auth())
+        .and(upload_body)
+        .and(with_state.clone())
+        .and_then(put_tags_handler);
+
+    let challenge = warp::path("challenge")
+        .and(warp::post())
+        .and(request_auth())
+        .and(warp::body::content_length_limit(1024 * 1024))
+        .and(warp::body::bytes())
+        .and(with_state)
+        .and_then(challenge_handler);
+
+    let routes = put_params.or(put_blocks).or(put_tags).or(challenge);
+
+    tokio::spawn(poll_merkle_challenges(state, Duration::from_secs(config.poll_interval_secs)));
+    warp::serve(routes).run(config.bind).await;
+}
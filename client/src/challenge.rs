@@ -0,0 +1,284 @@
+//! Challenge generation for the Compact PoR scheme.
+//!
+//! A challenge samples `ℓ` distinct blocks. If a fraction `ε` of the `n` blocks is corrupted,
+//! it misses all of them with probability `∏_{k<ℓ} (n - c - k) / (n - k)` where `c = ⌈ε·n⌉`,
+//! which is at most `(1 - ε)^ℓ`. `ChallengePolicy` either picks the smallest `ℓ` that brings
+//! this below `δ`, or uses a fixed `ℓ` and reports the confidence it reaches.
+//!
+//! Indices and coefficients are drawn from ChaCha20 seeded by the caller, so a challenge can
+//! be replayed from its seed; the seed must stay secret until the proof is due.
+
+use crate::dynamic::DynamicFile;
+use crate::por::random_scalar;
+use crate::proof::{Challenge, ChallengeItem};
+use rand::rngs::OsRng;
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::collections::HashSet;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ChallengeError {
+    /// `ε`, `δ` or `ℓ` is out of range.
+    InvalidParameters(String),
+    /// There are no blocks to challenge.
+    EmptyFile,
+}
+
+impl fmt::Display for ChallengeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChallengeError::InvalidParameters(reason) => write!(f, "Invalid challenge parameters: {}", reason),
+            ChallengeError::EmptyFile => write!(f, "The file has no block to challenge"),
+        }
+    }
+}
+
+impl std::error::Error for ChallengeError {}
+
+/// How many blocks a challenge samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChallengePolicy {
+    /// Detect the corruption of a fraction `epsilon` of the blocks with probability at least
+    /// `1 - delta`.
+    Detection { epsilon: f64, delta: f64 },
+    /// Always sample `count` blocks; the confidence is reported against `epsilon`.
+    Fixed { count: usize, epsilon: f64 },
+}
+
+impl ChallengePolicy {
+    fn epsilon(&self) -> f64 {
+        match self {
+            ChallengePolicy::Detection { epsilon, .. } | ChallengePolicy::Fixed { epsilon, .. } => *epsilon,
+        }
+    }
+
+    fn validate(&self) -> Result<(), ChallengeError> {
+        let epsilon = self.epsilon();
+        if !(epsilon > 0.0 && epsilon <= 1.0) {
+            return Err(ChallengeError::InvalidParameters(format!("epsilon must be in (0, 1], got {}", epsilon)));
+        }
+
+        match self {
+            ChallengePolicy::Detection { delta, .. } if !(*delta > 0.0 && *delta < 1.0) => Err(
+                ChallengeError::InvalidParameters(format!("delta must be in (0, 1), got {}", delta)),
+            ),
+            ChallengePolicy::Fixed { count: 0, .. } => Err(ChallengeError::InvalidParameters(
+                "the sample size must be at least 1".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Number of blocks `ℓ` to sample from a file of `block_count` blocks. Never more than
+    /// `block_count`, where detection is certain.
+    pub fn sample_size(&self, block_count: u64) -> Result<usize, ChallengeError> {
+        self.validate()?;
+        if block_count == 0 {
+            return Err(ChallengeError::EmptyFile);
+        }
+        let max_size = usize::try_from(block_count).unwrap_or(usize::MAX);
+
+        match *self {
+            ChallengePolicy::Fixed { count, .. } => Ok(count.min(max_size)),
+            ChallengePolicy::Detection { epsilon, delta } => {
+                let corrupted = corrupted_blocks(block_count, epsilon);
+                let mut miss_probability = 1.0;
+                for k in 0..max_size {
+                    let remaining = (block_count - k as u64) as f64;
+                    miss_probability *= (remaining - corrupted as f64).max(0.0) / remaining;
+                    if miss_probability <= delta {
+                        return Ok(k + 1);
+                    }
+                }
+                Ok(max_size)
+            }
+        }
+    }
+}
+
+/// A challenge together with the confidence it gives.
+#[derive(Debug, Clone)]
+pub struct SampledChallenge {
+    pub challenge: Challenge,
+    /// Probability of detecting the corruption of a fraction `ε` of the blocks.
+    pub confidence: f64,
+}
+
+/// Probability that `sample_size` distinct blocks out of `block_count` hit at least one of the
+/// `⌈epsilon · block_count⌉` corrupted blocks.
+pub fn detection_probability(block_count: u64, epsilon: f64, sample_size: usize) -> f64 {
+    if block_count == 0 {
+        return 0.0;
+    }
+
+    let corrupted = corrupted_blocks(block_count, epsilon);
+    let mut miss_probability = 1.0;
+    for k in 0..(sample_size as u64).min(block_count) {
+        let remaining = (block_count - k) as f64;
+        miss_probability *= (remaining - corrupted as f64).max(0.0) / remaining;
+    }
+    1.0 - miss_probability
+}
+
+/// Draws a fresh seed from the operating system.
+pub fn random_seed() -> [u8; 32] {
+    let mut seed = [0u8; 32];
+    OsRng.fill_bytes(&mut seed);
+    seed
+}
+
+/// Challenges a statically tagged file of `block_count` blocks (all at version 0).
+pub fn sample_challenge(
+    seed: &[u8; 32],
+    file_name: &[u8],
+    block_count: u64,
+    policy: &ChallengePolicy,
+) -> Result<SampledChallenge, ChallengeError> {
+    sample(seed, file_name, block_count, policy, |position| (position, 0))
+}
+
+/// Challenges the live blocks of a dynamic file, at their current versions.
+pub fn sample_dynamic_challenge(
+    seed: &[u8; 32],
+    file: &DynamicFile,
+    policy: &ChallengePolicy,
+) -> Result<SampledChallenge, ChallengeError> {
+    let live_indices: Vec<u64> = file.live_indices().collect();
+
+    sample(seed, file.file_name(), live_indices.len() as u64, policy, |position| {
+        let index = live_indices[position as usize];
+        let state = file.block(index).expect("Live indices are in range");
+        (index, state.version)
+    })
+}
+
+fn sample<F: Fn(u64) -> (u64, u64)>(
+    seed: &[u8; 32],
+    file_name: &[u8],
+    block_count: u64,
+    policy: &ChallengePolicy,
+    block_at: F,
+) -> Result<SampledChallenge, ChallengeError> {
+    let sample_size = policy.sample_size(block_count)?;
+    let mut rng = ChaCha20Rng::from_seed(*seed);
+
+    let items = distinct_positions(&mut rng, block_count, sample_size)
+        .into_iter()
+        .map(|position| {
+            let (index, version) = block_at(position);
+            ChallengeItem {
+                index,
+                version,
+                coefficient: random_scalar(&mut rng),
+            }
+        })
+        .collect();
+
+    Ok(SampledChallenge {
+        challenge: Challenge {
+            file_name: file_name.to_vec(),
+            items,
+        },
+        confidence: detection_probability(block_count, policy.epsilon(), sample_size),
+    })
+}
+
+/// Floyd's algorithm: `count` distinct values of `0..n` in `count` draws, returned sorted so
+/// the server reads its blocks in order.
+fn distinct_positions<R: RngCore>(rng: &mut R, n: u64, count: usize) -> Vec<u64> {
+    let mut positions = HashSet::with_capacity(count);
+    for j in (n - count as u64)..n {
+        let t = rng.gen_range(0..=j);
+        if !positions.insert(t) {
+            positions.insert(j);
+        }
+    }
+
+    let mut positions: Vec<u64> = positions.into_iter().collect();
+    positions.sort_unstable();
+    positions
+}
+
+fn corrupted_blocks(block_count: u64, epsilon: f64) -> u64 {
+    ((epsilon * block_count as f64).ceil() as u64).clamp(1, block_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::por::{PorKeyPair, PorParams};
+
+    #[test]
+    fn picks_the_smallest_sample_reaching_the_confidence() {
+        // Half of 10 blocks corrupted: missing them takes 5/10 · 4/9 · 3/8 · 2/7 ≈ 0.024 after 4 draws
+        let policy = ChallengePolicy::Detection { epsilon: 0.5, delta: 0.05 };
+        assert_eq!(policy.sample_size(10).unwrap(), 4);
+
+        for (block_count, epsilon, delta) in [(1_000_000, 0.01, 0.01), (1000, 0.01, 0.01), (50_000, 0.05, 0.001)] {
+            let size = ChallengePolicy::Detection { epsilon, delta }.sample_size(block_count).unwrap();
+            assert!(detection_probability(block_count, epsilon, size) >= 1.0 - delta);
+            assert!(detection_probability(block_count, epsilon, size - 1) < 1.0 - delta);
+            // Never more than sampling with replacement would need
+            assert!(size as f64 <= (delta.ln() / (1.0 - epsilon).ln()).ceil());
+        }
+    }
+
+    #[test]
+    fn never_samples_more_blocks_than_the_file_has() {
+        let detection = ChallengePolicy::Detection { epsilon: 0.01, delta: 0.000_001 };
+        assert_eq!(detection.sample_size(20).unwrap(), 20);
+        assert_eq!(detection_probability(20, 0.01, 20), 1.0);
+
+        let fixed = ChallengePolicy::Fixed { count: 460, epsilon: 0.01 };
+        assert_eq!(fixed.sample_size(100).unwrap(), 100);
+        assert_eq!(fixed.sample_size(1_000_000).unwrap(), 460);
+    }
+
+    #[test]
+    fn rejects_invalid_policies_and_empty_files() {
+        let invalid = [
+            ChallengePolicy::Detection { epsilon: 0.0, delta: 0.01 },
+            ChallengePolicy::Detection { epsilon: 0.01, delta: 1.0 },
+            ChallengePolicy::Detection { epsilon: f64::NAN, delta: 0.01 },
+            ChallengePolicy::Fixed { count: 0, epsilon: 0.01 },
+        ];
+        for policy in invalid {
+            assert!(matches!(policy.sample_size(100), Err(ChallengeError::InvalidParameters(_))), "{:?}", policy);
+        }
+
+        let policy = ChallengePolicy::Fixed { count: 1, epsilon: 0.01 };
+        assert!(matches!(policy.sample_size(0), Err(ChallengeError::EmptyFile)));
+    }
+
+    #[test]
+    fn challenges_replay_from_their_seed() {
+        let policy = ChallengePolicy::Fixed { count: 8, epsilon: 0.1 };
+        let first = sample_challenge(&[1; 32], b"file", 100, &policy).unwrap();
+        let again = sample_challenge(&[1; 32], b"file", 100, &policy).unwrap();
+        let other = sample_challenge(&[2; 32], b"file", 100, &policy).unwrap();
+
+        assert_eq!(first.challenge, again.challenge);
+        assert_ne!(first.challenge, other.challenge);
+
+        let indices: Vec<u64> = first.challenge.items.iter().map(|item| item.index).collect();
+        assert_eq!(indices.len(), 8);
+        assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(indices.iter().all(|&index| index < 100));
+        assert_eq!(first.confidence, detection_probability(100, 0.1, 8));
+    }
+
+    #[test]
+    fn dynamic_challenges_sample_live_blocks_at_their_version() {
+        let keys = PorKeyPair::generate(PorParams::new(1), &mut ChaCha20Rng::seed_from_u64(1));
+        let mut file = DynamicFile::new(b"file", 31, 4);
+        file.modify(&keys, 1, b"changed").unwrap();
+        file.delete(2).unwrap();
+
+        let policy = ChallengePolicy::Fixed { count: 4, epsilon: 0.5 };
+        let sampled = sample_dynamic_challenge(&[3; 32], &file, &policy).unwrap();
+        let blocks: Vec<(u64, u64)> = sampled.challenge.items.iter().map(|item| (item.index, item.version)).collect();
+
+        assert_eq!(blocks, [(0, 0), (1, 1), (3, 0)]);
+    }
+}
@@ -0,0 +1,538 @@
+//! Configuration of the HTTP API, and the cluster and PoR key settings it shares with the
+//! auditor and the storage server.
+//!
+//! Settings are read from three layers, each overriding the previous one:
+//!
+//! 1. a TOML file, `client.toml` by default, or the file given by `--config` or `CONFIG_FILE`,
+//! 2. environment variables (`CLUSTER`, `RPC_URL`, `WS_URL`, `COMMITMENT`, `PROGRAM_ID`,
+//!    `BIND_ADDRESS`, `KEYSTORE_DIR`, `CONFIRM_TIMEOUT_SECS`, `SEND_ATTEMPTS`, `POR_KEY_FILE`,
+//!    `POR_BLOCK_SIZE`, `POR_SECTORS_PER_BLOCK`),
+//! 3. command line flags (`--cluster`, `--rpc-url`, `--ws-url`, `--commitment`, `--program-id`,
+//!    `--bind`, `--keystore`, `--confirm-timeout`, `--send-attempts`, `--por-key-file`,
+//!    `--por-block-size`, `--por-sectors-per-block`), as `--flag value` or `--flag=value`.
+//!
+//! The auditor and the storage server take the place of the TOML file with the cluster settings
+//! of their JSON configuration (`ClusterSettings`), under the same environment and flags.
+//!
+//! The password of the PoR key file is only read from `POR_KEY_PASSWORD`, never from a file or
+//! the command line.
+//!
+//! ```toml
+//! cluster = "devnet"            # localnet, devnet, testnet, mainnet, or an RPC URL
+//! rpc_url = "http://10.0.0.5:8899"
+//! ws_url = "ws://10.0.0.5:8900" # Derived from rpc_url when omitted
+//! commitment = "confirmed"      # processed, confirmed or finalized
+//! program_id = "7VSn..."
+//! bind_address = "127.0.0.1:3030" # Loopback only, the API spends from every keystore key
+//! keystore_dir = "keystore"
+//! confirm_timeout_secs = 90     # Longest wait for the confirmation of a transaction
+//! send_attempts = 3             # Sends of a transaction whose blockhash expires
+//! por_key_file = "por_key.json"
+//! por_block_size = 4096         # Or por_sectors_per_block; only used to generate a new key
+//! ```
+//!
+//! Every setting is validated once at startup by `ApiConfig::load`.
+
+use anchor_client::solana_sdk::commitment_config::CommitmentConfig;
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::Cluster;
+use crate::keys::DEFAULT_SECTORS_PER_BLOCK;
+use crate::por::PorParams;
+use crate::transactions::RetryPolicy;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+const DEFAULT_CONFIG_FILE: &str = "client.toml";
+const DEFAULT_BIND_ADDRESS: &str = "127.0.0.1:3030";
+const DEFAULT_KEYSTORE_DIR: &str = "keystore";
+const DEFAULT_POR_KEY_FILE: &str = "por_key.json";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    /// The configuration file is not valid TOML, or has unknown keys.
+    File(PathBuf, String),
+    /// A setting has an invalid value.
+    Invalid { setting: &'static str, reason: String },
+    /// A command line flag is unknown or misses its value.
+    Argument(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            ConfigError::File(path, reason) => write!(f, "{}: {}", path.display(), reason),
+            ConfigError::Invalid { setting, reason } => write!(f, "Invalid {}: {}", setting, reason),
+            ConfigError::Argument(reason) => write!(f, "Invalid argument: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Settings of one configuration layer, unset when `None`.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigLayer {
+    pub cluster: Option<String>,
+    pub rpc_url: Option<String>,
+    pub ws_url: Option<String>,
+    pub commitment: Option<String>,
+    pub program_id: Option<String>,
+    pub bind_address: Option<String>,
+    pub keystore_dir: Option<String>,
+    pub confirm_timeout_secs: Option<u64>,
+    pub send_attempts: Option<u32>,
+    pub por_key_file: Option<String>,
+    pub por_block_size: Option<usize>,
+    pub por_sectors_per_block: Option<usize>,
+}
+
+impl ConfigLayer {
+    /// Reads a TOML configuration file.
+    pub fn from_file(path: &PathBuf) -> Result<Self, ConfigError> {
+        let content = fs::read_to_string(path).map_err(|err| ConfigError::Io(path.clone(), err))?;
+        toml::from_str(&content).map_err(|err| ConfigError::File(path.clone(), err.to_string()))
+    }
+
+    /// Reads the settings set in the environment.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let var = |name: &str| std::env::var(name).ok();
+        Ok(ConfigLayer {
+            cluster: var("CLUSTER"),
+            rpc_url: var("RPC_URL"),
+            ws_url: var("WS_URL"),
+            commitment: var("COMMITMENT"),
+            program_id: var("PROGRAM_ID"),
+            bind_address: var("BIND_ADDRESS"),
+            keystore_dir: var("KEYSTORE_DIR"),
+            confirm_timeout_secs: var("CONFIRM_TIMEOUT_SECS")
+                .map(|value| parse_number("confirm_timeout_secs", &value))
+                .transpose()?,
+            send_attempts: var("SEND_ATTEMPTS").map(|value| parse_number("send_attempts", &value)).transpose()?,
+            por_key_file: var("POR_KEY_FILE"),
+            por_block_size: var("POR_BLOCK_SIZE").map(|value| parse_number("por_block_size", &value)).transpose()?,
+            por_sectors_per_block: var("POR_SECTORS_PER_BLOCK")
+                .map(|value| parse_number("por_sectors_per_block", &value))
+                .transpose()?,
+        })
+    }
+
+    /// Overrides the settings of `self` with those set in `other`.
+    pub fn merge(self, other: ConfigLayer) -> Self {
+        ConfigLayer {
+            cluster: other.cluster.or(self.cluster),
+            rpc_url: other.rpc_url.or(self.rpc_url),
+            ws_url: other.ws_url.or(self.ws_url),
+            commitment: other.commitment.or(self.commitment),
+            program_id: other.program_id.or(self.program_id),
+            bind_address: other.bind_address.or(self.bind_address),
+            keystore_dir: other.keystore_dir.or(self.keystore_dir),
+            confirm_timeout_secs: other.confirm_timeout_secs.or(self.confirm_timeout_secs),
+            send_attempts: other.send_attempts.or(self.send_attempts),
+            por_key_file: other.por_key_file.or(self.por_key_file),
+            por_block_size: other.por_block_size.or(self.por_block_size),
+            por_sectors_per_block: other.por_sectors_per_block.or(self.por_sectors_per_block),
+        }
+    }
+
+    /// Overrides the settings of `self` with the environment, then with the command line.
+    pub fn merge_env_and_flags(self, command_line: &CommandLine) -> Result<Self, ConfigError> {
+        Ok(self.merge(ConfigLayer::from_env()?).merge(command_line.layer.clone()))
+    }
+}
+
+/// Cluster settings of the JSON configuration of the auditor and the storage server.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ClusterSettings {
+    #[serde(default)]
+    pub cluster: Option<String>,
+    #[serde(default)]
+    pub rpc_url: Option<String>,
+    #[serde(default)]
+    pub ws_url: Option<String>,
+    #[serde(default)]
+    pub commitment: Option<String>,
+    /// Mediator program, the program this crate was built with when omitted.
+    #[serde(default)]
+    pub program_id: Option<String>,
+}
+
+impl From<ClusterSettings> for ConfigLayer {
+    fn from(settings: ClusterSettings) -> Self {
+        ConfigLayer {
+            cluster: settings.cluster,
+            rpc_url: settings.rpc_url,
+            ws_url: settings.ws_url,
+            commitment: settings.commitment,
+            program_id: settings.program_id,
+            ..ConfigLayer::default()
+        }
+    }
+}
+
+/// Command line of the API: the settings set by flags, the `--config` file and the positional arguments.
+#[derive(Debug, Default)]
+pub struct CommandLine {
+    pub layer: ConfigLayer,
+    pub config_file: Option<PathBuf>,
+    pub positional: Vec<String>,
+}
+
+impl CommandLine {
+    /// Parses the arguments, without the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        let mut command_line = CommandLine::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                command_line.positional.push(arg);
+                continue;
+            };
+
+            let (name, value) = match flag.split_once('=') {
+                Some((name, value)) => (name.to_string(), value.to_string()),
+                None => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| ConfigError::Argument(format!("--{} needs a value", flag)))?;
+                    (flag.to_string(), value)
+                }
+            };
+
+            let layer = &mut command_line.layer;
+            let setting = match name.as_str() {
+                "config" => {
+                    command_line.config_file = Some(PathBuf::from(value));
+                    continue;
+                }
+                "cluster" => &mut layer.cluster,
+                "rpc-url" => &mut layer.rpc_url,
+                "ws-url" => &mut layer.ws_url,
+                "commitment" => &mut layer.commitment,
+                "program-id" => &mut layer.program_id,
+                "bind" => &mut layer.bind_address,
+                "keystore" => &mut layer.keystore_dir,
+                "confirm-timeout" => {
+                    layer.confirm_timeout_secs = Some(parse_number("confirm_timeout_secs", &value)?);
+                    continue;
+                }
+                "send-attempts" => {
+                    layer.send_attempts = Some(parse_number("send_attempts", &value)?);
+                    continue;
+                }
+                "por-key-file" => &mut layer.por_key_file,
+                "por-block-size" => {
+                    layer.por_block_size = Some(parse_number("por_block_size", &value)?);
+                    continue;
+                }
+                "por-sectors-per-block" => {
+                    layer.por_sectors_per_block = Some(parse_number("por_sectors_per_block", &value)?);
+                    continue;
+                }
+                _ => return Err(ConfigError::Argument(format!("unknown flag --{}", name))),
+            };
+            *setting = Some(value);
+        }
+
+        Ok(command_line)
+    }
+}
+
+/// Validated cluster settings, shared by the API, the auditor and the storage server.
+#[derive(Debug, Clone)]
+pub struct ClusterConfig {
+    pub rpc_url: String,
+    pub ws_url: String,
+    pub commitment: CommitmentConfig,
+    /// Mediator program the instructions are sent to.
+    pub program_id: Pubkey,
+}
+
+impl ClusterConfig {
+    /// Validates the cluster settings of `layer`, defaulting to a local validator.
+    pub fn from_layer(layer: &ConfigLayer) -> Result<Self, ConfigError> {
+        let invalid = |setting: &'static str, reason: String| ConfigError::Invalid { setting, reason };
+
+        // An explicit RPC URL takes precedence over the cluster
+        let cluster = match (&layer.rpc_url, &layer.cluster) {
+            (Some(rpc_url), _) => Cluster::from_str(rpc_url).map_err(|err| invalid("rpc_url", err.to_string()))?,
+            (None, Some(cluster)) => Cluster::from_str(cluster).map_err(|err| invalid("cluster", err.to_string()))?,
+            (None, None) => Cluster::Localnet,
+        };
+
+        let ws_url = match &layer.ws_url {
+            Some(ws_url) if ws_url.starts_with("ws://") || ws_url.starts_with("wss://") => ws_url.clone(),
+            Some(ws_url) => return Err(invalid("ws_url", format!("{} is not a ws:// or wss:// URL", ws_url))),
+            None => cluster.ws_url().to_string(),
+        };
+
+        let commitment = match &layer.commitment {
+            Some(commitment) => CommitmentConfig::from_str(commitment)
+                .map_err(|_| invalid("commitment", format!("{} is not processed, confirmed or finalized", commitment)))?,
+            None => CommitmentConfig::confirmed(),
+        };
+
+        let program_id = match &layer.program_id {
+            Some(program_id) => Pubkey::from_str(program_id).map_err(|err| invalid("program_id", err.to_string()))?,
+            None => solana_smart_contract::ID,
+        };
+
+        Ok(ClusterConfig {
+            rpc_url: cluster.url().to_string(),
+            ws_url,
+            commitment,
+            program_id,
+        })
+    }
+}
+
+/// Validated settings of the PoR key file.
+#[derive(Clone)]
+pub struct PorKeyConfig {
+    pub key_file: PathBuf,
+    pub password: Zeroizing<String>,
+    /// Block layout of a newly generated key; an existing key file keeps its own.
+    pub params: PorParams,
+}
+
+impl PorKeyConfig {
+    /// Validates the PoR key settings of `layer`, with the password read from `POR_KEY_PASSWORD`.
+    pub fn from_layer(layer: &ConfigLayer, password: Option<Zeroizing<String>>) -> Result<Self, ConfigError> {
+        let invalid = |setting: &'static str, reason: String| ConfigError::Invalid { setting, reason };
+
+        let password = password.ok_or_else(|| invalid("POR_KEY_PASSWORD", "must be set to open the PoR key file".to_string()))?;
+
+        let params = match (layer.por_block_size, layer.por_sectors_per_block) {
+            (Some(_), Some(_)) => {
+                return Err(invalid("por_block_size", "can't be set with por_sectors_per_block".to_string()));
+            }
+            (Some(block_size), None) => PorParams::with_block_size(block_size),
+            (None, sectors_per_block) => PorParams::new(sectors_per_block.unwrap_or(DEFAULT_SECTORS_PER_BLOCK)),
+        };
+        if !params.is_valid() {
+            let setting = if layer.por_block_size.is_some() { "por_block_size" } else { "por_sectors_per_block" };
+            return Err(invalid(setting, format!("{:?} is not a valid block layout", params)));
+        }
+
+        Ok(PorKeyConfig {
+            key_file: PathBuf::from(layer.por_key_file.clone().unwrap_or_else(|| DEFAULT_POR_KEY_FILE.to_string())),
+            password,
+            params,
+        })
+    }
+
+    /// Reads `POR_KEY_PASSWORD`.
+    pub fn password_from_env() -> Option<Zeroizing<String>> {
+        std::env::var("POR_KEY_PASSWORD").ok().map(Zeroizing::new)
+    }
+}
+
+// Never print the password
+impl fmt::Debug for PorKeyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PorKeyConfig")
+            .field("key_file", &self.key_file)
+            .field("params", &self.params)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Validated configuration of the API.
+#[derive(Debug, Clone)]
+pub struct ApiConfig {
+    pub cluster: ClusterConfig,
+    pub bind_address: SocketAddr,
+    pub keystore_dir: PathBuf,
+    /// Confirmation and resending of the transactions sent by the API.
+    pub retry: RetryPolicy,
+    pub por: PorKeyConfig,
+}
+
+impl ApiConfig {
+    /// Loads the configuration file, then the environment, then the command line.
+    ///
+    /// The default configuration file is optional; a file named by `--config` or `CONFIG_FILE`
+    /// must exist.
+    pub fn load(command_line: &CommandLine) -> Result<Self, ConfigError> {
+        let config_file = command_line
+            .config_file
+            .clone()
+            .or_else(|| std::env::var("CONFIG_FILE").ok().map(PathBuf::from));
+
+        let file_layer = match config_file {
+            Some(path) => ConfigLayer::from_file(&path)?,
+            None => {
+                let path = PathBuf::from(DEFAULT_CONFIG_FILE);
+                if path.exists() {
+                    ConfigLayer::from_file(&path)?
+                } else {
+                    ConfigLayer::default()
+                }
+            }
+        };
+
+        let layer = file_layer.merge_env_and_flags(command_line)?;
+        ApiConfig::from_layer(layer, PorKeyConfig::password_from_env())
+    }
+
+    /// Validates the settings, filling in the defaults of those left unset.
+    pub fn from_layer(layer: ConfigLayer, por_key_password: Option<Zeroizing<String>>) -> Result<Self, ConfigError> {
+        let invalid = |setting: &'static str, reason: String| ConfigError::Invalid { setting, reason };
+        let cluster = ClusterConfig::from_layer(&layer)?;
+        let por = PorKeyConfig::from_layer(&layer, por_key_password)?;
+
+        let bind_address = layer
+            .bind_address
+            .as_deref()
+            .unwrap_or(DEFAULT_BIND_ADDRESS)
+            .parse()
+            .map_err(|err: std::net::AddrParseError| invalid("bind_address", err.to_string()))?;
+
+        let mut retry = RetryPolicy::default();
+        if let Some(confirm_timeout_secs) = layer.confirm_timeout_secs {
+            retry.confirm_timeout = Duration::from_secs(confirm_timeout_secs);
+        }
+        match layer.send_attempts {
+            Some(0) => return Err(invalid("send_attempts", "at least one attempt is needed".to_string())),
+            Some(send_attempts) => retry.max_attempts = send_attempts,
+            None => {}
+        }
+
+        Ok(ApiConfig {
+            cluster,
+            bind_address,
+            keystore_dir: PathBuf::from(layer.keystore_dir.unwrap_or_else(|| DEFAULT_KEYSTORE_DIR.to_string())),
+            retry,
+            por,
+        })
+    }
+}
+
+fn parse_number<T: FromStr>(setting: &'static str, value: &str) -> Result<T, ConfigError> {
+    value.parse().map_err(|_| ConfigError::Invalid {
+        setting,
+        reason: format!("{} is not a valid number", value),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn password() -> Option<Zeroizing<String>> {
+        Some(Zeroizing::new("hunter2".to_string()))
+    }
+
+    fn flags(args: &[&str]) -> CommandLine {
+        CommandLine::parse(args.iter().map(|arg| arg.to_string())).unwrap()
+    }
+
+    #[test]
+    fn defaults_to_a_local_validator() {
+        let config = ApiConfig::from_layer(ConfigLayer::default(), password()).unwrap();
+
+        assert_eq!(config.cluster.rpc_url, Cluster::Localnet.url());
+        assert_eq!(config.cluster.ws_url, Cluster::Localnet.ws_url());
+        assert_eq!(config.cluster.commitment, CommitmentConfig::confirmed());
+        assert_eq!(config.cluster.program_id, solana_smart_contract::ID);
+        assert_eq!(config.bind_address, DEFAULT_BIND_ADDRESS.parse().unwrap());
+        assert_eq!(config.por.key_file, PathBuf::from(DEFAULT_POR_KEY_FILE));
+        assert_eq!(config.por.params, PorParams::new(DEFAULT_SECTORS_PER_BLOCK));
+    }
+
+    #[test]
+    fn flags_override_the_environment_which_overrides_the_file() {
+        let file = ConfigLayer {
+            cluster: Some("devnet".to_string()),
+            commitment: Some("finalized".to_string()),
+            keystore_dir: Some("file_keystore".to_string()),
+            por_key_file: Some("file_key.json".to_string()),
+            ..ConfigLayer::default()
+        };
+        let env = ConfigLayer {
+            commitment: Some("processed".to_string()),
+            keystore_dir: Some("env_keystore".to_string()),
+            ..ConfigLayer::default()
+        };
+        let command_line = flags(&["--keystore", "flag_keystore", "--por-block-size=4096", "auditor"]);
+
+        let config = ApiConfig::from_layer(file.merge(env).merge(command_line.layer), password()).unwrap();
+
+        assert_eq!(config.cluster.rpc_url, Cluster::Devnet.url());
+        assert_eq!(config.cluster.commitment, CommitmentConfig::processed());
+        assert_eq!(config.keystore_dir, PathBuf::from("flag_keystore"));
+        assert_eq!(config.por.key_file, PathBuf::from("file_key.json"));
+        assert_eq!(config.por.params, PorParams::with_block_size(4096));
+        assert_eq!(command_line.positional, vec!["auditor".to_string()]);
+    }
+
+    #[test]
+    fn an_rpc_url_takes_precedence_over_the_cluster() {
+        let layer = ConfigLayer {
+            cluster: Some("mainnet".to_string()),
+            rpc_url: Some("http://10.0.0.5:8899".to_string()),
+            ..ConfigLayer::default()
+        };
+
+        let cluster = ClusterConfig::from_layer(&layer).unwrap();
+        assert_eq!(cluster.rpc_url, "http://10.0.0.5:8899");
+        assert_eq!(cluster.ws_url, "ws://10.0.0.5:8900/");
+    }
+
+    #[test]
+    fn daemon_settings_are_overridden_like_the_file() {
+        let settings: ClusterSettings = serde_json::from_str(r#"{"rpc_url": "http://10.0.0.5:8899", "program_id": null}"#).unwrap();
+        let command_line = flags(&["--ws-url", "ws://10.0.0.6:8900"]);
+
+        let cluster = ClusterConfig::from_layer(&ConfigLayer::from(settings).merge(command_line.layer)).unwrap();
+        assert_eq!(cluster.rpc_url, "http://10.0.0.5:8899");
+        assert_eq!(cluster.ws_url, "ws://10.0.0.6:8900");
+        assert_eq!(cluster.program_id, solana_smart_contract::ID);
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        let invalid_setting = |layer: ConfigLayer, password: Option<Zeroizing<String>>| match ApiConfig::from_layer(layer, password) {
+            Err(ConfigError::Invalid { setting, .. }) => setting,
+            other => panic!("expected an invalid setting, got {:?}", other),
+        };
+
+        assert_eq!(invalid_setting(ConfigLayer::default(), None), "POR_KEY_PASSWORD");
+        let ws_url = ConfigLayer {
+            ws_url: Some("http://127.0.0.1:8900".to_string()),
+            ..ConfigLayer::default()
+        };
+        assert_eq!(invalid_setting(ws_url, password()), "ws_url");
+        let both_layouts = ConfigLayer {
+            por_block_size: Some(4096),
+            por_sectors_per_block: Some(16),
+            ..ConfigLayer::default()
+        };
+        assert_eq!(invalid_setting(both_layouts, password()), "por_block_size");
+        let no_sectors = ConfigLayer {
+            por_sectors_per_block: Some(0),
+            ..ConfigLayer::default()
+        };
+        assert_eq!(invalid_setting(no_sectors, password()), "por_sectors_per_block");
+
+        assert!(matches!(CommandLine::parse(["--por-block-size=big".to_string()]), Err(ConfigError::Invalid { .. })));
+        assert!(matches!(CommandLine::parse(["--bind".to_string()]), Err(ConfigError::Argument(_))));
+        assert!(matches!(CommandLine::parse(["--unknown=1".to_string()]), Err(ConfigError::Argument(_))));
+    }
+
+    #[test]
+    fn the_por_key_password_is_never_printed() {
+        let config = ApiConfig::from_layer(ConfigLayer::default(), password()).unwrap();
+        assert!(!format!("{:?}", config).contains("hunter2"));
+    }
+}
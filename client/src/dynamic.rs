@@ -0,0 +1,326 @@
+//! Dynamic PoR: appending, modifying and deleting blocks after the file has been tagged.
+//!
+//! Every block carries a version number that is folded into its hashed index
+//! (`H(name || i || version)`), so re-tagging a block invalidates its previous tag.
+//! The versions of all blocks are committed in a Merkle tree whose root, together with the
+//! block count and a monotonically increasing file version, is stored on-chain through the
+//! `update_file_state` instruction, once per `commit` however many blocks changed. A server
+//! replaying a stale tag or block fails verification, since the verifier hashes the committed
+//! version.
+//!
+//! Deleted blocks keep their index as tombstones so the indices of the following blocks,
+//! and therefore their tags, stay valid. Challenges only sample live blocks.
+//!
+//! Erasure-coded files can't be edited: the parity blocks of a stripe depend on all of its
+//! data blocks, so changing one block alone would leave them stale. Re-tag the file instead.
+
+use crate::merkle::{self, Hash, MerkleTree};
+use crate::por::PorKeyPair;
+use bls12_381::G1Affine;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DynamicError {
+    BlockOutOfRange { index: u64, block_count: u64 },
+    BlockDeleted(u64),
+    InvalidBlockSize { expected: usize, actual: usize },
+    /// The file is erasure-coded, so its blocks can't be edited one by one.
+    ErasureCoded,
+}
+
+impl fmt::Display for DynamicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DynamicError::BlockOutOfRange { index, block_count } => write!(
+                f,
+                "Block {} is out of range, the file has {} blocks",
+                index, block_count
+            ),
+            DynamicError::BlockDeleted(index) => write!(f, "Block {} was deleted", index),
+            DynamicError::InvalidBlockSize { expected, actual } => write!(
+                f,
+                "Invalid block size: expected at most {} bytes, got {} bytes",
+                expected, actual
+            ),
+            DynamicError::ErasureCoded => write!(f, "Erasure-coded files can't be edited, re-tag the file instead"),
+        }
+    }
+}
+
+impl std::error::Error for DynamicError {}
+
+/// Version information of a single block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockState {
+    pub version: u64,
+    pub deleted: bool,
+}
+
+/// A block that was (re)tagged and must be sent to the server.
+#[derive(Debug, Clone)]
+pub struct BlockUpdate {
+    pub index: u64,
+    pub version: u64,
+    pub block: Vec<u8>,
+    pub tag: G1Affine,
+}
+
+/// Client-side bookkeeping of a mutable file.
+#[derive(Debug, Clone)]
+pub struct DynamicFile {
+    file_name: Vec<u8>,
+    block_size: usize,
+    blocks: Vec<BlockState>,
+    file_version: u64,
+    /// Some blocks changed since the last commit.
+    pending: bool,
+    erasure_coded: bool,
+}
+
+impl DynamicFile {
+    /// Tracks a file whose `block_count` blocks were tagged statically (all at version 0).
+    /// The file version starts at 0, meaning nothing was committed on-chain yet.
+    pub fn new(file_name: &[u8], block_size: usize, block_count: u64) -> Self {
+        DynamicFile {
+            file_name: file_name.to_vec(),
+            block_size,
+            blocks: vec![BlockState { version: 0, deleted: false }; block_count as usize],
+            file_version: 0,
+            pending: false,
+            erasure_coded: false,
+        }
+    }
+
+    /// Marks the file as erasure-coded when tagged, which forbids editing it.
+    pub fn erasure_coded(mut self) -> Self {
+        self.erasure_coded = true;
+        self
+    }
+
+    pub fn file_name(&self) -> &[u8] {
+        &self.file_name
+    }
+
+    pub fn block_count(&self) -> u64 {
+        self.blocks.len() as u64
+    }
+
+    /// Number of the last committed state; bumped by every commit.
+    pub fn file_version(&self) -> u64 {
+        self.file_version
+    }
+
+    /// Ends the batch of edits made since the last commit, and returns the file version to
+    /// record on-chain with `state_root()`. `None` when nothing changed.
+    pub fn commit(&mut self) -> Option<u64> {
+        if !self.pending {
+            return None;
+        }
+
+        self.pending = false;
+        self.file_version += 1;
+        Some(self.file_version)
+    }
+
+    pub fn block(&self, index: u64) -> Option<BlockState> {
+        self.blocks.get(index as usize).copied()
+    }
+
+    /// Indices of the blocks that can be challenged.
+    pub fn live_indices(&self) -> impl Iterator<Item = u64> + '_ {
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, state)| !state.deleted)
+            .map(|(index, _)| index as u64)
+    }
+
+    /// Appends a block at the end of the file.
+    pub fn append(&mut self, keys: &PorKeyPair, data: &[u8]) -> Result<BlockUpdate, DynamicError> {
+        let block = self.pad_block(data)?;
+        let index = self.block_count();
+        self.blocks.push(BlockState { version: 0, deleted: false });
+        self.pending = true;
+
+        Ok(self.tag(keys, index, 0, block))
+    }
+
+    /// Replaces the content of block `index`, bumping its version.
+    pub fn modify(&mut self, keys: &PorKeyPair, index: u64, data: &[u8]) -> Result<BlockUpdate, DynamicError> {
+        let block = self.pad_block(data)?;
+        let state = self.live_block_mut(index)?;
+        state.version += 1;
+        let version = state.version;
+        self.pending = true;
+
+        Ok(self.tag(keys, index, version, block))
+    }
+
+    /// Deletes block `index`. The index stays reserved and its version is bumped so the old tag
+    /// can no longer be used.
+    pub fn delete(&mut self, index: u64) -> Result<(), DynamicError> {
+        let state = self.live_block_mut(index)?;
+        state.version += 1;
+        state.deleted = true;
+        self.pending = true;
+
+        Ok(())
+    }
+
+    /// Root of the Merkle tree over the states of all blocks, as committed on-chain.
+    pub fn state_root(&self) -> Hash {
+        self.state_tree().root()
+    }
+
+    /// Authentication path proving the state of block `index` against `state_root()`.
+    pub fn state_proof(&self, index: u64) -> Option<Vec<Hash>> {
+        self.state_tree().proof(index as usize)
+    }
+
+    fn state_tree(&self) -> MerkleTree {
+        MerkleTree::from_leaf_hashes(
+            self.blocks
+                .iter()
+                .enumerate()
+                .map(|(index, state)| block_state_leaf(index as u64, state))
+                .collect(),
+        )
+    }
+
+    fn live_block_mut(&mut self, index: u64) -> Result<&mut BlockState, DynamicError> {
+        if self.erasure_coded {
+            return Err(DynamicError::ErasureCoded);
+        }
+
+        let block_count = self.block_count();
+        let state = self
+            .blocks
+            .get_mut(index as usize)
+            .ok_or(DynamicError::BlockOutOfRange { index, block_count })?;
+
+        if state.deleted {
+            return Err(DynamicError::BlockDeleted(index));
+        }
+        Ok(state)
+    }
+
+    fn pad_block(&self, data: &[u8]) -> Result<Vec<u8>, DynamicError> {
+        if self.erasure_coded {
+            return Err(DynamicError::ErasureCoded);
+        }
+        if data.len() > self.block_size {
+            return Err(DynamicError::InvalidBlockSize {
+                expected: self.block_size,
+                actual: data.len(),
+            });
+        }
+
+        let mut block = data.to_vec();
+        block.resize(self.block_size, 0);
+        Ok(block)
+    }
+
+    fn tag(&self, keys: &PorKeyPair, index: u64, version: u64, block: Vec<u8>) -> BlockUpdate {
+        let tag = keys.tag_block(&self.file_name, index, version, &block);
+        BlockUpdate { index, version, block, tag }
+    }
+}
+
+/// Merkle leaf committing to the state of block `index`.
+pub fn block_state_leaf(index: u64, state: &BlockState) -> Hash {
+    let mut data = [0u8; 17];
+    data[..8].copy_from_slice(&index.to_be_bytes());
+    data[8..16].copy_from_slice(&state.version.to_be_bytes());
+    data[16] = state.deleted as u8;
+
+    merkle::leaf_hash(&data)
+}
+
+/// Checks a block state claimed by the server against the committed `state_root` over
+/// `block_count` blocks.
+pub fn verify_block_state(state_root: &Hash, block_count: u64, index: u64, state: &BlockState, path: &[Hash]) -> bool {
+    let leaf = block_state_leaf(index, state);
+    merkle::verify_path(state_root, &leaf, index as usize, block_count as usize, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::por::PorParams;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    fn keys() -> PorKeyPair {
+        PorKeyPair::generate(PorParams::new(1), &mut ChaCha20Rng::seed_from_u64(11))
+    }
+
+    #[test]
+    fn bumps_the_file_version_once_per_commit() {
+        let keys = keys();
+        let mut file = DynamicFile::new(b"file", 31, 3);
+        assert_eq!(file.commit(), None);
+
+        let appended = file.append(&keys, b"new block").unwrap();
+        let modified = file.modify(&keys, 1, b"changed").unwrap();
+        assert_eq!(file.file_version(), 0);
+        assert_eq!(file.commit(), Some(1));
+        assert_eq!(file.commit(), None);
+
+        assert_eq!((appended.index, appended.version), (3, 0));
+        assert_eq!((modified.index, modified.version), (1, 1));
+        assert_eq!(modified.tag, keys.tag_block(b"file", 1, 1, &modified.block));
+        assert_eq!(file.block_count(), 4);
+
+        file.delete(0).unwrap();
+        assert_eq!(file.commit(), Some(2));
+        assert_eq!(file.live_indices().collect::<Vec<_>>(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn proves_block_states_against_the_committed_root() {
+        let keys = keys();
+        let mut file = DynamicFile::new(b"file", 31, 5);
+        file.modify(&keys, 2, b"changed").unwrap();
+        file.delete(4).unwrap();
+        let root = file.state_root();
+
+        let state = file.block(2).unwrap();
+        let path = file.state_proof(2).unwrap();
+        assert!(verify_block_state(&root, 5, 2, &state, &path));
+        // A replayed old version doesn't match the commitment
+        let stale = BlockState { version: 0, deleted: false };
+        assert!(!verify_block_state(&root, 5, 2, &stale, &path));
+
+        let deleted = file.block(4).unwrap();
+        assert_eq!(deleted, BlockState { version: 1, deleted: true });
+        assert!(verify_block_state(&root, 5, 4, &deleted, &file.state_proof(4).unwrap()));
+    }
+
+    #[test]
+    fn rejects_edits_of_missing_deleted_or_oversized_blocks() {
+        let keys = keys();
+        let mut file = DynamicFile::new(b"file", 31, 2);
+        file.delete(1).unwrap();
+
+        assert_eq!(
+            file.modify(&keys, 2, b"data").unwrap_err(),
+            DynamicError::BlockOutOfRange { index: 2, block_count: 2 }
+        );
+        assert_eq!(file.delete(1).unwrap_err(), DynamicError::BlockDeleted(1));
+        assert_eq!(
+            file.append(&keys, &[0; 32]).unwrap_err(),
+            DynamicError::InvalidBlockSize { expected: 31, actual: 32 }
+        );
+    }
+
+    #[test]
+    fn rejects_edits_of_erasure_coded_files() {
+        let keys = keys();
+        let mut file = DynamicFile::new(b"file", 31, 3).erasure_coded();
+
+        assert_eq!(file.append(&keys, b"data").unwrap_err(), DynamicError::ErasureCoded);
+        assert_eq!(file.modify(&keys, 0, b"data").unwrap_err(), DynamicError::ErasureCoded);
+        assert_eq!(file.delete(0).unwrap_err(), DynamicError::ErasureCoded);
+        assert_eq!(file.commit(), None);
+    }
+}
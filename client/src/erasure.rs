@@ -0,0 +1,336 @@
+//! Reed–Solomon erasure coding applied to a file before it is split into blocks and tagged.
+//!
+//! The file is cut into stripes of `data_shards` blocks. Every stripe is extended with
+//! `parity_shards` parity blocks, so the encoded file is `data_shards + parity_shards`
+//! blocks per stripe, laid out stripe after stripe. Any `data_shards` blocks of a stripe are
+//! enough to rebuild it.
+//!
+//! The guarantee is per stripe: losing up to `parity_shards` blocks of every stripe is
+//! harmless, but losing `parity_shards + 1` blocks of a single stripe loses that stripe.
+//! Stripes are encoded independently, without the keyed permutation across stripes the
+//! Shacham–Waters construction assumes, so a server that drops those few blocks still passes
+//! almost every spot check. Spot checks only bound the fraction of blocks lost over the whole
+//! file; they don't prove the whole file can be recovered.
+
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use std::fmt;
+
+/// GF(2^8) codes cannot have more than 256 shards per stripe.
+pub const MAX_SHARDS_PER_STRIPE: usize = 256;
+
+#[derive(Debug)]
+pub enum ErasureError {
+    /// The coding parameters are out of range.
+    InvalidParameters(String),
+    /// A block has a different size than the coder's block size.
+    InvalidBlockSize { index: usize, expected: usize, actual: usize },
+    /// The number of blocks does not match the encoded layout.
+    InvalidBlockCount { expected: usize, actual: usize },
+    /// Too few blocks of a stripe survived to rebuild it.
+    NotEnoughBlocks { stripe: usize, available: usize, required: usize },
+    /// Error reported by the Reed–Solomon backend.
+    Codec(reed_solomon_erasure::Error),
+}
+
+impl fmt::Display for ErasureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErasureError::InvalidParameters(reason) => write!(f, "Invalid erasure coding parameters: {}", reason),
+            ErasureError::InvalidBlockSize { index, expected, actual } => write!(
+                f,
+                "Invalid size for block {}: expected {} bytes, got {} bytes",
+                index, expected, actual
+            ),
+            ErasureError::InvalidBlockCount { expected, actual } => write!(
+                f,
+                "Invalid block count: expected {} blocks, got {} blocks",
+                expected, actual
+            ),
+            ErasureError::NotEnoughBlocks { stripe, available, required } => write!(
+                f,
+                "Stripe {} cannot be recovered: {} blocks available, {} required",
+                stripe, available, required
+            ),
+            ErasureError::Codec(err) => write!(f, "Reed-Solomon error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ErasureError {}
+
+impl From<reed_solomon_erasure::Error> for ErasureError {
+    fn from(err: reed_solomon_erasure::Error) -> Self {
+        ErasureError::Codec(err)
+    }
+}
+
+/// An erasure-coded file, ready to be tagged block by block.
+#[derive(Debug, Clone)]
+pub struct EncodedFile {
+    /// Length of the original file in bytes, needed to strip the padding on recovery.
+    pub original_len: u64,
+    pub block_size: usize,
+    pub data_shards: usize,
+    pub parity_shards: usize,
+    /// All encoded blocks, stripe after stripe (data blocks first, then parity blocks).
+    pub blocks: Vec<Vec<u8>>,
+}
+
+impl EncodedFile {
+    pub fn stripe_count(&self) -> usize {
+        self.blocks.len() / (self.data_shards + self.parity_shards)
+    }
+}
+
+/// Reed–Solomon encoder/decoder for files split into fixed-size blocks.
+pub struct ErasureCoder {
+    data_shards: usize,
+    parity_shards: usize,
+    block_size: usize,
+    codec: ReedSolomon,
+}
+
+impl ErasureCoder {
+    /// Creates a coder adding `parity_shards` parity blocks to every `data_shards` data blocks.
+    pub fn new(data_shards: usize, parity_shards: usize, block_size: usize) -> Result<Self, ErasureError> {
+        if data_shards == 0 || parity_shards == 0 {
+            return Err(ErasureError::InvalidParameters(
+                "data and parity shard counts must be positive".to_string(),
+            ));
+        }
+        if data_shards + parity_shards > MAX_SHARDS_PER_STRIPE {
+            return Err(ErasureError::InvalidParameters(format!(
+                "at most {} shards per stripe are supported, got {}",
+                MAX_SHARDS_PER_STRIPE,
+                data_shards + parity_shards
+            )));
+        }
+        if block_size == 0 {
+            return Err(ErasureError::InvalidParameters("block size must be positive".to_string()));
+        }
+
+        Ok(ErasureCoder {
+            data_shards,
+            parity_shards,
+            block_size,
+            codec: ReedSolomon::new(data_shards, parity_shards)?,
+        })
+    }
+
+    /// Creates a coder that expands the file by `expansion` (e.g. `1.5` stores 50% extra parity).
+    ///
+    /// The parity shard count is rounded up, so the effective expansion is at least `expansion`.
+    pub fn with_expansion(data_shards: usize, expansion: f64, block_size: usize) -> Result<Self, ErasureError> {
+        if !expansion.is_finite() || expansion <= 1.0 {
+            return Err(ErasureError::InvalidParameters(format!(
+                "expansion must be greater than 1, got {}",
+                expansion
+            )));
+        }
+
+        let parity_shards = (data_shards as f64 * (expansion - 1.0)).ceil() as usize;
+        Self::new(data_shards, parity_shards, block_size)
+    }
+
+    pub fn data_shards(&self) -> usize {
+        self.data_shards
+    }
+
+    pub fn parity_shards(&self) -> usize {
+        self.parity_shards
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Ratio between the encoded and the original size, ignoring padding.
+    pub fn expansion(&self) -> f64 {
+        (self.data_shards + self.parity_shards) as f64 / self.data_shards as f64
+    }
+
+    /// Number of bytes of original data covered by one stripe.
+    pub fn stripe_data_len(&self) -> usize {
+        self.data_shards * self.block_size
+    }
+
+    /// Encodes a single stripe of at most `stripe_data_len()` bytes into
+    /// `data_shards + parity_shards` blocks. Short input is zero padded.
+    pub fn encode_stripe(&self, data: &[u8]) -> Result<Vec<Vec<u8>>, ErasureError> {
+        if data.len() > self.stripe_data_len() {
+            return Err(ErasureError::InvalidParameters(format!(
+                "stripe holds at most {} bytes, got {} bytes",
+                self.stripe_data_len(),
+                data.len()
+            )));
+        }
+
+        let mut shards: Vec<Vec<u8>> = (0..self.data_shards + self.parity_shards)
+            .map(|_| vec![0u8; self.block_size])
+            .collect();
+
+        for (shard, chunk) in shards.iter_mut().zip(data.chunks(self.block_size)) {
+            shard[..chunk.len()].copy_from_slice(chunk);
+        }
+
+        self.codec.encode(&mut shards)?;
+        Ok(shards)
+    }
+
+    /// Erasure-codes the whole file.
+    pub fn encode(&self, data: &[u8]) -> Result<EncodedFile, ErasureError> {
+        let stripe_count = data.len().div_ceil(self.stripe_data_len()).max(1);
+        let mut blocks = Vec::with_capacity(stripe_count * (self.data_shards + self.parity_shards));
+
+        if data.is_empty() {
+            blocks.extend(self.encode_stripe(data)?);
+        }
+        for stripe in data.chunks(self.stripe_data_len()) {
+            blocks.extend(self.encode_stripe(stripe)?);
+        }
+
+        Ok(EncodedFile {
+            original_len: data.len() as u64,
+            block_size: self.block_size,
+            data_shards: self.data_shards,
+            parity_shards: self.parity_shards,
+            blocks,
+        })
+    }
+
+    /// Rebuilds the original file from the surviving blocks.
+    ///
+    /// `blocks` must follow the encoded layout, with `None` for every lost or corrupted block.
+    /// Each stripe needs at least `data_shards` surviving blocks.
+    pub fn recover(&self, mut blocks: Vec<Option<Vec<u8>>>, original_len: u64) -> Result<Vec<u8>, ErasureError> {
+        let stripe_len = self.data_shards + self.parity_shards;
+        if blocks.is_empty() || !blocks.len().is_multiple_of(stripe_len) {
+            return Err(ErasureError::InvalidBlockCount {
+                expected: blocks.len().div_ceil(stripe_len).max(1) * stripe_len,
+                actual: blocks.len(),
+            });
+        }
+
+        for (index, block) in blocks.iter().enumerate() {
+            if let Some(block) = block {
+                if block.len() != self.block_size {
+                    return Err(ErasureError::InvalidBlockSize {
+                        index,
+                        expected: self.block_size,
+                        actual: block.len(),
+                    });
+                }
+            }
+        }
+
+        let mut data = Vec::with_capacity(blocks.len() / stripe_len * self.stripe_data_len());
+        for (stripe, shards) in blocks.chunks_mut(stripe_len).enumerate() {
+            let available = shards.iter().filter(|shard| shard.is_some()).count();
+            if available < self.data_shards {
+                return Err(ErasureError::NotEnoughBlocks {
+                    stripe,
+                    available,
+                    required: self.data_shards,
+                });
+            }
+
+            self.codec.reconstruct_data(shards)?;
+            for shard in shards.iter().take(self.data_shards) {
+                // reconstruct_data fills every data shard on success
+                data.extend_from_slice(shard.as_deref().unwrap_or_default());
+            }
+        }
+
+        if (data.len() as u64) < original_len {
+            return Err(ErasureError::InvalidParameters(format!(
+                "original length {} exceeds the {} bytes held by the blocks",
+                original_len,
+                data.len()
+            )));
+        }
+
+        data.truncate(original_len as usize);
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn recovers_from_as_many_erasures_as_parity_blocks() {
+        let coder = ErasureCoder::new(4, 2, 16).unwrap();
+        let data = file(150);
+        let encoded = coder.encode(&data).unwrap();
+        assert_eq!(encoded.stripe_count(), 3);
+        assert_eq!(encoded.blocks.len(), 18);
+
+        // Two blocks lost in every stripe, data and parity alike
+        let mut blocks: Vec<Option<Vec<u8>>> = encoded.blocks.into_iter().map(Some).collect();
+        for lost in [0, 5, 7, 8, 12, 13] {
+            blocks[lost] = None;
+        }
+        assert_eq!(coder.recover(blocks, encoded.original_len).unwrap(), data);
+    }
+
+    #[test]
+    fn rejects_a_stripe_missing_too_many_blocks() {
+        let coder = ErasureCoder::new(4, 2, 16).unwrap();
+        let encoded = coder.encode(&file(64)).unwrap();
+
+        let mut blocks: Vec<Option<Vec<u8>>> = encoded.blocks.into_iter().map(Some).collect();
+        for lost in [1, 2, 4] {
+            blocks[lost] = None;
+        }
+        assert!(matches!(
+            coder.recover(blocks, encoded.original_len),
+            Err(ErasureError::NotEnoughBlocks { stripe: 0, available: 3, required: 4 })
+        ));
+    }
+
+    #[test]
+    fn rejects_blocks_of_the_wrong_layout() {
+        let coder = ErasureCoder::new(4, 2, 16).unwrap();
+        let encoded = coder.encode(&file(64)).unwrap();
+
+        let mut blocks: Vec<Option<Vec<u8>>> = encoded.blocks.iter().cloned().map(Some).collect();
+        blocks.pop();
+        assert!(matches!(
+            coder.recover(blocks, encoded.original_len),
+            Err(ErasureError::InvalidBlockCount { expected: 6, actual: 5 })
+        ));
+
+        let mut blocks: Vec<Option<Vec<u8>>> = encoded.blocks.into_iter().map(Some).collect();
+        blocks[3] = Some(vec![0; 15]);
+        assert!(matches!(
+            coder.recover(blocks, encoded.original_len),
+            Err(ErasureError::InvalidBlockSize { index: 3, expected: 16, actual: 15 })
+        ));
+    }
+
+    #[test]
+    fn encodes_an_empty_file_as_one_stripe() {
+        let coder = ErasureCoder::new(2, 1, 8).unwrap();
+        let encoded = coder.encode(&[]).unwrap();
+        assert_eq!(encoded.blocks.len(), 3);
+
+        let blocks = encoded.blocks.into_iter().map(Some).collect();
+        assert!(coder.recover(blocks, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rounds_the_parity_up_to_the_expansion() {
+        let coder = ErasureCoder::with_expansion(10, 1.25, 16).unwrap();
+        assert_eq!(coder.parity_shards(), 3);
+        assert!(coder.expansion() >= 1.25);
+
+        assert!(ErasureCoder::with_expansion(10, 1.0, 16).is_err());
+        assert!(ErasureCoder::new(200, 57, 16).is_err());
+        assert!(ErasureCoder::new(4, 0, 16).is_err());
+    }
+}
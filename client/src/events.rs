@@ -0,0 +1,334 @@
+//! Live events of the subscriptions of the mediator program.
+//!
+//! The listener subscribes to the logs of the transactions mentioning the program through the
+//! pubsub websocket, fetches every successful one, and turns its program instructions into
+//! typed events: the turn changed, a proof was submitted, funds were released, the subscription
+//! ended. Events are published on a broadcast channel, which the HTTP API streams to browsers.
+//! A dropped websocket is reopened with an exponential backoff.
+
+use crate::query::{program_transaction, ProgramTransaction};
+use crate::transactions::TransactionError;
+use anchor_client::solana_client::nonblocking::pubsub_client::{PubsubClient, PubsubClientError};
+use anchor_client::solana_client::nonblocking::rpc_client::RpcClient;
+use anchor_client::solana_client::rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter};
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::solana_sdk::signature::Signature;
+use serde::Serialize;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio_stream::StreamExt;
+use utoipa::ToSchema;
+
+/// Events kept for the receivers that fall behind, after which they miss the oldest ones.
+pub const EVENT_CAPACITY: usize = 1024;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+// A transaction notified at some commitment may not be served by `getTransaction` right away
+const FETCH_ATTEMPTS: u32 = 3;
+const FETCH_DELAY: Duration = Duration::from_millis(500);
+
+/// Something that happened to one subscription, in a confirmed transaction.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct SubscriptionEvent {
+    pub program_context: String,
+    pub signature: String,
+    pub slot: u64,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    /// `is_server_turn` was set by the instruction.
+    TurnChanged { is_server_turn: bool },
+    /// The server recorded a BLS proof, or answered a Merkle challenge.
+    ProofSubmitted {
+        server: String,
+        #[schema(value_type = String)]
+        proof_kind: &'static str,
+    },
+    /// The server retrieved its payment; `lamports` is 0 while the mediator holds 5 SOL or less.
+    FundsReleased { server: String, mediator: String, lamports: u64 },
+    SubscriptionEnded,
+}
+
+impl EventKind {
+    /// Name of the event, as in the `type` field.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::TurnChanged { .. } => "turn_changed",
+            EventKind::ProofSubmitted { .. } => "proof_submitted",
+            EventKind::FundsReleased { .. } => "funds_released",
+            EventKind::SubscriptionEnded => "subscription_ended",
+        }
+    }
+}
+
+/// Events of the program instructions of `transaction`, in order; none if it failed.
+pub fn events_of(transaction: &ProgramTransaction) -> Vec<SubscriptionEvent> {
+    if transaction.error.is_some() {
+        return Vec::new();
+    }
+
+    let event = |program_context: &Pubkey, kind: EventKind| SubscriptionEvent {
+        program_context: program_context.to_string(),
+        signature: transaction.signature.to_string(),
+        slot: transaction.slot,
+        kind,
+    };
+
+    let mut events = Vec::new();
+    for call in &transaction.calls {
+        let accounts = call.accounts.as_slice();
+        match (call.name, accounts) {
+            // Accounts: payer, mediator, program context, system program
+            (Some("set_client_curve_points" | "set_merkle_commitment" | "extend_subscription"), [_, _, program_context, ..]) => {
+                events.push(event(program_context, EventKind::TurnChanged { is_server_turn: true }));
+            }
+            // Accounts: server, then every program context covered by the proof
+            (Some("submit_aggregated_proof"), [server, program_contexts @ ..]) => {
+                for program_context in program_contexts {
+                    let kind = EventKind::ProofSubmitted {
+                        server: server.to_string(),
+                        proof_kind: "bls",
+                    };
+                    events.push(event(program_context, kind));
+                }
+            }
+            // Accounts: server, program context
+            (Some("submit_merkle_proof"), [server, program_context, ..]) => {
+                let kind = EventKind::ProofSubmitted {
+                    server: server.to_string(),
+                    proof_kind: "merkle",
+                };
+                events.push(event(program_context, kind));
+            }
+            // Accounts: server, mediator, program context, system program
+            (Some("retrieve"), [server, mediator, program_context, ..]) => {
+                let released = transaction.lamport_changes.get(mediator).copied().unwrap_or(0);
+                let kind = EventKind::FundsReleased {
+                    server: server.to_string(),
+                    mediator: mediator.to_string(),
+                    lamports: released.min(0).unsigned_abs(),
+                };
+                events.push(event(program_context, kind));
+                events.push(event(program_context, EventKind::TurnChanged { is_server_turn: false }));
+            }
+            // Accounts: program context
+            (Some("end_subscription"), [program_context, ..]) => {
+                events.push(event(program_context, EventKind::SubscriptionEnded));
+                events.push(event(program_context, EventKind::TurnChanged { is_server_turn: true }));
+            }
+            _ => {}
+        }
+    }
+
+    events
+}
+
+/// Publishes the events of the program deployed at `program_id` to `sender`, until the process
+/// stops. Transactions are fetched through `rpc`, at its commitment.
+pub async fn run_listener(rpc: &RpcClient, ws_url: &str, program_id: &Pubkey, sender: &broadcast::Sender<SubscriptionEvent>) {
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        match listen(rpc, ws_url, program_id, sender, &mut backoff).await {
+            Ok(()) => eprintln!("Program log subscription closed, reconnecting in {:?}", backoff),
+            Err(err) => eprintln!("Program log subscription failed: {}, reconnecting in {:?}", err, backoff),
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+// Forwards the events of the transactions notified on one websocket connection
+async fn listen(
+    rpc: &RpcClient,
+    ws_url: &str,
+    program_id: &Pubkey,
+    sender: &broadcast::Sender<SubscriptionEvent>,
+    backoff: &mut Duration,
+) -> Result<(), PubsubClientError> {
+    let pubsub = PubsubClient::new(ws_url).await?;
+    let filter = RpcTransactionLogsFilter::Mentions(vec![program_id.to_string()]);
+    let config = RpcTransactionLogsConfig {
+        commitment: Some(rpc.commitment()),
+    };
+    let (mut notifications, unsubscribe) = pubsub.logs_subscribe(filter, config).await?;
+    *backoff = INITIAL_BACKOFF;
+
+    while let Some(notification) = notifications.next().await {
+        // Failed transactions change nothing, and nobody needs the events of an unwatched stream
+        let logs = notification.value;
+        if logs.err.is_some() || sender.receiver_count() == 0 {
+            continue;
+        }
+        let Ok(signature) = Signature::from_str(&logs.signature) else { continue };
+
+        match fetch_transaction(rpc, program_id, &signature).await {
+            Ok(transaction) => {
+                for event in events_of(&transaction) {
+                    // Fails only when every receiver is gone
+                    let _ = sender.send(event);
+                }
+            }
+            Err(err) => eprintln!("Skipped the events of transaction {}: {}", signature, err),
+        }
+    }
+
+    unsubscribe().await;
+    Ok(())
+}
+
+async fn fetch_transaction(rpc: &RpcClient, program_id: &Pubkey, signature: &Signature) -> Result<ProgramTransaction, TransactionError> {
+    let mut attempt = 1;
+    loop {
+        match program_transaction(rpc, program_id, signature).await {
+            Err(_) if attempt < FETCH_ATTEMPTS => {
+                tokio::time::sleep(FETCH_DELAY).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::ProgramCall;
+    use anchor_lang::solana_program::native_token::LAMPORTS_PER_SOL;
+    use std::collections::HashMap;
+
+    fn transaction(calls: Vec<ProgramCall>) -> ProgramTransaction {
+        ProgramTransaction {
+            signature: Signature::new_unique(),
+            slot: 42,
+            error: None,
+            calls,
+            lamport_changes: HashMap::new(),
+        }
+    }
+
+    fn call(name: &'static str, accounts: &[Pubkey]) -> ProgramCall {
+        ProgramCall {
+            name: Some(name),
+            accounts: accounts.to_vec(),
+        }
+    }
+
+    fn kinds(events: &[SubscriptionEvent]) -> Vec<(String, EventKind)> {
+        events
+            .iter()
+            .map(|event| (event.program_context.clone(), event.kind.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn client_instructions_give_the_turn_to_the_server() {
+        let (payer, mediator, program_context) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let accounts = [payer, mediator, program_context, Pubkey::default()];
+        let transaction = transaction(vec![call("set_client_curve_points", &accounts), call("extend_subscription", &accounts)]);
+
+        let events = events_of(&transaction);
+        let turn_changed = (program_context.to_string(), EventKind::TurnChanged { is_server_turn: true });
+        assert_eq!(kinds(&events), vec![turn_changed.clone(), turn_changed]);
+        assert!(events.iter().all(|event| event.signature == transaction.signature.to_string() && event.slot == 42));
+    }
+
+    #[test]
+    fn an_aggregated_proof_is_submitted_for_every_program_context() {
+        let (server, first, second) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let transaction = transaction(vec![call("submit_aggregated_proof", &[server, first, second])]);
+
+        let submitted = EventKind::ProofSubmitted {
+            server: server.to_string(),
+            proof_kind: "bls",
+        };
+        assert_eq!(
+            kinds(&events_of(&transaction)),
+            vec![(first.to_string(), submitted.clone()), (second.to_string(), submitted)]
+        );
+    }
+
+    #[test]
+    fn a_retrieve_releases_what_the_mediator_lost() {
+        let (server, mediator, program_context) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let mut transaction = transaction(vec![call("retrieve", &[server, mediator, program_context, Pubkey::default()])]);
+        transaction.lamport_changes.insert(mediator, -(LAMPORTS_PER_SOL as i64));
+        transaction.lamport_changes.insert(server, LAMPORTS_PER_SOL as i64 - 5000);
+
+        let released = EventKind::FundsReleased {
+            server: server.to_string(),
+            mediator: mediator.to_string(),
+            lamports: LAMPORTS_PER_SOL,
+        };
+        assert_eq!(
+            kinds(&events_of(&transaction)),
+            vec![
+                (program_context.to_string(), released),
+                (program_context.to_string(), EventKind::TurnChanged { is_server_turn: false }),
+            ]
+        );
+    }
+
+    #[test]
+    fn ending_a_subscription_and_merkle_proofs_are_reported() {
+        let (server, program_context) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let transaction = transaction(vec![call("submit_merkle_proof", &[server, program_context]), call("end_subscription", &[program_context])]);
+
+        assert_eq!(
+            kinds(&events_of(&transaction)),
+            vec![
+                (
+                    program_context.to_string(),
+                    EventKind::ProofSubmitted {
+                        server: server.to_string(),
+                        proof_kind: "merkle",
+                    }
+                ),
+                (program_context.to_string(), EventKind::SubscriptionEnded),
+                (program_context.to_string(), EventKind::TurnChanged { is_server_turn: true }),
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_unknown_and_truncated_calls_have_no_events() {
+        let program_context = Pubkey::new_unique();
+        let mut failed = transaction(vec![call("end_subscription", &[program_context])]);
+        failed.error = Some("custom program error: 0x1770".to_string());
+        assert!(events_of(&failed).is_empty());
+
+        let unknown = ProgramCall {
+            name: None,
+            accounts: vec![program_context],
+        };
+        let ignored = transaction(vec![unknown, call("initialize", &[program_context]), call("retrieve", &[program_context])]);
+        assert!(events_of(&ignored).is_empty());
+    }
+
+    #[test]
+    fn events_serialize_with_their_type() {
+        let event = SubscriptionEvent {
+            program_context: "context".to_string(),
+            signature: "signature".to_string(),
+            slot: 7,
+            kind: EventKind::TurnChanged { is_server_turn: false },
+        };
+
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({
+                "program_context": "context",
+                "signature": "signature",
+                "slot": 7,
+                "type": event.kind.name(),
+                "is_server_turn": false,
+            })
+        );
+    }
+}
@@ -0,0 +1,366 @@
+//! PoR key management: generation and password-encrypted key files.
+//!
+//! A key file is a versioned JSON document. The public key `(g, v, u_1..u_s)` is stored in the
+//! clear (compressed points as hex) so it can be read without the password; the secret `α`
+//! is encrypted with ChaCha20-Poly1305 under a key derived from the password with Argon2id.
+//! The public key and the block size are passed as associated data, so swapping them for
+//! others makes decryption fail.
+
+use crate::por::{PorKeyPair, PorParams, PorPublicKey, PorSecretKey};
+use argon2::{Algorithm, Argon2, Params, Version};
+use bls12_381::{G1Affine, G2Affine};
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+/// Current version of the key file format.
+pub const KEY_FILE_VERSION: u32 = 1;
+
+/// Sectors per block used when generating keys without an explicit choice.
+pub const DEFAULT_SECTORS_PER_BLOCK: usize = 16;
+
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+
+#[derive(Debug)]
+pub enum KeyFileError {
+    Io(io::Error),
+    Format(String),
+    UnsupportedVersion(u32),
+    /// Wrong password, or the file was tampered with.
+    Decryption,
+    Kdf(String),
+}
+
+impl fmt::Display for KeyFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyFileError::Io(err) => write!(f, "I/O error: {}", err),
+            KeyFileError::Format(reason) => write!(f, "Invalid key file: {}", reason),
+            KeyFileError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported key file version {}, expected {}",
+                version, KEY_FILE_VERSION
+            ),
+            KeyFileError::Decryption => write!(f, "Wrong password or corrupted key file"),
+            KeyFileError::Kdf(reason) => write!(f, "Key derivation failed: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for KeyFileError {}
+
+impl From<io::Error> for KeyFileError {
+    fn from(err: io::Error) -> Self {
+        KeyFileError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for KeyFileError {
+    fn from(err: serde_json::Error) -> Self {
+        KeyFileError::Format(err.to_string())
+    }
+}
+
+/// Argon2id cost parameters, stored in the file so they can be raised later.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            memory_kib: 64 * 1024,
+            iterations: 3,
+            parallelism: 1,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PublicKeyFile {
+    pub g: String,
+    pub v: String,
+    pub u: Vec<String>,
+    pub block_size: usize,
+}
+
+/// On-disk layout of a key file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct KeyFile {
+    pub version: u32,
+    pub kdf: KdfParams,
+    pub salt: String,
+    pub nonce: String,
+    pub public_key: PublicKeyFile,
+    pub encrypted_secret_key: String,
+}
+
+impl PublicKeyFile {
+    pub fn from_public_key(public: &PorPublicKey) -> Self {
+        PublicKeyFile {
+            g: hex::encode(public.g.to_compressed()),
+            v: hex::encode(public.v.to_compressed()),
+            u: public.u.iter().map(|u_j| hex::encode(u_j.to_compressed())).collect(),
+            block_size: public.block_size,
+        }
+    }
+
+    pub fn to_public_key(&self) -> Result<PorPublicKey, KeyFileError> {
+        let params = PorParams {
+            sectors_per_block: self.u.len(),
+            block_size: self.block_size,
+        };
+        if !params.is_valid() {
+            return Err(KeyFileError::Format("invalid block size".to_string()));
+        }
+
+        Ok(PorPublicKey {
+            g: decode_g2(&self.g)?,
+            v: decode_g2(&self.v)?,
+            u: self.u.iter().map(|u_j| decode_g1(u_j)).collect::<Result<_, _>>()?,
+            block_size: params.block_size,
+        })
+    }
+
+    /// Canonical bytes bound to the encrypted secret as associated data.
+    fn associated_data(&self) -> Vec<u8> {
+        let mut data = format!("por-key-file-v{}:{}:{}", KEY_FILE_VERSION, self.g, self.v).into_bytes();
+        for u_j in &self.u {
+            data.push(b':');
+            data.extend_from_slice(u_j.as_bytes());
+        }
+        data.extend_from_slice(format!(":block_size={}", self.block_size).as_bytes());
+        data
+    }
+}
+
+impl KeyFile {
+    /// Encrypts a key pair with `password`.
+    pub fn seal(keys: &PorKeyPair, password: &[u8], kdf: KdfParams) -> Result<Self, KeyFileError> {
+        let public_key = PublicKeyFile::from_public_key(&keys.public);
+        let mut salt = [0u8; SALT_SIZE];
+        let mut nonce = [0u8; NONCE_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let alpha_bytes = keys.secret.to_bytes();
+        let cipher = derive_cipher(password, &salt, &kdf)?;
+        let ciphertext = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &alpha_bytes[..],
+                    aad: &public_key.associated_data(),
+                },
+            )
+            .map_err(|_| KeyFileError::Decryption)?;
+
+        Ok(KeyFile {
+            version: KEY_FILE_VERSION,
+            kdf,
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce),
+            public_key,
+            encrypted_secret_key: hex::encode(ciphertext),
+        })
+    }
+
+    /// Decrypts the key pair with `password`.
+    pub fn open(&self, password: &[u8]) -> Result<PorKeyPair, KeyFileError> {
+        if self.version != KEY_FILE_VERSION {
+            return Err(KeyFileError::UnsupportedVersion(self.version));
+        }
+
+        let salt = decode_hex(&self.salt, "salt")?;
+        let nonce = decode_hex(&self.nonce, "nonce")?;
+        if nonce.len() != NONCE_SIZE {
+            return Err(KeyFileError::Format("invalid nonce length".to_string()));
+        }
+        let ciphertext = decode_hex(&self.encrypted_secret_key, "encrypted secret key")?;
+
+        let cipher = derive_cipher(password, &salt, &self.kdf)?;
+        let plaintext = Zeroizing::new(cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: &self.public_key.associated_data(),
+                },
+            )
+            .map_err(|_| KeyFileError::Decryption)?);
+
+        let alpha_bytes: Zeroizing<[u8; 32]> = Zeroizing::new(
+            plaintext
+                .as_slice()
+                .try_into()
+                .map_err(|_| KeyFileError::Format("invalid secret key length".to_string()))?,
+        );
+        let secret = PorSecretKey::from_bytes(&alpha_bytes)
+            .ok_or_else(|| KeyFileError::Format("secret key is not a valid scalar".to_string()))?;
+
+        let public = self.public_key.to_public_key()?;
+        if !secret.matches(&public) {
+            return Err(KeyFileError::Format("secret key doesn't match v".to_string()));
+        }
+
+        Ok(PorKeyPair { secret, public })
+    }
+}
+
+/// Generates a fresh PoR key pair `(α, g, v = g^α, u_1..u_s)`.
+pub fn generate_keys(params: PorParams) -> PorKeyPair {
+    PorKeyPair::generate(params, &mut OsRng)
+}
+
+/// Encrypts `keys` with `password` and writes them to `path`, readable by the owner only.
+pub fn save_keys(path: &Path, keys: &PorKeyPair, password: &[u8]) -> Result<(), KeyFileError> {
+    let key_file = KeyFile::seal(keys, password, KdfParams::default())?;
+    let contents = serde_json::to_vec_pretty(&key_file)?;
+
+    // Write next to the target and rename, so a crash never leaves a truncated key file
+    let temp_path = path.with_extension("tmp");
+    {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options.open(&temp_path)?;
+        file.write_all(&contents)?;
+        file.sync_all()?;
+    }
+    fs::rename(&temp_path, path)?;
+
+    Ok(())
+}
+
+/// Reads and decrypts the key pair stored at `path`.
+pub fn load_keys(path: &Path, password: &[u8]) -> Result<PorKeyPair, KeyFileError> {
+    let key_file: KeyFile = serde_json::from_slice(&fs::read(path)?)?;
+    key_file.open(password)
+}
+
+/// Reads only the public key stored at `path`; no password needed.
+pub fn load_public_key(path: &Path) -> Result<PorPublicKey, KeyFileError> {
+    let key_file: KeyFile = serde_json::from_slice(&fs::read(path)?)?;
+    key_file.public_key.to_public_key()
+}
+
+/// Loads the key pair at `path`, generating and saving a new one if the file doesn't exist.
+pub fn load_or_generate_keys(path: &Path, password: &[u8], params: PorParams) -> Result<PorKeyPair, KeyFileError> {
+    if path.exists() {
+        return load_keys(path, password);
+    }
+
+    let keys = generate_keys(params);
+    save_keys(path, &keys, password)?;
+    Ok(keys)
+}
+
+fn derive_cipher(password: &[u8], salt: &[u8], kdf: &KdfParams) -> Result<ChaCha20Poly1305, KeyFileError> {
+    let params = Params::new(kdf.memory_kib, kdf.iterations, kdf.parallelism, Some(32))
+        .map_err(|err| KeyFileError::Kdf(err.to_string()))?;
+
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password, salt, &mut key[..])
+        .map_err(|err| KeyFileError::Kdf(err.to_string()))?;
+
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key[..])))
+}
+
+fn decode_hex(value: &str, field: &str) -> Result<Vec<u8>, KeyFileError> {
+    hex::decode(value).map_err(|err| KeyFileError::Format(format!("invalid {}: {}", field, err)))
+}
+
+fn decode_g1(value: &str) -> Result<G1Affine, KeyFileError> {
+    let bytes: [u8; 48] = decode_hex(value, "G1 point")?
+        .try_into()
+        .map_err(|_| KeyFileError::Format("invalid G1 point length".to_string()))?;
+
+    Option::from(G1Affine::from_compressed(&bytes)).ok_or_else(|| KeyFileError::Format("invalid G1 point".to_string()))
+}
+
+fn decode_g2(value: &str) -> Result<G2Affine, KeyFileError> {
+    let bytes: [u8; 96] = decode_hex(value, "G2 point")?
+        .try_into()
+        .map_err(|_| KeyFileError::Format("invalid G2 point length".to_string()))?;
+
+    Option::from(G2Affine::from_compressed(&bytes)).ok_or_else(|| KeyFileError::Format("invalid G2 point".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    // Cheap enough for tests
+    const KDF: KdfParams = KdfParams {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    fn keys(params: PorParams) -> PorKeyPair {
+        PorKeyPair::generate(params, &mut ChaCha20Rng::seed_from_u64(5))
+    }
+
+    #[test]
+    fn opens_with_the_password_it_was_sealed_with() {
+        let keys = keys(PorParams::with_block_size(100));
+        let key_file = KeyFile::seal(&keys, b"password", KDF).unwrap();
+        assert_eq!(key_file.version, KEY_FILE_VERSION);
+
+        let opened = key_file.open(b"password").unwrap();
+        assert_eq!(opened.secret, keys.secret);
+        assert_eq!(opened.public, keys.public);
+        assert_eq!(opened.public.block_size(), 100);
+    }
+
+    #[test]
+    fn rejects_a_wrong_password() {
+        let key_file = KeyFile::seal(&keys(PorParams::new(2)), b"password", KDF).unwrap();
+
+        assert!(matches!(key_file.open(b"passw0rd"), Err(KeyFileError::Decryption)));
+    }
+
+    #[test]
+    fn rejects_a_swapped_public_key_or_block_size() {
+        let key_file = KeyFile::seal(&keys(PorParams::with_block_size(60)), b"password", KDF).unwrap();
+
+        let other = PorKeyPair::generate(PorParams::with_block_size(60), &mut ChaCha20Rng::seed_from_u64(6));
+        let mut swapped = key_file.clone();
+        swapped.public_key = PublicKeyFile::from_public_key(&other.public);
+        assert!(matches!(swapped.open(b"password"), Err(KeyFileError::Decryption)));
+
+        let mut resized = key_file;
+        resized.public_key.block_size = 62;
+        assert!(matches!(resized.open(b"password"), Err(KeyFileError::Decryption)));
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let mut key_file = KeyFile::seal(&keys(PorParams::new(2)), b"password", KDF).unwrap();
+
+        key_file.version = KEY_FILE_VERSION + 1;
+        assert!(matches!(
+            key_file.open(b"password"),
+            Err(KeyFileError::UnsupportedVersion(version)) if version == KEY_FILE_VERSION + 1
+        ));
+    }
+}
@@ -0,0 +1,22 @@
+pub mod aggregation;
+pub mod api;
+pub mod auditor;
+pub mod auth;
+pub mod challenge;
+pub mod config;
+pub mod dynamic;
+pub mod erasure;
+pub mod events;
+pub mod keys;
+pub mod merkle;
+pub mod pop;
+pub mod por;
+pub mod proof;
+pub mod query;
+pub mod signer;
+pub mod storage;
+pub mod tagger;
+pub mod transactions;
+pub mod typescript;
+pub mod verification;
+pub mod wire;
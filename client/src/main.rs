@@ -1,623 +1,1508 @@
-use anchor_client::{solana_client::rpc_client::RpcClient, solana_sdk::{
-    commitment_config::CommitmentConfig, native_token::LAMPORTS_PER_SOL, signature::Keypair,
-    signer::Signer, system_program,
-}, Client, ClientError, Cluster, DynSigner, Program};
+use anchor_client::{solana_client::nonblocking::rpc_client::RpcClient as NonblockingRpcClient, solana_sdk::{
+    native_token::LAMPORTS_PER_SOL, system_program,
+}};
 
-use solana_smart_contract::{instruction as ix};
+use solana_smart_contract::{instruction as ix, is_valid_por_params, MerkleLeafProof, ProgramContext, ProofKind};
 use solana_smart_contract::accounts as accounts;
-use std::{rc::Rc};
 
-use bls12_381::{pairing, G1Affine, G2Affine};
+use bls12_381::{G1Affine, G2Affine};
+use client::api::{self, ApiError, ErrorBody};
+use client::auditor::{Auditor, AuditorConfig};
+use client::challenge::random_seed;
+use client::events::{self, EventKind, SubscriptionEvent, EVENT_CAPACITY};
+use client::config::{ApiConfig, ClusterConfig, CommandLine, ConfigError, ConfigLayer, PorKeyConfig};
+use client::keys::load_or_generate_keys;
+use client::pop::{prove_possession, verify_possession_compressed};
+use client::por::PorKeyPair;
+use client::proof::Proof;
+use client::query::{self, HistoryEntry, MAX_SIGNATURES};
+use client::transactions;
+use client::typescript;
+use client::signer::{self, Keystore, TransactionSigner};
+use client::verification::{verify_batch, verify_proof, BatchVerification, ProofInstance};
+use client::wire::{HexArray, WireProof};
+use rand::rngs::OsRng;
 use serde::{Deserialize, Serialize};
-use serde::de::{Deserializer, Error as DeError};
-use serde::ser::Serializer;
-use solana_sdk::instruction::Instruction;
-use warp::{Filter, Rejection, Reply, http::StatusCode, reject::Reject, reject};
+use warp::{Filter, Rejection, Reply, http::StatusCode};
+use warp::body::BodyDeserializeError;
+use warp::reject::{InvalidQuery, MethodNotAllowed, PayloadTooLarge, UnsupportedMediaType};
 use warp::reply::Json;
-use std::{sync::Arc};
+use std::{collections::HashSet, convert::Infallible, path::Path, str::FromStr, sync::{Arc, RwLock}};
+use tokio::sync::broadcast;
+use tokio_stream::{wrappers::{errors::BroadcastStreamRecvError, BroadcastStream}, StreamExt};
+use warp::sse::Event;
 use anchor_lang::prelude::Pubkey;
+use solana_sdk::instruction::Instruction;
 use solana_sdk::signature::Signature;
-use warp::hyper::body::HttpBody;
-
-#[derive(Debug)]
-struct HexArray<const N: usize>([u8; N]);
-
-impl<const N: usize> Serialize for HexArray<N> {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        serializer.serialize_str(&hex::encode(self.0))
-    }
-}
-
-impl<'de, const N: usize> Deserialize<'de> for HexArray<N> {
-    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-        let s: &str = Deserialize::deserialize(deserializer)?;
-        let bytes = hex::decode(s).map_err(DeError::custom)?;
-        if bytes.len() != N {
-            return Err(DeError::custom(format!(
-                "Invalid length: expected {} bytes, got {} bytes",
-                N,
-                bytes.len()
-            )));
-        }
-        let mut array = [0u8; N];
-        array.copy_from_slice(&bytes);
-        Ok(HexArray(array))
-    }
-}
+use utoipa::{IntoParams, IntoResponses, OpenApi, ToSchema};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 struct RequestPayload {
-    #[serde(with = "hex_array_96")]
+    #[serde(with = "client::wire::hex_array")]
+    #[schema(value_type = String)]
     g_compressed: [u8; 96],
-    #[serde(with = "hex_array_48")]
+    #[serde(with = "client::wire::hex_array")]
+    #[schema(value_type = String)]
     sigma_compressed: [u8; 48],
-    #[serde(with = "hex_array_96")]
+    #[serde(with = "client::wire::hex_array")]
+    #[schema(value_type = String)]
     v_compressed: [u8; 96],
-    #[serde(with = "hex_array_48")]
+    #[serde(with = "client::wire::hex_array")]
+    #[schema(value_type = String)]
     multiplication_sum_compressed: [u8; 48],
 }
 
-mod hex_array_96 {
-    use serde::{Deserialize, Serialize};
-    use super::HexArray;
-
-    pub fn serialize<S>(value: &[u8; 96], serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        HexArray(*value).serialize(serializer)
+impl RequestPayload {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        Ok(())
     }
 
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<[u8; 96], D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let array = HexArray::<96>::deserialize(deserializer)?;
-        Ok(array.0)
+    /// Decompresses the curve points of the payload into a proof ready for verification.
+    pub fn to_proof_instance(&self) -> Result<ProofInstance, ApiError> {
+        let invalid_point = |name: &str| ApiError::InvalidCurvePoint(format!("Invalid {} point", name));
+        let g = Option::from(G2Affine::from_compressed(&self.g_compressed)).ok_or_else(|| invalid_point("g"))?;
+        let sigma = Option::from(G1Affine::from_compressed(&self.sigma_compressed)).ok_or_else(|| invalid_point("sigma"))?;
+        let v = Option::from(G2Affine::from_compressed(&self.v_compressed)).ok_or_else(|| invalid_point("v"))?;
+        let multiplication_sum = Option::from(G1Affine::from_compressed(&self.multiplication_sum_compressed))
+            .ok_or_else(|| invalid_point("multiplication sum"))?;
+
+        Ok(ProofInstance { g, v, sigma, multiplication_sum })
     }
 }
 
-mod hex_array_64 {
-    use serde::{Deserialize, Serialize};
-    use super::HexArray;
+#[derive(Serialize, ToSchema)]
+struct VerifyBatchResponse {
+    verified: bool,
+    invalid_proofs: Vec<usize>,   // Indices of the proofs that failed verification
+}
 
-    pub fn serialize<S>(value: &[u8; 64], serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        HexArray(*value).serialize(serializer)
-    }
+#[utoipa::path(
+    post,
+    path = "/verify",
+    request_body = RequestPayload,
+    responses(
+        (status = 200, body = String, description = "`Verified` or `Not Verified`"),
+        ErrorResponses,
+    )
+)]
+async fn verify_handler(body: RequestPayload) -> Result<Json, Rejection> {
+    let proof = body
+        .validate()
+        .and_then(|_| body.to_proof_instance())
+        .map_err(api::reject)?;
+
+    let is_verified = verify_proof(&proof);
+    Ok(warp::reply::json(&if is_verified { "Verified" } else { "Not Verified" }))
+}
 
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<[u8; 64], D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let array = HexArray::<64>::deserialize(deserializer)?;
-        Ok(array.0)
+#[utoipa::path(
+    post,
+    path = "/verifyBatch",
+    request_body = Vec<RequestPayload>,
+    responses(
+        (status = 200, body = VerifyBatchResponse, description = "Outcome of the batch verification"),
+        ErrorResponses,
+    )
+)]
+async fn verify_batch_handler(body: Vec<RequestPayload>) -> Result<Json, Rejection> {
+    let mut proofs = Vec::with_capacity(body.len());
+    for (index, payload) in body.iter().enumerate() {
+        match payload.validate().and_then(|_| payload.to_proof_instance()) {
+            Ok(proof) => proofs.push(proof),
+            Err(err) => return Err(api::reject(ApiError::InvalidInput(format!("Proof {}: {}", index, err)))),
+        }
     }
+
+    let outcome = verify_batch(&proofs, &mut OsRng);
+    let invalid_proofs = match &outcome {
+        BatchVerification::AllValid => Vec::new(),
+        BatchVerification::Invalid(invalid_proofs) => invalid_proofs.clone(),
+    };
+
+    Ok(warp::reply::json(&VerifyBatchResponse {
+        verified: outcome.is_valid(),
+        invalid_proofs,
+    }))
 }
 
-mod hex_array_48 {
-    use serde::{Deserialize, Serialize};
-    use super::HexArray;
+/// Handles errors and converts them into `{code, message, details}` JSON responses
+async fn handle_rejection(err: Rejection) -> Result<impl Reply, std::convert::Infallible> {
+    let error = if let Some(api_error) = err.find::<ApiError>() {
+        return Ok(warp::reply::with_status(warp::reply::json(&api_error.body()), api_error.status()));
+    } else if err.is_not_found() {
+        ApiError::NotFound("No such endpoint".to_string())
+    } else if let Some(body_error) = err.find::<BodyDeserializeError>() {
+        ApiError::InvalidInput(body_error.to_string())
+    } else if let Some(query_error) = err.find::<InvalidQuery>() {
+        ApiError::InvalidInput(query_error.to_string())
+    } else if err.find::<MethodNotAllowed>().is_some() {
+        return Ok(error_reply(StatusCode::METHOD_NOT_ALLOWED, "method_not_allowed", "Method not allowed"));
+    } else if err.find::<PayloadTooLarge>().is_some() {
+        return Ok(error_reply(StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large", "Request body too large"));
+    } else if err.find::<UnsupportedMediaType>().is_some() {
+        return Ok(error_reply(StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported_media_type", "Expected a JSON body"));
+    } else {
+        eprintln!("Unhandled rejection: {:?}", err);
+        ApiError::Internal("Internal Server Error".to_string())
+    };
+
+    Ok(warp::reply::with_status(warp::reply::json(&error.body()), error.status()))
+}
 
-    pub fn serialize<S>(value: &[u8; 48], serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        HexArray(*value).serialize(serializer)
-    }
+// Error response for the rejections of warp itself
+fn error_reply(status: StatusCode, code: &str, message: &str) -> warp::reply::WithStatus<Json> {
+    let body = ErrorBody {
+        code: code.to_string(),
+        message: message.to_string(),
+        details: None,
+    };
+    warp::reply::with_status(warp::reply::json(&body), status)
+}
 
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<[u8; 48], D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let array = HexArray::<48>::deserialize(deserializer)?;
-        Ok(array.0)
-    }
+// Error responses of every endpoint, documented once
+#[derive(IntoResponses)]
+#[allow(dead_code)]
+enum ErrorResponses {
+    #[response(status = "4XX", description = "Invalid request, or rejected by the program; `code` tells which")]
+    Client(ErrorBody),
+    #[response(status = "5XX", description = "RPC, signer or internal error")]
+    Server(ErrorBody),
 }
 
-impl RequestPayload {
-    pub fn validate(&self) -> Result<(), String> {
-        Ok(())
-    }
+// Function to load the PoR key pair, generated and saved on the first start
+fn load_por_keys(config: &PorKeyConfig) -> Result<PorKeyPair, ConfigError> {
+    let por_keys = load_or_generate_keys(&config.key_file, config.password.as_bytes(), config.params)
+        .map_err(|err| ConfigError::File(config.key_file.clone(), err.to_string()))?;
+
+    println!("PoR Keys loaded from {}:", config.key_file.display());
+    println!("   g: {}", hex::encode(por_keys.public.g.to_compressed()));
+    println!("   v: {}", hex::encode(por_keys.public.v.to_compressed()));
+    println!("   Sectors per block: {}", por_keys.public.sectors_per_block());
+    println!("   Block size: {} bytes", por_keys.public.block_size());
+
+    Ok(por_keys)
 }
 
-/// Custom rejection type for errors
-#[derive(Debug)]
-struct ClientRejection(String);
+// Function to request airdrop for a given public key
+async fn request_airdrop(connection: &NonblockingRpcClient, payer_pubkey: &Pubkey, amount: u64) -> Result<Signature, ApiError> {
+    println!("\nRequesting {} SOL airdrop to payer", amount);
+    Ok(connection.request_airdrop(payer_pubkey, amount).await?)
+}
 
-impl Reject for ClientRejection {}
+// State shared by every handler, built once at startup
+struct AppState {
+    config: ApiConfig,
+    rpc: NonblockingRpcClient,   // Nonblocking, so that its futures can be awaited in warp handlers
+    keystore: RwLock<Keystore>,
+    events: broadcast::Sender<SubscriptionEvent>,   // Published by the program log listener
+}
 
-/// Handles errors and converts them into proper JSON responses
-async fn handle_rejection(err: Rejection) -> Result<impl Reply, std::convert::Infallible> {
-    if let Some(ClientRejection(client_err)) = err.find::<ClientRejection>() {
-        let json = warp::reply::json(&format!("Client error: {:?}", client_err));
-        return Ok(warp::reply::with_status(json, StatusCode::BAD_REQUEST));
+impl AppState {
+    fn new(config: ApiConfig, keystore: Keystore) -> Self {
+        AppState {
+            rpc: NonblockingRpcClient::new_with_commitment(config.cluster.rpc_url.clone(), config.cluster.commitment),
+            config,
+            keystore: RwLock::new(keystore),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+        }
     }
+}
 
-    // Handle other errors
-    Ok(warp::reply::with_status(
-        warp::reply::json(&"Internal Server Error"),
-        StatusCode::INTERNAL_SERVER_ERROR,
-    ))
+type SharedState = Arc<AppState>;
+
+// Finds a signer of the keystore by id or public key
+fn find_signer(keystore: &RwLock<Keystore>, reference: &str) -> Result<Arc<dyn TransactionSigner>, Rejection> {
+    let keystore = keystore.read().map_err(|_| api::reject(ApiError::Internal("Keystore unavailable".to_string())))?;
+    keystore.get(reference).map_err(api::reject)
 }
 
-// Function to generate and print Keypairs
-fn generate_keypairs() -> (Arc<Keypair>, Arc<Keypair>, Arc<Keypair>, Arc<Keypair>) {
-    let payer = Arc::new(Keypair::new());
-    let server = Arc::new(Keypair::new());
-    let mediator = Arc::new(Keypair::new());
-    let program_context = Arc::new(Keypair::new());
+// Resolves an account that doesn't sign, given by keystore id or public key
+fn find_account(keystore: &RwLock<Keystore>, reference: &str) -> Result<Pubkey, Rejection> {
+    let keystore = keystore.read().map_err(|_| api::reject(ApiError::Internal("Keystore unavailable".to_string())))?;
+    keystore.resolve_pubkey(reference).map_err(api::reject)
+}
 
-    println!("Generated Keypairs:");
-    println!("   Payer: {}", payer.pubkey());
-    println!("   Server: {}", server.pubkey());
-    println!("   Mediator: {}", mediator.pubkey());
-    println!("   Program Context: {}", program_context.pubkey());
+// Decoded ProgramContext account, byte arrays as hex
+#[derive(Serialize, Debug, ToSchema)]
+struct ProgramContextSnapshot {
+    address: String,
+    is_subscription_ended: bool,
+    is_server_turn: bool,
+    subscription_duration: u64,
+    mediator_balance: u64,
+    #[serde(with = "client::wire::hex_array")]
+    #[schema(value_type = String)]
+    g_norm: [u8; 96],
+    #[serde(with = "client::wire::hex_array")]
+    #[schema(value_type = String)]
+    v_norm: [u8; 96],
+    client: String,
+    file_version: u64,
+    block_count: u64,
+    #[serde(with = "client::wire::hex_array")]
+    #[schema(value_type = String)]
+    file_state_root: [u8; 32],
+    #[schema(value_type = String)]
+    proof_kind: &'static str,
+    #[serde(with = "client::wire::hex_array")]
+    #[schema(value_type = String)]
+    challenge_seed: [u8; 32],
+    challenge_size: u8,
+    is_challenge_pending: bool,
+    #[serde(with = "client::wire::hex_array")]
+    #[schema(value_type = String)]
+    last_proof_digest: [u8; 32],
+    proof_count: u64,
+    #[serde(with = "client::wire::hex_array")]
+    #[schema(value_type = String)]
+    pop: [u8; 48],
+    sectors_per_block: u32,
+    block_size: u32,
+    server: String,
+}
 
-    (payer, server, mediator, program_context)
+impl ProgramContextSnapshot {
+    fn new(address: &Pubkey, program_context: &ProgramContext) -> Self {
+        ProgramContextSnapshot {
+            address: address.to_string(),
+            is_subscription_ended: program_context.is_subscription_ended,
+            is_server_turn: program_context.is_server_turn,
+            subscription_duration: program_context.subscription_duration,
+            mediator_balance: program_context.mediator_balance,
+            g_norm: program_context.g_norm,
+            v_norm: program_context.v_norm,
+            client: program_context.client.to_string(),
+            file_version: program_context.file_version,
+            block_count: program_context.block_count,
+            file_state_root: program_context.file_state_root,
+            proof_kind: match program_context.proof_kind {
+                ProofKind::Bls => "bls",
+                ProofKind::Merkle => "merkle",
+            },
+            challenge_seed: program_context.challenge_seed,
+            challenge_size: program_context.challenge_size,
+            is_challenge_pending: program_context.is_challenge_pending,
+            last_proof_digest: program_context.last_proof_digest,
+            proof_count: program_context.proof_count,
+            pop: program_context.pop,
+            sectors_per_block: program_context.sectors_per_block,
+            block_size: program_context.block_size,
+            server: program_context.server.to_string(),
+        }
+    }
 }
 
-// Function to request airdrop for a given public key
-async fn request_airdrop(connection: &RpcClient, payer_pubkey: &Pubkey, amount: u64) -> Signature {
-    println!("\nRequesting {} SOL airdrop to payer", amount);
-    let airdrop_signature = connection.request_airdrop(payer_pubkey, amount).unwrap();
-    airdrop_signature
+// A confirmed instruction of the program
+struct ConfirmedInstruction {
+    signature: Signature,
+    slot: u64,
+    accounts: Vec<Pubkey>,  // Accounts the instruction may write to
 }
 
-// Function to confirm transaction completion
-async fn confirm_airdrop(connection: &RpcClient, airdrop_signature: &Signature) {
-    while !connection.confirm_transaction(airdrop_signature).unwrap() {
-        std::thread::sleep(std::time::Duration::from_millis(100));
+// Response of every instruction endpoint: the confirmed transaction and the accounts it left behind
+#[derive(Serialize, Debug, ToSchema)]
+struct InstructionResponse {
+    signature: String,
+    slot: u64,                        // Slot in which the transaction was processed
+    accounts: Vec<String>,            // Writable accounts of the instruction
+    program_contexts: Vec<ProgramContextSnapshot>,
+    mediator_lamports: Option<u64>,   // Balance of the mediator account, when the instruction uses one
+}
+
+// Reads back the accounts touched by a confirmed instruction
+async fn instruction_response(state: &AppState, confirmed: ConfirmedInstruction, program_contexts: &[Pubkey], mediator: Option<Pubkey>) -> Result<Json, Rejection> {
+    let rpc = &state.rpc;
+
+    let mut snapshots = Vec::with_capacity(program_contexts.len());
+    for program_context in program_contexts {
+        let account = transactions::fetch_program_context(rpc, &state.config.cluster.program_id, program_context)
+            .await
+            .map_err(api::reject)?;
+        snapshots.push(ProgramContextSnapshot::new(program_context, &account));
     }
-    println!("   Airdrop confirmed!");
+
+    let mediator_lamports = match mediator {
+        Some(mediator) => Some(
+            rpc.get_balance(&mediator)
+                .await
+                .map_err(api::reject)?,
+        ),
+        None => None,
+    };
+
+    Ok(warp::reply::json(&InstructionResponse {
+        signature: confirmed.signature.to_string(),
+        slot: confirmed.slot,
+        accounts: confirmed.accounts.iter().map(Pubkey::to_string).collect(),
+        program_contexts: snapshots,
+        mediator_lamports,
+    }))
 }
 
-// Function to create a program client
-fn create_program_client(payer: Arc<Keypair>) -> Client<Arc<Keypair>> {
-    Client::new_with_options(
-        Cluster::Localnet,
-        Arc::clone(&payer),
-        CommitmentConfig::confirmed(),
-    )
+// Signs and sends one instruction of the program, and waits for its confirmation
+async fn send_program_instruction(state: &AppState, name: &str, payer: &dyn TransactionSigner, instruction: Instruction, signers: &[&dyn TransactionSigner]) -> Result<ConfirmedInstruction, Rejection> {
+    let signature = transactions::send_signed_instructions(&state.rpc, payer, std::slice::from_ref(&instruction), signers, &state.config.retry)
+        .await
+        .map_err(api::reject)?;
+    let slot = transactions::transaction_slot(&state.rpc, &signature)
+        .await
+        .map_err(api::reject)?;
+
+    println!("   {} Instruction Confirmed: {} (slot {})", name, signature, slot);
+    Ok(ConfirmedInstruction {
+        signature,
+        slot,
+        accounts: instruction
+            .accounts
+            .iter()
+            .filter(|account| account.is_writable)
+            .map(|account| account.pubkey)
+            .collect(),
+    })
 }
 
-async fn initialize_instruction(program: &Program<Rc<Keypair>>, mediator: Rc<Keypair>, program_context: Rc<Keypair>) -> Result<Instruction, ClientError> {
-    let initialize_ix = program
-        .request()
-        .accounts(accounts::Initialize {
-            payer: program.payer(),
+async fn initialize_instruction_endpoint(state: &AppState, payer: &dyn TransactionSigner, mediator: &dyn TransactionSigner, program_context: &dyn TransactionSigner) -> Result<ConfirmedInstruction, Rejection> {
+    let initialize_ix = transactions::program_instruction(
+        &state.config.cluster.program_id,
+        accounts::Initialize {
+            payer: payer.pubkey(),
             mediator: mediator.pubkey(),
             program_context: program_context.pubkey(),
             system_program: system_program::ID,
-        })
-        .args(ix::Initialize)
-        .instructions()?
-        .remove(0);
+        },
+        ix::Initialize,
+    );
 
-    Ok(initialize_ix)
+    // The new mediator and program context accounts sign their own creation
+    send_program_instruction(state, "Initialize", payer, initialize_ix, &[mediator, program_context]).await
 }
 
-async fn set_client_curve_points_instruction(program: &Program<Rc<Keypair>>, mediator: Rc<Keypair>, program_context: Rc<Keypair>) -> Result<Instruction, ClientError> {
-    let g_norm: [u8; 96] = [1; 96]; // Example array, replace with actual data
-    let v_norm: [u8; 96] = [1; 96]; // Example array, replace with actual data
+// Endpoint for set client curve points instruction
+async fn set_client_curve_points_instruction_endpoint(state: &AppState, payer: &dyn TransactionSigner, mediator: Pubkey, program_context: Pubkey, server: Pubkey, curve_points: &ClientCurvePoints) -> Result<ConfirmedInstruction, Rejection> {
+    // Reject invalid keys before paying for the transaction
+    curve_points
+        .validate()
+        .map_err(api::reject)?;
+
+    let set_client_curve_points_ix = transactions::program_instruction(
+        &state.config.cluster.program_id,
+        accounts::SetClientCurvePoints {
+            payer: payer.pubkey(),
+            mediator,
+            program_context,
+            system_program: system_program::ID,
+        },
+        ix::SetClientCurvePoints {
+            g_norm: curve_points.g_norm,
+            v_norm: curve_points.v_norm,
+            pop: curve_points.pop,
+            sectors_per_block: curve_points.sectors_per_block,
+            block_size: curve_points.block_size,
+            server,
+        },
+    );
+
+    send_program_instruction(state, "Set Client Curve Points", payer, set_client_curve_points_ix, &[]).await
+}
 
-    let set_client_curve_points_ix = program
-        .request()
-        .accounts(accounts::SetClientCurvePoints {
-            payer: program.payer(),
-            mediator: mediator.pubkey(),
-            program_context: program_context.pubkey(),
+// Endpoint for set merkle commitment instruction
+async fn set_merkle_commitment_instruction_endpoint(state: &AppState, payer: &dyn TransactionSigner, mediator: Pubkey, program_context: Pubkey, server: Pubkey, block_count: u64, merkle_root: [u8; 32]) -> Result<ConfirmedInstruction, Rejection> {
+    let set_merkle_commitment_ix = transactions::program_instruction(
+        &state.config.cluster.program_id,
+        accounts::SetMerkleCommitment {
+            payer: payer.pubkey(),
+            mediator,
+            program_context,
             system_program: system_program::ID,
-        })
-        .args(ix::SetClientCurvePoints {
-            g_norm,
-            v_norm,
-        })
-        .instructions()?
-        .remove(0);
+        },
+        ix::SetMerkleCommitment { block_count, merkle_root, server },
+    );
 
-    Ok(set_client_curve_points_ix)
+    send_program_instruction(state, "Set Merkle Commitment", payer, set_merkle_commitment_ix, &[]).await
 }
 
-async fn end_subscription_instruction(program: &Program<Rc<Keypair>>, program_context: Rc<Keypair>) -> Result<Instruction, ClientError> {
-    let end_subscription_ix = program
-        .request()
-        .accounts(accounts::EndSubscription {
-            program_context: program_context.pubkey(),
-        })
-        .args(ix::EndSubscription)
-        .instructions()?
-        .remove(0);
+// Endpoint for issue merkle challenge instruction
+async fn issue_merkle_challenge_instruction_endpoint(state: &AppState, payer: &dyn TransactionSigner, program_context: Pubkey, challenge_seed: [u8; 32], challenge_size: u8) -> Result<ConfirmedInstruction, Rejection> {
+    let issue_merkle_challenge_ix = transactions::program_instruction(
+        &state.config.cluster.program_id,
+        accounts::IssueMerkleChallenge {
+            payer: payer.pubkey(),
+            program_context,
+        },
+        ix::IssueMerkleChallenge { challenge_seed, challenge_size },
+    );
 
-    Ok(end_subscription_ix)
+    send_program_instruction(state, "Issue Merkle Challenge", payer, issue_merkle_challenge_ix, &[]).await
 }
 
-// Main function to send all instructions
-async fn send_instructions(program: &Program<Rc<Keypair>>, mediator: Rc<Keypair>, program_context: Rc<Keypair>) -> Result<Signature, ClientError> {
-    // Build the instructions
-    let initialize_ix = initialize_instruction(program, mediator.clone(), program_context.clone()).await?;
-    let set_client_curve_points_ix = set_client_curve_points_instruction(program, mediator.clone(), program_context.clone()).await?;
-    let end_subscription_ix = end_subscription_instruction(program, program_context.clone()).await?;
-
-    // Send the transaction with the instructions
-    let signature = program
-        .request()
-        .instruction(initialize_ix)
-        .instruction(set_client_curve_points_ix)
-        .instruction(end_subscription_ix)
-        .signer(&mediator)
-        .signer(&program_context)
-        .send()
-        .await?;
+// Endpoint for submit merkle proof instruction
+async fn submit_merkle_proof_instruction_endpoint(state: &AppState, server: &dyn TransactionSigner, program_context: Pubkey, proofs: Vec<MerkleLeafProof>) -> Result<ConfirmedInstruction, Rejection> {
+    let submit_merkle_proof_ix = transactions::submit_merkle_proof_instruction(&state.config.cluster.program_id, &server.pubkey(), &program_context, proofs);
 
-    println!("   Transaction confirmed: {}", signature);
-    Ok(signature)
+    send_program_instruction(state, "Submit Merkle Proof", server, submit_merkle_proof_ix, &[]).await
 }
 
-// Function to fetch and print account data
-async fn fetch_account_data(program: &Program<Rc<Keypair>>, program_context: Rc<Keypair>, mediator: Rc<Keypair>) {
-    let program_context_account: solana_smart_contract::ProgramContext = program.account::<solana_smart_contract::ProgramContext>(program_context.pubkey()).await.unwrap();
-    let mediator_account: solana_smart_contract::Mediator = program.account::<solana_smart_contract::Mediator>(mediator.pubkey()).await.unwrap();
+// Endpoint for submit aggregated proof instruction
+async fn submit_aggregated_proof_instruction_endpoint(state: &AppState, server: &dyn TransactionSigner, program_contexts: &[Pubkey], proof: &Proof) -> Result<ConfirmedInstruction, Rejection> {
+    let submit_aggregated_proof_ix = transactions::submit_aggregated_proof_instruction(&state.config.cluster.program_id, &server.pubkey(), program_contexts, proof);
 
-    println!("   Counter value: {}", program_context_account.mediator_balance);
-    println!("   Counter value: {}", mediator_account.balance);
+    send_program_instruction(state, "Submit Aggregated Proof", server, submit_aggregated_proof_ix, &[]).await
 }
 
-async fn initialize_instruction_endpoint(program: &Program<Arc<Keypair>>, payer: Arc<Keypair>, mediator: Arc<Keypair>, program_context: Arc<Keypair>) -> Result<&str, Rejection> {
-    let initialize_ix = program
-        .request()
-        .accounts(accounts::Initialize {
+// Endpoint for extend subscription instruction
+async fn extend_subscription_instruction_endpoint(state: &AppState, payer: &dyn TransactionSigner, mediator: Pubkey, program_context: Pubkey) -> Result<ConfirmedInstruction, Rejection> {
+    let extend_subscription_ix = transactions::program_instruction(
+        &state.config.cluster.program_id,
+        accounts::ExtendSubscription {
             payer: payer.pubkey(),
-            mediator: mediator.pubkey(),
-            program_context: program_context.pubkey(),
+            mediator,
+            program_context,
             system_program: system_program::ID,
-        })
-        .args(ix::Initialize)
-        .instructions()
-        .map_err(|err| reject::custom(ClientRejection(err.to_string())))?
-        .remove(0);
-
-    let signature = program
-        .request()
-        .instruction(initialize_ix)
-        .signer(&payer)
-        .signer(&mediator)
-        .signer(&program_context)
-        .send()
-        .await
-        .map_err(|err| reject::custom(ClientRejection(err.to_string())))?;
+        },
+        ix::ExtendSubscription,
+    );
 
-    println!("   Initialize Instruction Confirmed: {}", signature);
-    Ok("signature")
+    send_program_instruction(state, "Extend Subscription", payer, extend_subscription_ix, &[]).await
 }
 
-// Endpoint for set client curve points instruction
-async fn set_client_curve_points_instruction_endpoint(program: &Program<Arc<Keypair>>, payer: Arc<Keypair>, mediator: Arc<Keypair>, program_context: Arc<Keypair>) -> Result<Signature, Rejection> {
-    let g_norm: [u8; 96] = [1; 96]; // Example array, replace with actual data
-    let v_norm: [u8; 96] = [1; 96]; // Example array, replace with actual data
-
-    let set_client_curve_points_ix = program
-        .request()
-        .accounts(accounts::SetClientCurvePoints {
+// Endpoint for update file state instruction
+async fn update_file_state_instruction_endpoint(state: &AppState, payer: &dyn TransactionSigner, program_context: Pubkey, file_version: u64, block_count: u64, file_state_root: [u8; 32]) -> Result<ConfirmedInstruction, Rejection> {
+    let update_file_state_ix = transactions::program_instruction(
+        &state.config.cluster.program_id,
+        accounts::UpdateFileState {
             payer: payer.pubkey(),
-            mediator: mediator.pubkey(),
-            program_context: program_context.pubkey(),
-            system_program: system_program::ID,
-        })
-        .args(ix::SetClientCurvePoints {
-            g_norm,
-            v_norm,
-        })
-        .instructions()
-        .map_err(|err| reject::custom(ClientRejection(err.to_string())))?
-        .remove(0);
-
-    let signature = program
-        .request()
-        .instruction(set_client_curve_points_ix)
-        .signer(&payer)
-        .signer(&mediator)
-        .signer(&program_context)
-        .send()
-        .await
-        .map_err(|err| reject::custom(ClientRejection(err.to_string())))?;
+            program_context,
+        },
+        ix::UpdateFileState { file_version, block_count, file_state_root },
+    );
 
-    println!("   Set Client Curve Points Instruction Confirmed: {}", signature);
-    Ok(signature)
+    send_program_instruction(state, "Update File State", payer, update_file_state_ix, &[]).await
 }
 
-// Endpoint for set client curve points instruction
-async fn end_subscription_instruction_endpoint(program: &Program<Arc<Keypair>>, payer: Arc<Keypair>, mediator: Arc<Keypair>, program_context: Arc<Keypair>) -> Result<Signature, Rejection> {
-    let end_subscription_ix = program
-        .request()
-        .accounts(accounts::EndSubscription {
-            program_context: program_context.pubkey(),
-        })
-        .args(ix::EndSubscription)
-        .instructions()
-        .map_err(|err| reject::custom(ClientRejection(err.to_string())))?
-        .remove(0);
-
-    let signature = program
-        .request()
-        .instruction(end_subscription_ix)
-        .signer(&payer)
-        .signer(&mediator)
-        .signer(&program_context)
-        .send()
-        .await
-        .map_err(|err| reject::custom(ClientRejection(err.to_string())))?;
-
-    println!("   Set Client Curve Points Instruction Confirmed: {}", signature);
-    Ok(signature)
-}
-
-// #[tokio::main]
-// async fn main() -> Result<(), ClientError> { // Corrected return type
-//     // Initialize connection and keypairs
-//     let connection = RpcClient::new_with_commitment("http://127.0.0.1:8899", CommitmentConfig::confirmed());
-//     let (payer, server, mediator, program_context) = generate_keypairs();
-//
-//     // Request airdrop to payer
-//     let airdrop_signature = request_airdrop(&connection, &payer.pubkey(), 10 * LAMPORTS_PER_SOL).await;
-//     confirm_airdrop(&connection, &airdrop_signature).await;
-//
-//     // Create program client
-//     let provider = create_program_client(Arc::clone(&payer));
-//     let program = provider.program(solana_smart_contract::ID)?;
-//
-//     initialize_instruction_endpoint(&program, payer, mediator, program_context).await;
-//
-//     // let provider2 = create_program_client(Rc::clone(&payer));
-//     // let program2 = provider2.program(solana_smart_contract::ID)?;
-//     // set_client_curve_points_instruction_endpoint(&program2, Rc::clone(&payer), Rc::clone(&mediator), Rc::clone(&program_context)).await;
-//
-//     // Send transaction instructions
-//     // let _signature = send_instructions(&program, Rc::clone(&mediator), Rc::clone(&program_context)).await;
-//
-//     // Fetch and display account data
-//     // fetch_account_data(&program, Rc::clone(&program_context), Rc::clone(&mediator)).await;
-//
-//     // Request airdrop to server
-//     request_airdrop(&connection, &server.pubkey(), 10 * LAMPORTS_PER_SOL).await;
-//
-//     Ok(())
-// }
+// Endpoint for end subscription instruction
+async fn end_subscription_instruction_endpoint(state: &AppState, payer: &dyn TransactionSigner, program_context: Pubkey) -> Result<ConfirmedInstruction, Rejection> {
+    let end_subscription_ix = transactions::program_instruction(
+        &state.config.cluster.program_id,
+        accounts::EndSubscription { program_context },
+        ix::EndSubscription,
+    );
 
+    send_program_instruction(state, "End Subscription", payer, end_subscription_ix, &[]).await
+}
 
+// Endpoint for retrieve instruction
+async fn retrieve_instruction_endpoint(state: &AppState, server: &dyn TransactionSigner, mediator: Pubkey, program_context: Pubkey) -> Result<ConfirmedInstruction, Rejection> {
+    let retrieve_ix = transactions::program_instruction(
+        &state.config.cluster.program_id,
+        accounts::Retrieve {
+            server: server.pubkey(),
+            mediator,
+            program_context,
+            system_program: system_program::ID,
+        },
+        ix::Retrieve,
+    );
+
+    send_program_instruction(state, "Retrieve", server, retrieve_ix, &[]).await
+}
 
 // Define a struct to handle the incoming request body (amount of SOL)
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 struct AirdropRequest {
-    #[serde(with = "hex_array_64")]
-    payer_keypair_as_hex: [u8; 64], // Serialized keypair (private + public key)
+    recipient: String, // Keystore id or public key of the account to fund
     amount_sol: u64,  // Amount of SOL to airdrop
 }
 
 // Define a struct for the response, if needed
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct AirdropResponse {
-    message: String
+    message: String,
+    signature: String,
 }
 
-async fn airdrop_handler(payload: AirdropRequest) -> Result<Json, Rejection> {
-    let payer = deserialize_keypair_from_bytes(&payload.payer_keypair_as_hex);
-    println!("Serialized keypair bytes: {:?}", payer.pubkey());
-
-    println!("\nRequesting {} SOL airdrop to payer", payload.amount_sol);
+#[utoipa::path(
+    post,
+    path = "/airdrop",
+    request_body = AirdropRequest,
+    responses(
+        (status = 200, body = AirdropResponse, description = "The confirmed airdrop"),
+        ErrorResponses,
+    )
+)]
+async fn airdrop_handler(payload: AirdropRequest, state: SharedState) -> Result<Json, Rejection> {
+    let recipient = find_account(&state.keystore, &payload.recipient)?;
 
-    let connection = RpcClient::new_with_commitment("http://127.0.0.1:8899", CommitmentConfig::confirmed());
+    println!("\nRequesting {} SOL airdrop to {}", payload.amount_sol, recipient);
 
     // Request airdrop to payer
-    let airdrop_signature = request_airdrop(&connection, &payer.pubkey(), payload.amount_sol * LAMPORTS_PER_SOL).await;
-    confirm_airdrop(&connection, &airdrop_signature).await;
+    let lamports = payload
+        .amount_sol
+        .checked_mul(LAMPORTS_PER_SOL)
+        .ok_or_else(|| api::reject(ApiError::InvalidInput("Airdrop amount too large".to_string())))?;
+    let airdrop_signature = request_airdrop(&state.rpc, &recipient, lamports).await.map_err(api::reject)?;
+    transactions::confirm_signature(&state.rpc, &airdrop_signature, None, &state.config.retry)
+        .await
+        .map_err(api::reject)?;
 
     println!("   Airdrop confirmed!");
 
     // Return a JSON response with the key and message
     Ok(warp::reply::json(&AirdropResponse {
         message: format!("Airdrop of {} SOL confirmed", payload.amount_sol),
+        signature: airdrop_signature.to_string(),
     }))
 }
 
-// Define a struct to handle the incoming request body (amount of SOL)
-#[derive(Serialize, Deserialize, Debug)]
+// Define a struct to handle the incoming request body
+#[derive(Deserialize, Debug, ToSchema)]
+struct CreateSignerRequest {
+    id: String, // Keystore id of the new keypair
+}
+
+#[derive(Serialize, ToSchema)]
+struct SignerResponse {
+    id: String,
+    pubkey: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/signers",
+    responses(
+        (status = 200, body = Vec<SignerResponse>, description = "Signers of the keystore"),
+        ErrorResponses,
+    )
+)]
+async fn list_signers_handler(state: SharedState) -> Result<Json, Rejection> {
+    let keystore = state.keystore.read().map_err(|_| api::reject(ApiError::Internal("Keystore unavailable".to_string())))?;
+    let signers: Vec<SignerResponse> = keystore
+        .list()
+        .into_iter()
+        .map(|(id, pubkey)| SignerResponse { id, pubkey: pubkey.to_string() })
+        .collect();
+
+    Ok(warp::reply::json(&signers))
+}
+
+// Generates a keypair in the keystore; only its public key leaves the server
+#[utoipa::path(
+    post,
+    path = "/signers",
+    request_body = CreateSignerRequest,
+    responses(
+        (status = 200, body = SignerResponse, description = "The generated signer"),
+        ErrorResponses,
+    )
+)]
+async fn create_signer_handler(payload: CreateSignerRequest, state: SharedState) -> Result<Json, Rejection> {
+    // The keypair file is written without holding the keystore, which every request reads
+    let path = state
+        .keystore
+        .read()
+        .map_err(|_| api::reject(ApiError::Internal("Keystore unavailable".to_string())))?
+        .new_keypair_path(&payload.id)
+        .map_err(api::reject)?;
+    let id = payload.id.clone();
+    let keypair = tokio::task::spawn_blocking(move || signer::create_keypair_file(&id, &path))
+        .await
+        .map_err(|err| api::reject(ApiError::Internal(err.to_string())))?
+        .map_err(api::reject)?;
+    let pubkey = state
+        .keystore
+        .write()
+        .map_err(|_| api::reject(ApiError::Internal("Keystore unavailable".to_string())))?
+        .insert_generated(&payload.id, keypair)
+        .map_err(api::reject)?;
+
+    println!("Generated signer {}: {}", payload.id, pubkey);
+    Ok(warp::reply::json(&SignerResponse {
+        id: payload.id,
+        pubkey: pubkey.to_string(),
+    }))
+}
+
+// Define a struct to handle the incoming request body; signers are keystore ids or public keys
+#[derive(Deserialize, Debug, ToSchema)]
 struct InitializeSubscriptionRequest {
-    #[serde(with = "hex_array_64")]
-    payer_keypair_as_hex: [u8; 64], // Serialized keypair (private + public key)
-    #[serde(with = "hex_array_64")]
-    mediator_keypair_as_hex: [u8; 64], // Serialized keypair (private + public key)
-    #[serde(with = "hex_array_64")]
-    program_context_keypair_as_hex: [u8; 64], // Serialized keypair (private + public key)
+    payer: String,            // Client paying for the accounts
+    mediator: String,         // New mediator account, signs its creation
+    program_context: String,  // New program context account, signs its creation
 }
 
-// Define a struct for the response, if needed
-#[derive(Serialize)]
-struct InitializeSubscriptionResponse {
-    message: String
+#[utoipa::path(
+    post,
+    path = "/initializeSubscription",
+    request_body = InitializeSubscriptionRequest,
+    responses(
+        (status = 200, body = InstructionResponse, description = "The confirmed transaction and the accounts it left behind"),
+        ErrorResponses,
+    )
+)]
+async fn initialize_subscription_handler(payload: InitializeSubscriptionRequest, state: SharedState) -> Result<Json, Rejection> {
+    let payer = find_signer(&state.keystore, &payload.payer)?;
+    let mediator = find_signer(&state.keystore, &payload.mediator)?;
+    let program_context = find_signer(&state.keystore, &payload.program_context)?;
+
+    let confirmed = initialize_instruction_endpoint(&state, payer.as_ref(), mediator.as_ref(), program_context.as_ref()).await?;
+    instruction_response(&state, confirmed, &[program_context.pubkey()], Some(mediator.pubkey())).await
 }
 
-async fn initialize_subscription_handler(payload: InitializeSubscriptionRequest) -> Result<Json, Rejection> {
-    let payer = Arc::new(deserialize_keypair_from_bytes(&payload.payer_keypair_as_hex));
-    let mediator = Arc::new(deserialize_keypair_from_bytes(&payload.mediator_keypair_as_hex));
-    let program_context = Arc::new(deserialize_keypair_from_bytes(&payload.program_context_keypair_as_hex));
+// PoR public key of a subscription, as stored in its program context
+#[derive(Deserialize, Debug, ToSchema)]
+struct ClientCurvePoints {
+    #[serde(with = "client::wire::hex_array")]
+    #[schema(value_type = String)]
+    g_norm: [u8; 96],         // Generator g, compressed G2
+    #[serde(with = "client::wire::hex_array")]
+    #[schema(value_type = String)]
+    v_norm: [u8; 96],         // Public key v = g^alpha, compressed G2
+    #[serde(with = "client::wire::hex_array")]
+    #[schema(value_type = String)]
+    pop: [u8; 48],            // Proof of possession of alpha, compressed G1
+    sectors_per_block: u32,
+    block_size: u32,
+}
 
-    let payer_clone = Arc::clone(&payer);
+impl ClientCurvePoints {
+    /// Public key of a PoR key pair, with a fresh proof of possession.
+    fn from_por_keys(por_keys: &PorKeyPair) -> Self {
+        ClientCurvePoints {
+            g_norm: por_keys.public.g.to_compressed(),
+            v_norm: por_keys.public.v.to_compressed(),
+            pop: prove_possession(por_keys).to_compressed(),
+            sectors_per_block: por_keys.public.sectors_per_block() as u32,
+            block_size: por_keys.public.block_size() as u32,
+        }
+    }
 
-    // Create program client
-    let provider = create_program_client(payer);
+    /// Checks the points decompress, the proof of possession and the block layout.
+    fn validate(&self) -> Result<(), ApiError> {
+        let g = Option::<G2Affine>::from(G2Affine::from_compressed(&self.g_norm))
+            .ok_or_else(|| ApiError::InvalidCurvePoint("Invalid g point".to_string()))?;
+        let v = Option::<G2Affine>::from(G2Affine::from_compressed(&self.v_norm))
+            .ok_or_else(|| ApiError::InvalidCurvePoint("Invalid v point".to_string()))?;
+        if bool::from(g.is_identity()) || bool::from(v.is_identity()) {
+            return Err(ApiError::InvalidCurvePoint("The g and v points must not be the identity".to_string()));
+        }
 
-    let program = provider
-        .program(solana_smart_contract::ID)
-        .map_err(|err| reject::custom(ClientRejection(err.to_string())))?;  // Convert ClientError to warp::Rejection
+        if !verify_possession_compressed(&self.g_norm, &self.v_norm, &self.pop) {
+            return Err(ApiError::InvalidCurvePoint(
+                "Invalid proof of possession for the client curve points".to_string(),
+            ));
+        }
 
-    initialize_instruction_endpoint(&program, payer_clone, mediator, program_context).await;
+        if !is_valid_por_params(self.sectors_per_block, self.block_size) {
+            return Err(ApiError::InvalidInput(format!(
+                "Invalid block layout: {} sectors per block of {} bytes",
+                self.sectors_per_block, self.block_size
+            )));
+        }
 
-    // Return a JSON response with the key and message
-    Ok(warp::reply::json(&AirdropResponse {
-        message: format!("Airdrop of {} SOL confirmed", 5),
-    }))
+        Ok(())
+    }
 }
 
-// Define a struct to handle the incoming request body (amount of SOL)
-#[derive(Serialize, Deserialize, Debug)]
+// Define a struct to handle the incoming request body; signers are keystore ids or public keys
+#[derive(Deserialize, Debug, ToSchema)]
 struct SetClientCurvePointsRequest {
-    #[serde(with = "hex_array_64")]
-    payer_keypair_as_hex: [u8; 64], // Serialized keypair (private + public key)
-    #[serde(with = "hex_array_64")]
-    mediator_keypair_as_hex: [u8; 64], // Serialized keypair (private + public key)
-    #[serde(with = "hex_array_64")]
-    program_context_keypair_as_hex: [u8; 64], // Serialized keypair (private + public key)
+    payer: String,            // Client registering its curve points
+    mediator: String,         // Mediator account of the subscription
+    program_context: String,  // Program context account of the subscription
+    server: String,           // Server storing the file, the only one allowed to prove and be paid
+    #[serde(default)]
+    curve_points: Option<ClientCurvePoints>,  // The server's PoR key when omitted
 }
 
-// Define a struct for the response, if needed
-#[derive(Serialize)]
-struct SetClientCurvePointsResponse {
-    message: String
+#[utoipa::path(
+    post,
+    path = "/setClientCurvePoints",
+    request_body = SetClientCurvePointsRequest,
+    responses(
+        (status = 200, body = InstructionResponse, description = "The confirmed transaction and the accounts it left behind"),
+        ErrorResponses,
+    )
+)]
+async fn set_client_curve_points_handler(payload: SetClientCurvePointsRequest, state: SharedState, por_keys: Arc<PorKeyPair>) -> Result<Json, Rejection> {
+    let payer = find_signer(&state.keystore, &payload.payer)?;
+    let mediator = find_account(&state.keystore, &payload.mediator)?;
+    let program_context = find_account(&state.keystore, &payload.program_context)?;
+    let server = find_account(&state.keystore, &payload.server)?;
+    let curve_points = payload
+        .curve_points
+        .unwrap_or_else(|| ClientCurvePoints::from_por_keys(&por_keys));
+
+    let confirmed = set_client_curve_points_instruction_endpoint(&state, payer.as_ref(), mediator, program_context, server, &curve_points).await?;
+    instruction_response(&state, confirmed, &[program_context], Some(mediator)).await
 }
 
-async fn set_client_curve_points_handler(payload: SetClientCurvePointsRequest) -> Result<Json, Rejection> {
-    let payer = Arc::new(deserialize_keypair_from_bytes(&payload.payer_keypair_as_hex));
-    let mediator = Arc::new(deserialize_keypair_from_bytes(&payload.mediator_keypair_as_hex));
-    let program_context = Arc::new(deserialize_keypair_from_bytes(&payload.program_context_keypair_as_hex));
+// Define a struct to handle the incoming request body; signers are keystore ids or public keys
+#[derive(Deserialize, Debug, ToSchema)]
+struct SetMerkleCommitmentRequest {
+    payer: String,            // Client committing to its file
+    mediator: String,         // Mediator account of the subscription
+    program_context: String,  // Program context account of the subscription
+    server: String,           // Server storing the file, the only one allowed to prove and be paid
+    block_count: u64,         // Number of MERKLE_LEAF_SIZE-byte leaves of the file
+    #[serde(with = "client::wire::hex_array")]
+    #[schema(value_type = String)]
+    merkle_root: [u8; 32],    // Root of the Merkle tree over the leaf hashes
+}
 
-    let payer_clone = Arc::clone(&payer);
+#[utoipa::path(
+    post,
+    path = "/setMerkleCommitment",
+    request_body = SetMerkleCommitmentRequest,
+    responses(
+        (status = 200, body = InstructionResponse, description = "The confirmed transaction and the accounts it left behind"),
+        ErrorResponses,
+    )
+)]
+async fn set_merkle_commitment_handler(payload: SetMerkleCommitmentRequest, state: SharedState) -> Result<Json, Rejection> {
+    let payer = find_signer(&state.keystore, &payload.payer)?;
+    let mediator = find_account(&state.keystore, &payload.mediator)?;
+    let program_context = find_account(&state.keystore, &payload.program_context)?;
+    let server = find_account(&state.keystore, &payload.server)?;
+
+    let confirmed = set_merkle_commitment_instruction_endpoint(&state, payer.as_ref(), mediator, program_context, server, payload.block_count, payload.merkle_root).await?;
+    instruction_response(&state, confirmed, &[program_context], Some(mediator)).await
+}
 
-    // Create program client
-    let provider = create_program_client(payer);
+// Define a struct to handle the incoming request body; signers are keystore ids or public keys
+#[derive(Deserialize, Debug, ToSchema)]
+struct IssueMerkleChallengeRequest {
+    payer: String,            // Client of the subscription
+    program_context: String,  // Program context account of the subscription
+    #[serde(default, with = "optional_hex_array")]
+    #[schema(value_type = Option<String>)]
+    challenge_seed: Option<[u8; 32]>,  // Random when omitted
+    challenge_size: u8,       // Number of challenged blocks
+}
 
-    let program = provider
-        .program(solana_smart_contract::ID)
-        .map_err(|err| reject::custom(ClientRejection(err.to_string())))?;  // Convert ClientError to warp::Rejection
+// Serde adapter for optional hex arrays
+mod optional_hex_array {
+    use client::wire::HexArray;
+    use serde::{Deserialize, Deserializer};
 
-    set_client_curve_points_instruction_endpoint(&program, payer_clone, mediator, program_context).await;
+    pub fn deserialize<'de, D, const N: usize>(deserializer: D) -> Result<Option<[u8; N]>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Option::<HexArray<N>>::deserialize(deserializer)?.map(|array| array.0))
+    }
+}
 
-    // Return a JSON response with the key and message
-    Ok(warp::reply::json(&SetClientCurvePointsResponse {
-        message: format!("Airdrop of {} SOL confirmed", 5),
-    }))
+#[utoipa::path(
+    post,
+    path = "/issueMerkleChallenge",
+    request_body = IssueMerkleChallengeRequest,
+    responses(
+        (status = 200, body = InstructionResponse, description = "The confirmed transaction and the accounts it left behind"),
+        ErrorResponses,
+    )
+)]
+async fn issue_merkle_challenge_handler(payload: IssueMerkleChallengeRequest, state: SharedState) -> Result<Json, Rejection> {
+    let payer = find_signer(&state.keystore, &payload.payer)?;
+    let program_context = find_account(&state.keystore, &payload.program_context)?;
+    let challenge_seed = payload.challenge_seed.unwrap_or_else(random_seed);
+
+    let confirmed = issue_merkle_challenge_instruction_endpoint(&state, payer.as_ref(), program_context, challenge_seed, payload.challenge_size).await?;
+    instruction_response(&state, confirmed, &[program_context], None).await
 }
 
-// Define a struct to handle the incoming request body (amount of SOL)
-#[derive(Serialize, Deserialize, Debug)]
+// One challenged block with its authentication path
+#[derive(Deserialize, Debug, ToSchema)]
+struct MerkleLeafProofPayload {
+    block: String,              // Content of the block, as hex
+    #[schema(value_type = Vec<String>)]
+    path: Vec<HexArray<32>>,    // Sibling hashes from the leaf up to the root
+}
+
+// Define a struct to handle the incoming request body; signers are keystore ids or public keys
+#[derive(Deserialize, Debug, ToSchema)]
+struct SubmitMerkleProofRequest {
+    server: String,           // Storage server answering the challenge
+    program_context: String,  // Program context account of the subscription
+    proofs: Vec<MerkleLeafProofPayload>,  // One per challenged block, in challenge order
+}
+
+#[utoipa::path(
+    post,
+    path = "/submitMerkleProof",
+    request_body = SubmitMerkleProofRequest,
+    responses(
+        (status = 200, body = InstructionResponse, description = "The confirmed transaction and the accounts it left behind"),
+        ErrorResponses,
+    )
+)]
+async fn submit_merkle_proof_handler(payload: SubmitMerkleProofRequest, state: SharedState) -> Result<Json, Rejection> {
+    let server = find_signer(&state.keystore, &payload.server)?;
+    let program_context = find_account(&state.keystore, &payload.program_context)?;
+
+    let mut proofs = Vec::with_capacity(payload.proofs.len());
+    for proof in payload.proofs {
+        let block = hex::decode(&proof.block).map_err(|err| api::reject(ApiError::InvalidInput(format!("Invalid block: {}", err))))?;
+        proofs.push(MerkleLeafProof {
+            block,
+            path: proof.path.into_iter().map(|hash| hash.0).collect(),
+        });
+    }
+
+    let confirmed = submit_merkle_proof_instruction_endpoint(&state, server.as_ref(), program_context, proofs).await?;
+    instruction_response(&state, confirmed, &[program_context], None).await
+}
+
+// Define a struct to handle the incoming request body; signers are keystore ids or public keys
+#[derive(Deserialize, Debug, ToSchema)]
+struct SubmitAggregatedProofRequest {
+    server: String,                 // Storage server submitting the proof
+    program_contexts: Vec<String>,  // Program context accounts covered by the proof, all of the same client key
+    #[serde(with = "client::wire::hex_array")]
+    #[schema(value_type = String)]
+    sigma: [u8; 48],                // Aggregated tag, compressed G1
+    #[schema(value_type = Vec<String>)]
+    mu: Vec<HexArray<32>>,          // One scalar per sector, little-endian
+}
+
+#[utoipa::path(
+    post,
+    path = "/submitAggregatedProof",
+    request_body = SubmitAggregatedProofRequest,
+    responses(
+        (status = 200, body = InstructionResponse, description = "The confirmed transaction and the accounts it left behind"),
+        ErrorResponses,
+    )
+)]
+async fn submit_aggregated_proof_handler(payload: SubmitAggregatedProofRequest, state: SharedState) -> Result<Json, Rejection> {
+    let server = find_signer(&state.keystore, &payload.server)?;
+    let program_contexts = payload
+        .program_contexts
+        .iter()
+        .map(|program_context| find_account(&state.keystore, program_context))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Only submit proofs made of valid curve points and scalars
+    let wire = WireProof {
+        sigma: payload.sigma,
+        mu: payload.mu.into_iter().map(|scalar| scalar.0).collect(),
+    };
+    let proof = Proof::try_from(&wire).map_err(|err| api::reject(ApiError::InvalidCurvePoint(err.to_string())))?;
+
+    let confirmed = submit_aggregated_proof_instruction_endpoint(&state, server.as_ref(), &program_contexts, &proof).await?;
+    instruction_response(&state, confirmed, &program_contexts, None).await
+}
+
+// Define a struct to handle the incoming request body; signers are keystore ids or public keys
+#[derive(Deserialize, Debug, ToSchema)]
+struct ExtendSubscriptionRequest {
+    payer: String,            // Client paying the next period
+    mediator: String,         // Mediator account of the subscription
+    program_context: String,  // Program context account of the subscription
+}
+
+#[utoipa::path(
+    post,
+    path = "/extendSubscription",
+    request_body = ExtendSubscriptionRequest,
+    responses(
+        (status = 200, body = InstructionResponse, description = "The confirmed transaction and the accounts it left behind"),
+        ErrorResponses,
+    )
+)]
+async fn extend_subscription_handler(payload: ExtendSubscriptionRequest, state: SharedState) -> Result<Json, Rejection> {
+    let payer = find_signer(&state.keystore, &payload.payer)?;
+    let mediator = find_account(&state.keystore, &payload.mediator)?;
+    let program_context = find_account(&state.keystore, &payload.program_context)?;
+
+    let confirmed = extend_subscription_instruction_endpoint(&state, payer.as_ref(), mediator, program_context).await?;
+    instruction_response(&state, confirmed, &[program_context], Some(mediator)).await
+}
+
+// Define a struct to handle the incoming request body; signers are keystore ids or public keys
+#[derive(Deserialize, Debug, ToSchema)]
+struct UpdateFileStateRequest {
+    payer: String,            // Client of the subscription
+    program_context: String,  // Program context account of the subscription
+    file_version: u64,        // Must be the current version plus one
+    block_count: u64,         // Number of blocks of the updated file
+    #[serde(with = "client::wire::hex_array")]
+    #[schema(value_type = String)]
+    file_state_root: [u8; 32],
+}
+
+#[utoipa::path(
+    post,
+    path = "/updateFileState",
+    request_body = UpdateFileStateRequest,
+    responses(
+        (status = 200, body = InstructionResponse, description = "The confirmed transaction and the accounts it left behind"),
+        ErrorResponses,
+    )
+)]
+async fn update_file_state_handler(payload: UpdateFileStateRequest, state: SharedState) -> Result<Json, Rejection> {
+    let payer = find_signer(&state.keystore, &payload.payer)?;
+    let program_context = find_account(&state.keystore, &payload.program_context)?;
+
+    let confirmed = update_file_state_instruction_endpoint(
+        &state,
+        payer.as_ref(),
+        program_context,
+        payload.file_version,
+        payload.block_count,
+        payload.file_state_root,
+    )
+    .await?;
+    instruction_response(&state, confirmed, &[program_context], None).await
+}
+
+// Define a struct to handle the incoming request body; signers are keystore ids or public keys
+#[derive(Deserialize, Debug, ToSchema)]
 struct EndSubscriptionRequest {
-    #[serde(with = "hex_array_64")]
-    payer_keypair_as_hex: [u8; 64], // Serialized keypair (private + public key)
-    #[serde(with = "hex_array_64")]
-    mediator_keypair_as_hex: [u8; 64], // Serialized keypair (private + public key)
-    #[serde(with = "hex_array_64")]
-    program_context_keypair_as_hex: [u8; 64], // Serialized keypair (private + public key)
+    payer: String,            // Client ending its subscription
+    program_context: String,  // Program context account of the subscription
 }
 
-// Define a struct for the response, if needed
-#[derive(Serialize)]
-struct EndSubscriptionResponse {
-    message: String
+#[utoipa::path(
+    post,
+    path = "/endSubscription",
+    request_body = EndSubscriptionRequest,
+    responses(
+        (status = 200, body = InstructionResponse, description = "The confirmed transaction and the accounts it left behind"),
+        ErrorResponses,
+    )
+)]
+async fn end_subscription_handler(payload: EndSubscriptionRequest, state: SharedState) -> Result<Json, Rejection> {
+    let payer = find_signer(&state.keystore, &payload.payer)?;
+    let program_context = find_account(&state.keystore, &payload.program_context)?;
+
+    let confirmed = end_subscription_instruction_endpoint(&state, payer.as_ref(), program_context).await?;
+    instruction_response(&state, confirmed, &[program_context], None).await
 }
 
-async fn end_subscription_handler(payload: EndSubscriptionRequest) -> Result<Json, Rejection> {
-    let payer = Arc::new(deserialize_keypair_from_bytes(&payload.payer_keypair_as_hex));
-    let mediator = Arc::new(deserialize_keypair_from_bytes(&payload.mediator_keypair_as_hex));
-    let program_context = Arc::new(deserialize_keypair_from_bytes(&payload.program_context_keypair_as_hex));
+// Define a struct to handle the incoming request body; signers are keystore ids or public keys
+#[derive(Deserialize, Debug, ToSchema)]
+struct RetrieveRequest {
+    server: String,           // Storage server collecting its payment
+    mediator: String,         // Mediator account of the subscription
+    program_context: String,  // Program context account of the subscription
+}
 
-    let payer_clone = Arc::clone(&payer);
+#[utoipa::path(
+    post,
+    path = "/retrieve",
+    request_body = RetrieveRequest,
+    responses(
+        (status = 200, body = InstructionResponse, description = "The confirmed transaction and the accounts it left behind"),
+        ErrorResponses,
+    )
+)]
+async fn retrieve_handler(payload: RetrieveRequest, state: SharedState) -> Result<Json, Rejection> {
+    let server = find_signer(&state.keystore, &payload.server)?;
+    let mediator = find_account(&state.keystore, &payload.mediator)?;
+    let program_context = find_account(&state.keystore, &payload.program_context)?;
+
+    let confirmed = retrieve_instruction_endpoint(&state, server.as_ref(), mediator, program_context).await?;
+    instruction_response(&state, confirmed, &[program_context], Some(mediator)).await
+}
 
-    // Create program client
-    let provider = create_program_client(payer);
+// Balances of the mediator account of a subscription
+#[derive(Serialize, Debug, ToSchema)]
+struct MediatorSnapshot {
+    address: String,
+    balance: u64,      // Balance recorded in the account data
+    lamports: u64,     // Lamports actually held by the account
+}
 
-    let program = provider
-        .program(solana_smart_contract::ID)
-        .map_err(|err| reject::custom(ClientRejection(err.to_string())))?;  // Convert ClientError to warp::Rejection
+// Response of GET /subscriptions/{context}
+#[derive(Serialize, Debug, ToSchema)]
+struct SubscriptionResponse {
+    program_context: ProgramContextSnapshot,
+    program_context_lamports: u64,
+    mediator: Option<MediatorSnapshot>,  // None when the creation of the subscription is out of the node's history
+}
 
-    set_client_curve_points_instruction_endpoint(&program, payer_clone, mediator, program_context).await;
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SubscriptionQuery {
+    mediator: Option<String>,  // Skips looking the mediator up in the transaction history
+}
 
-    // Return a JSON response with the key and message
-    Ok(warp::reply::json(&EndSubscriptionResponse {
-        message: format!("Airdrop of {} SOL confirmed", 5),
+#[utoipa::path(
+    get,
+    path = "/subscriptions/{program_context}",
+    params(("program_context" = String, Path, description = "Keystore id or address of the program context"), SubscriptionQuery),
+    responses(
+        (status = 200, body = SubscriptionResponse, description = "The subscription and its balances"),
+        ErrorResponses,
+    )
+)]
+async fn get_subscription_handler(program_context: String, query: SubscriptionQuery, state: SharedState) -> Result<Json, Rejection> {
+    let program_context = find_account(&state.keystore, &program_context)?;
+    let program_id = &state.config.cluster.program_id;
+
+    let account = transactions::fetch_program_context(&state.rpc, program_id, &program_context)
+        .await
+        .map_err(api::reject)?;
+    let program_context_lamports = state.rpc.get_balance(&program_context).await.map_err(api::reject)?;
+
+    let mediator = match query.mediator {
+        Some(mediator) => Some(find_account(&state.keystore, &mediator)?),
+        None => query::find_mediator(&state.rpc, program_id, &program_context)
+            .await
+            .map_err(api::reject)?,
+    };
+    let mediator = match mediator {
+        Some(mediator) => {
+            let mediator_account = query::fetch_mediator(&state.rpc, program_id, &mediator)
+                .await
+                .map_err(api::reject)?;
+            Some(MediatorSnapshot {
+                address: mediator.to_string(),
+                balance: mediator_account.balance,
+                lamports: state.rpc.get_balance(&mediator).await.map_err(api::reject)?,
+            })
+        }
+        None => None,
+    };
+
+    Ok(warp::reply::json(&SubscriptionResponse {
+        program_context: ProgramContextSnapshot::new(&program_context, &account),
+        program_context_lamports,
+        mediator,
     }))
 }
 
-/// Serializes the `Keypair` to bytes (secret + public key).
-fn serialize_keypair(keypair: &Keypair) -> String {
-    hex::encode(keypair.to_bytes())
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SubscriptionsQuery {
+    client: Option<String>, // Client that registered the curve points
+    server: Option<String>, // Server storing the file, the only one allowed to prove and be paid
+}
+
+#[utoipa::path(
+    get,
+    path = "/subscriptions",
+    params(SubscriptionsQuery),
+    responses(
+        (status = 200, body = Vec<ProgramContextSnapshot>, description = "The matching subscriptions"),
+        ErrorResponses,
+    )
+)]
+async fn list_subscriptions_handler(query: SubscriptionsQuery, state: SharedState) -> Result<Json, Rejection> {
+    let program_id = &state.config.cluster.program_id;
+    let client = query.client.map(|client| find_account(&state.keystore, &client)).transpose()?;
+    let server = query.server.map(|server| find_account(&state.keystore, &server)).transpose()?;
+
+    let program_contexts = query::find_program_contexts(&state.rpc, program_id, client.as_ref(), server.as_ref())
+        .await
+        .map_err(api::reject)?;
+
+    let snapshots: Vec<ProgramContextSnapshot> = program_contexts
+        .iter()
+        .map(|(pubkey, program_context)| ProgramContextSnapshot::new(pubkey, program_context))
+        .collect();
+    Ok(warp::reply::json(&snapshots))
+}
+
+// One instruction of the program in a transaction
+#[derive(Serialize, Debug, ToSchema)]
+struct ProgramCallResponse {
+    #[schema(value_type = Option<String>)]
+    instruction: Option<&'static str>,  // None for instructions unknown to this build
+    accounts: Vec<String>,
+}
+
+// One transaction of the history of a subscription
+#[derive(Serialize, Debug, ToSchema)]
+struct HistoryEntryResponse {
+    signature: String,
+    slot: u64,
+    block_time: Option<i64>,
+    error: Option<String>,               // None when the transaction succeeded
+    calls: Vec<ProgramCallResponse>,
+}
+
+impl From<HistoryEntry> for HistoryEntryResponse {
+    fn from(entry: HistoryEntry) -> Self {
+        HistoryEntryResponse {
+            signature: entry.signature.to_string(),
+            slot: entry.slot,
+            block_time: entry.block_time,
+            error: entry.error,
+            calls: entry
+                .calls
+                .into_iter()
+                .map(|call| ProgramCallResponse {
+                    instruction: call.name,
+                    accounts: call.accounts.iter().map(Pubkey::to_string).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct HistoryQuery {
+    before: Option<String>,  // Signature to page from, exclusive
+    limit: Option<usize>,    // 100 by default, at most MAX_SIGNATURES
 }
 
-/// Deserializes the bytes back into a `Keypair`.
-fn deserialize_keypair_from_hex(hex_keypair: &str) -> Keypair {
-    let keypair_bytes = hex::decode(hex_keypair).expect("Invalid hex");
-    Keypair::from_bytes(&keypair_bytes).expect("Invalid keypair bytes")
+#[utoipa::path(
+    get,
+    path = "/subscriptions/{program_context}/history",
+    params(("program_context" = String, Path, description = "Keystore id or address of the program context"), HistoryQuery),
+    responses(
+        (status = 200, body = Vec<HistoryEntryResponse>, description = "Transactions of the subscription, newest first"),
+        ErrorResponses,
+    )
+)]
+async fn subscription_history_handler(program_context: String, query: HistoryQuery, state: SharedState) -> Result<Json, Rejection> {
+    let program_context = find_account(&state.keystore, &program_context)?;
+    let before = query
+        .before
+        .map(|before| Signature::from_str(&before))
+        .transpose()
+        .map_err(|err| api::reject(ApiError::InvalidInput(format!("Invalid signature: {}", err))))?;
+    let limit = query.limit.unwrap_or(100).clamp(1, MAX_SIGNATURES);
+
+    let history = query::history(&state.rpc, &state.config.cluster.program_id, &program_context, before, limit)
+        .await
+        .map_err(api::reject)?;
+    let entries: Vec<HistoryEntryResponse> = history.into_iter().map(HistoryEntryResponse::from).collect();
+    Ok(warp::reply::json(&entries))
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct EventsQuery {
+    program_context: Option<String>,  // Comma separated; every subscription when unset
+}
+
+// Number of events a slow stream missed
+#[derive(Serialize, Debug, ToSchema)]
+struct LaggedEvent {
+    missed: u64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/events",
+    params(EventsQuery),
+    responses(
+        (status = 200, body = SubscriptionEvent, content_type = "text/event-stream", description = "Server-sent events named after their `type`, plus `lagged` events carrying a `LaggedEvent`"),
+        ErrorResponses,
+    )
+)]
+async fn events_handler(query: EventsQuery, state: SharedState) -> Result<impl Reply, Rejection> {
+    let program_contexts: Option<HashSet<String>> = query
+        .program_context
+        .map(|list| {
+            list.split(',')
+                .map(|reference| find_account(&state.keystore, reference.trim()).map(|pubkey| pubkey.to_string()))
+                .collect::<Result<_, _>>()
+        })
+        .transpose()?;
+
+    let stream = BroadcastStream::new(state.events.subscribe()).filter_map(move |received| {
+        let event = match received {
+            Ok(event) => {
+                if program_contexts.as_ref().is_some_and(|watched| !watched.contains(&event.program_context)) {
+                    return None;
+                }
+                Event::default().event(event.kind.name()).json_data(&event)
+            }
+            Err(BroadcastStreamRecvError::Lagged(missed)) => Event::default().event("lagged").json_data(LaggedEvent { missed }),
+        };
+        Some(Ok::<_, Infallible>(event.unwrap_or_else(|err| Event::default().event("error").data(err.to_string()))))
+    });
+
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(stream)))
 }
 
-/// Deserializes the bytes back into a `Keypair`.
-fn deserialize_keypair_from_bytes(bytes: &[u8]) -> Keypair {
-    Keypair::from_bytes(bytes).expect("Failed to recover keypair from bytes")
+// Function to open the keystore holding the signers of the API
+fn load_keystore(keystore_dir: &Path) -> Keystore {
+    let keystore = Keystore::open(keystore_dir).expect("Failed to open the keystore");
+
+    println!("Keystore loaded from {}:", keystore_dir.display());
+    for (id, pubkey) in keystore.list() {
+        println!("   {}: {}", id, pubkey);
+    }
+
+    keystore
 }
 
-pub const PAYER_PUBKEY: &str = "dcf858909bc5bcbefc152eaf0561eb1d6e6c223328f0e06d8ce855f987a07e327bc14a0545e1a150fdf695cf889c482569f0ff42b30fe63b037cf8679c7c7a09";
-pub const SERVER_PUBKEY: &str = "356f41cbba468c243804af4ef2cfdb127b6212cf4ac9de552c5a74fdf6c218164ce9382dc9cf36ff00009e7ced94a2a15a34e2908b431e7b998553fa67d1184e";
-pub const MEDIATOR_PUBKEY: &str = "91b298f138a41ab2b641a82e9c00c1505ecda791256f93e32beb814b980a070d9b20c29e5ed265c30606f226ccd53af629af477726504ef5d6bd2756715d96dd";
-pub const PROGRAM_CONTEXT_PUBKEY: &str = "9abcabbe576bc4c95fb7338bc941c79d89cd1163599d648f3374a81615277917d43d3c2fce05680bfe423fe37f35b9b2e6b6bf4cc08e3bd66f5c09ae9f649118";
+// OpenAPI document of the HTTP API, served at /openapi.json
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Mediator client API", description = "Proofs of retrievability settled by the mediator program"),
+    paths(
+        verify_handler,
+        verify_batch_handler,
+        airdrop_handler,
+        list_signers_handler,
+        create_signer_handler,
+        initialize_subscription_handler,
+        set_client_curve_points_handler,
+        set_merkle_commitment_handler,
+        issue_merkle_challenge_handler,
+        submit_merkle_proof_handler,
+        submit_aggregated_proof_handler,
+        extend_subscription_handler,
+        update_file_state_handler,
+        end_subscription_handler,
+        retrieve_handler,
+        get_subscription_handler,
+        list_subscriptions_handler,
+        subscription_history_handler,
+        events_handler,
+    ),
+    components(schemas(
+        RequestPayload,
+        VerifyBatchResponse,
+        AirdropRequest,
+        AirdropResponse,
+        CreateSignerRequest,
+        SignerResponse,
+        InitializeSubscriptionRequest,
+        ClientCurvePoints,
+        SetClientCurvePointsRequest,
+        SetMerkleCommitmentRequest,
+        IssueMerkleChallengeRequest,
+        MerkleLeafProofPayload,
+        SubmitMerkleProofRequest,
+        SubmitAggregatedProofRequest,
+        ExtendSubscriptionRequest,
+        UpdateFileStateRequest,
+        EndSubscriptionRequest,
+        RetrieveRequest,
+        InstructionResponse,
+        ProgramContextSnapshot,
+        MediatorSnapshot,
+        SubscriptionResponse,
+        ProgramCallResponse,
+        HistoryEntryResponse,
+        SubscriptionEvent,
+        EventKind,
+        LaggedEvent,
+        ErrorBody,
+    ))
+)]
+struct ApiDoc;
+
+// The OpenAPI document, without the empty license utoipa reads from Cargo.toml
+fn openapi_document() -> utoipa::openapi::OpenApi {
+    let mut document = ApiDoc::openapi();
+    document.info.license = None;
+    document
+}
+
+// Reports an invalid configuration and exits
+fn exit_with_error(err: impl std::fmt::Display) -> ! {
+    eprintln!("{}", err);
+    std::process::exit(2);
+}
+
+// Runs the auditor daemon configured by the JSON file at `config_path`, the environment and the command line
+async fn run_auditor(config_path: &str, command_line: &CommandLine) {
+    let config = AuditorConfig::load(config_path).unwrap_or_else(|err| exit_with_error(err));
+    let layer = ConfigLayer::from(config.cluster.clone())
+        .merge_env_and_flags(command_line)
+        .unwrap_or_else(|err| exit_with_error(err));
+    let cluster = ClusterConfig::from_layer(&layer).unwrap_or_else(|err| exit_with_error(err));
+    let por_config = PorKeyConfig::from_layer(&layer, PorKeyConfig::password_from_env()).unwrap_or_else(|err| exit_with_error(err));
+    let por_keys = Arc::new(load_por_keys(&por_config).unwrap_or_else(|err| exit_with_error(err)));
+
+    println!("Auditing {} subscriptions every {} seconds", config.subscriptions.len(), config.interval_secs);
+    let auditor = Auditor::new(config, cluster, por_keys).unwrap_or_else(|err| exit_with_error(err));
+    if let Err(err) = auditor.run().await {
+        eprintln!("Auditor stopped: {}", err);
+    }
+}
 
 #[tokio::main]
 async fn main() {
-    let (payer, server, mediator, program_context) = generate_keypairs();
+    let command_line = CommandLine::parse(std::env::args().skip(1)).unwrap_or_else(|err| exit_with_error(err));
 
-    let verify = warp::path("verify")
-        .and(warp::post())
-        .and(warp::body::json())
-        .map(|body: RequestPayload| {
-            if let Err(e) = body.validate() {
-                return warp::reply::json(&format!("Validation error: {}", e));
-            }
+    // `client auditor [config.json]` runs the auditor daemon instead of the HTTP API
+    if command_line.positional.first().map(String::as_str) == Some("auditor") {
+        run_auditor(command_line.positional.get(1).map(String::as_str).unwrap_or("auditor.json"), &command_line).await;
+        return;
+    }
 
-            let g_norm = G2Affine::from_compressed(&body.g_compressed).unwrap();
-            let σ_norm = G1Affine::from_compressed(&body.sigma_compressed).unwrap();
-            let v_norm = G2Affine::from_compressed(&body.v_compressed).unwrap();
-            let multiplication_sum_norm = G1Affine::from_compressed(&body.multiplication_sum_compressed).unwrap();
+    // `client openapi` prints the OpenAPI document the TypeScript client is generated from
+    if command_line.positional.first().map(String::as_str) == Some("openapi") {
+        println!("{}", openapi_document().to_pretty_json().expect("The OpenAPI document serializes"));
+        return;
+    }
+
+    // `client typescript` prints the TypeScript client of the explorer, generated from that document
+    if command_line.positional.first().map(String::as_str) == Some("typescript") {
+        let document = serde_json::to_value(openapi_document()).expect("The OpenAPI document serializes");
+        print!("{}", typescript::render(&document));
+        return;
+    }
 
-            let left_pairing = pairing(&σ_norm, &g_norm);
-            let right_pairing = pairing(&multiplication_sum_norm, &v_norm);
+    let config = ApiConfig::load(&command_line).unwrap_or_else(|err| exit_with_error(err));
+    println!("RPC: {} ({:?}), websocket: {}", config.cluster.rpc_url, config.cluster.commitment.commitment, config.cluster.ws_url);
+    println!("Program: {}", config.cluster.program_id);
 
-            let is_verified = left_pairing.eq(&right_pairing);
-            println!("{}", is_verified);
+    let keystore = load_keystore(&config.keystore_dir);
+    let por_keys = Arc::new(load_por_keys(&config.por).unwrap_or_else(|err| exit_with_error(err)));
 
-            warp::reply::json(&if is_verified { "Verified" } else { "Not Verified" })
-        });
+    let bind_address = config.bind_address;
+    let state: SharedState = Arc::new(AppState::new(config, keystore));
+
+    // Events are streamed to browsers by `/events`
+    let listener_state = Arc::clone(&state);
+    tokio::spawn(async move {
+        let state = listener_state;
+        events::run_listener(&state.rpc, &state.config.cluster.ws_url, &state.config.cluster.program_id, &state.events).await;
+    });
+
+    let with_state = warp::any().map(move || Arc::clone(&state));
+    let with_por_keys = warp::any().map(move || Arc::clone(&por_keys));
+
+    let verify = warp::path("verify")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and_then(verify_handler);
+
+    let verify_batch = warp::path("verifyBatch")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and_then(verify_batch_handler);
 
     let airdrop = warp::path("airdrop")
         .and(warp::post())
         .and(warp::body::json())
+        .and(with_state.clone())
         .and_then(airdrop_handler);
 
+    let list_signers = warp::path("signers")
+        .and(warp::get())
+        .and(with_state.clone())
+        .and_then(list_signers_handler);
+
+    let create_signer = warp::path("signers")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_state.clone())
+        .and_then(create_signer_handler);
+
     let initialize_subscription = warp::path("initializeSubscription")
         .and(warp::post())
         .and(warp::body::json())
+        .and(with_state.clone())
         .and_then(initialize_subscription_handler);
 
-    // let set_client_curve_points = warp::path("setClientCurvePoints")
-    //     .and(warp::post())
-    //     .and(warp::body::json())
-    //     .and_then(set_client_curve_points_handler);
+    let set_client_curve_points = warp::path("setClientCurvePoints")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_state.clone())
+        .and(with_por_keys)
+        .and_then(set_client_curve_points_handler);
+
+    let set_merkle_commitment = warp::path("setMerkleCommitment")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_state.clone())
+        .and_then(set_merkle_commitment_handler);
+
+    let issue_merkle_challenge = warp::path("issueMerkleChallenge")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_state.clone())
+        .and_then(issue_merkle_challenge_handler);
+
+    let submit_merkle_proof = warp::path("submitMerkleProof")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_state.clone())
+        .and_then(submit_merkle_proof_handler);
 
-    // let extend_subscription = warp::path("extendSubscription")
-    //     .and(warp::post())
-    //     .and(warp::body::json())
-    //     .and_then(extend_subscription_handler);
+    let submit_aggregated_proof = warp::path("submitAggregatedProof")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_state.clone())
+        .and_then(submit_aggregated_proof_handler);
 
-    // let end_subscription = warp::path("endSubscription")
-    //     .and(warp::post())
-    //     .and(warp::body::json())
-    //     .and_then(end_subscription_handler);
+    let extend_subscription = warp::path("extendSubscription")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_state.clone())
+        .and_then(extend_subscription_handler);
+
+    let update_file_state = warp::path("updateFileState")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_state.clone())
+        .and_then(update_file_state_handler);
+
+    let end_subscription = warp::path("endSubscription")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_state.clone())
+        .and_then(end_subscription_handler);
+
+    let get_subscription = warp::path!("subscriptions" / String)
+        .and(warp::get())
+        .and(warp::query::<SubscriptionQuery>())
+        .and(with_state.clone())
+        .and_then(get_subscription_handler);
+
+    let list_subscriptions = warp::path!("subscriptions")
+        .and(warp::get())
+        .and(warp::query::<SubscriptionsQuery>())
+        .and(with_state.clone())
+        .and_then(list_subscriptions_handler);
+
+    let subscription_history = warp::path!("subscriptions" / String / "history")
+        .and(warp::get())
+        .and(warp::query::<HistoryQuery>())
+        .and(with_state.clone())
+        .and_then(subscription_history_handler);
+
+    let subscription_events = warp::path!("events")
+        .and(warp::get())
+        .and(warp::query::<EventsQuery>())
+        .and(with_state.clone())
+        .and_then(events_handler);
+
+    let openapi = warp::path!("openapi.json")
+        .and(warp::get())
+        .map(|| warp::reply::json(&openapi_document()));
+
+    let retrieve = warp::path("retrieve")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_state)
+        .and_then(retrieve_handler);
 
     let routes = verify
+        .or(verify_batch)
         .or(airdrop)
-        .or(initialize_subscription);
-        // .or(set_client_curve_points)
-        // // .or(extend_subscription)
-        // .or(end_subscription);
-
-    println!("Server running at http://127.0.0.1:3030/");
-    warp::serve(routes).run(([127, 0, 0, 1], 3030)).await;
+        .or(list_signers)
+        .or(create_signer)
+        .or(initialize_subscription)
+        .or(set_client_curve_points)
+        .or(set_merkle_commitment)
+        .or(issue_merkle_challenge)
+        .or(submit_merkle_proof)
+        .or(submit_aggregated_proof)
+        .or(extend_subscription)
+        .or(update_file_state)
+        .or(end_subscription)
+        .or(retrieve)
+        .or(get_subscription)
+        .or(list_subscriptions)
+        .or(subscription_history)
+        .or(subscription_events)
+        .or(openapi)
+        .recover(handle_rejection);
+
+    println!("Server running at http://{}/", bind_address);
+    if !bind_address.ip().is_loopback() {
+        eprintln!("Warning: the API is not authenticated and signs with every keystore key, bind it to a loopback address");
+    }
+    warp::serve(routes).run(bind_address).await;
 }
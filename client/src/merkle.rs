@@ -0,0 +1,298 @@
+//! Binary SHA-256 Merkle tree, hashed the same way as the on-chain program.
+//!
+//! Leaves are hashed as `sha256(0x00 || data)` and inner nodes as `sha256(0x01 || left || right)`
+//! so a leaf can never be passed off as an inner node. A level with an odd number of nodes
+//! pairs its last node with itself. The root of an empty tree is all zeros.
+//!
+//! The same tree backs the Merkle proof-of-storage mode: the client commits on-chain the root
+//! over the file cut into `MERKLE_LEAF_SIZE`-byte leaves, and the server answers challenges
+//! with the challenged leaves and their authentication paths, which the program verifies with
+//! the `sha256` syscall. Leaves are kept small so a whole answer fits in one transaction; the
+//! program shrinks the largest challenge as the tree grows deeper (see `merkle_answer_len`).
+
+use sha2::{Digest, Sha256};
+pub use solana_smart_contract::{merkle_answer_len, MAX_MERKLE_ANSWER_LEN, MERKLE_LEAF_SIZE};
+use solana_smart_contract::{merkle_challenge_index, MerkleLeafProof};
+
+pub type Hash = [u8; 32];
+
+pub const LEAF_PREFIX: u8 = 0x00;
+pub const NODE_PREFIX: u8 = 0x01;
+
+pub fn leaf_hash(data: &[u8]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+pub fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    /// `levels[0]` holds the leaf hashes, the last level holds the root.
+    levels: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    pub fn from_leaf_hashes(leaves: Vec<Hash>) -> Self {
+        let mut levels = vec![leaves];
+        while levels.last().is_some_and(|level| level.len() > 1) {
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| node_hash(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+                .collect();
+            levels.push(next);
+        }
+
+        MerkleTree { levels }
+    }
+
+    pub fn leaf_count(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    pub fn root(&self) -> Hash {
+        self.levels.last().and_then(|level| level.first()).copied().unwrap_or([0u8; 32])
+    }
+
+    /// Authentication path of leaf `index`, from the leaf level up to (excluding) the root.
+    pub fn proof(&self, index: usize) -> Option<Vec<Hash>> {
+        if index >= self.leaf_count() {
+            return None;
+        }
+
+        let mut path = Vec::with_capacity(self.levels.len() - 1);
+        let mut position = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = position ^ 1;
+            path.push(*level.get(sibling).unwrap_or(&level[position]));
+            position /= 2;
+        }
+
+        Some(path)
+    }
+}
+
+/// Checks that `leaf` sits at `index` of the tree over `leaf_count` leaves committed by `root`.
+pub fn verify_path(root: &Hash, leaf: &Hash, index: usize, leaf_count: usize, path: &[Hash]) -> bool {
+    // Past the last leaf, a path through the duplicated last node would still reach the root
+    if index >= leaf_count {
+        return false;
+    }
+
+    let mut node = *leaf;
+    let mut position = index;
+    for sibling in path {
+        node = if position.is_multiple_of(2) {
+            node_hash(&node, sibling)
+        } else {
+            node_hash(sibling, &node)
+        };
+        position /= 2;
+    }
+
+    position == 0 && node == *root
+}
+
+/// Builds the tree over the hashes of `leaves`.
+pub fn tree_from_blocks<B: AsRef<[u8]>>(leaves: &[B]) -> MerkleTree {
+    MerkleTree::from_leaf_hashes(leaves.iter().map(|leaf| leaf_hash(leaf.as_ref())).collect())
+}
+
+/// Cuts file data into the leaves committed in Merkle proof mode, padding the last with zeros.
+pub fn file_leaves(data: &[u8]) -> Vec<Vec<u8>> {
+    data.chunks(MERKLE_LEAF_SIZE)
+        .map(|chunk| {
+            let mut leaf = chunk.to_vec();
+            leaf.resize(MERKLE_LEAF_SIZE, 0);
+            leaf
+        })
+        .collect()
+}
+
+/// Indices of the leaves asked by an on-chain Merkle challenge, none for an empty tree.
+pub fn challenge_indices(challenge_seed: &[u8; 32], challenge_size: u8, leaf_count: u64) -> Vec<u64> {
+    if leaf_count == 0 {
+        return Vec::new();
+    }
+
+    (0..challenge_size)
+        .map(|k| merkle_challenge_index(challenge_seed, k, leaf_count))
+        .collect()
+}
+
+/// Answers an on-chain Merkle challenge with the challenged leaves and their authentication paths.
+pub fn prove_challenge<B: AsRef<[u8]>>(
+    tree: &MerkleTree,
+    leaves: &[B],
+    challenge_seed: &[u8; 32],
+    challenge_size: u8,
+) -> Option<Vec<MerkleLeafProof>> {
+    prove_challenge_with(tree, challenge_seed, challenge_size, |index| {
+        leaves.get(index as usize).map(|leaf| leaf.as_ref().to_vec())
+    })
+}
+
+/// Like `prove_challenge`, reading only the challenged leaves through `read_leaf`.
+pub fn prove_challenge_with<F>(
+    tree: &MerkleTree,
+    challenge_seed: &[u8; 32],
+    challenge_size: u8,
+    mut read_leaf: F,
+) -> Option<Vec<MerkleLeafProof>>
+where
+    F: FnMut(u64) -> Option<Vec<u8>>,
+{
+    if tree.leaf_count() == 0 {
+        return None;
+    }
+
+    challenge_indices(challenge_seed, challenge_size, tree.leaf_count() as u64)
+        .into_iter()
+        .map(|index| {
+            Some(MerkleLeafProof {
+                block: read_leaf(index)?,
+                path: tree.proof(index as usize)?,
+            })
+        })
+        .collect()
+}
+
+/// Client-side check of an answer to a Merkle challenge, mirroring `submit_merkle_proof`.
+pub fn verify_challenge(
+    root: &Hash,
+    leaf_count: u64,
+    challenge_seed: &[u8; 32],
+    challenge_size: u8,
+    proofs: &[MerkleLeafProof],
+) -> bool {
+    if leaf_count == 0 || challenge_size == 0 || proofs.len() != challenge_size as usize {
+        return false;
+    }
+
+    challenge_indices(challenge_seed, challenge_size, leaf_count)
+        .iter()
+        .zip(proofs)
+        .all(|(index, proof)| {
+            proof.block.len() == MERKLE_LEAF_SIZE
+                && verify_path(root, &leaf_hash(&proof.block), *index as usize, leaf_count as usize, &proof.path)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transactions::submit_merkle_proof_instruction;
+    use anchor_client::solana_sdk::message::Message;
+    use anchor_client::solana_sdk::packet::PACKET_DATA_SIZE;
+    use anchor_client::solana_sdk::pubkey::Pubkey;
+    use solana_smart_contract::MAX_MERKLE_CHALLENGE_SIZE;
+
+    fn blocks(count: usize) -> Vec<Vec<u8>> {
+        (0..count).map(|index| vec![index as u8; 8]).collect()
+    }
+
+    #[test]
+    fn every_leaf_verifies_against_the_root() {
+        for count in 1..=9 {
+            let blocks = blocks(count);
+            let tree = tree_from_blocks(&blocks);
+            for (index, block) in blocks.iter().enumerate() {
+                let path = tree.proof(index).unwrap();
+                assert!(verify_path(&tree.root(), &leaf_hash(block), index, count, &path));
+                assert!(!verify_path(&tree.root(), &leaf_hash(b"other"), index, count, &path));
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_indices_past_the_last_leaf() {
+        // With 5 leaves the last node is paired with itself, so its path also hashes to the
+        // root from index 5
+        let blocks = blocks(5);
+        let tree = tree_from_blocks(&blocks);
+        let path = tree.proof(4).unwrap();
+
+        assert!(tree.proof(5).is_none());
+        assert!(!verify_path(&tree.root(), &leaf_hash(&blocks[4]), 5, 5, &path));
+        assert!(!verify_path(&tree.root(), &leaf_hash(&blocks[4]), usize::MAX, 5, &path));
+    }
+
+    #[test]
+    fn empty_tree_has_a_zero_root() {
+        let tree = MerkleTree::from_leaf_hashes(Vec::new());
+
+        assert_eq!(tree.root(), [0u8; 32]);
+        assert!(tree.proof(0).is_none());
+        assert!(!verify_path(&tree.root(), &[0u8; 32], 0, 0, &[]));
+    }
+
+    #[test]
+    fn answers_challenges_the_program_accepts() {
+        let leaves = file_leaves(&[7u8; MERKLE_LEAF_SIZE * 6 + 10]);
+        let tree = tree_from_blocks(&leaves);
+        let seed = [3u8; 32];
+        assert_eq!(leaves.len(), 7);
+        assert_eq!(leaves[6][10..], [0; MERKLE_LEAF_SIZE - 10]);
+
+        let proofs = prove_challenge(&tree, &leaves, &seed, 4).unwrap();
+        assert!(verify_challenge(&tree.root(), 7, &seed, 4, &proofs));
+
+        let mut forged = proofs.clone();
+        forged[0].block[0] ^= 1;
+        assert!(!verify_challenge(&tree.root(), 7, &seed, 4, &forged));
+    }
+
+    #[test]
+    fn the_largest_answers_fit_in_a_transaction() {
+        let server = Pubkey::new_unique();
+        for challenge_size in 1..=MAX_MERKLE_CHALLENGE_SIZE {
+            // Deepest tree the program still accepts a challenge of this size for
+            let Some(depth) = (0..64u32).rev().find(|depth| merkle_answer_len(challenge_size, 1 << depth) <= MAX_MERKLE_ANSWER_LEN)
+            else {
+                continue;
+            };
+            let proofs = (0..challenge_size)
+                .map(|_| MerkleLeafProof {
+                    block: vec![0xff; MERKLE_LEAF_SIZE],
+                    path: vec![[0xff; 32]; depth as usize],
+                })
+                .collect();
+            let instruction = submit_merkle_proof_instruction(&solana_smart_contract::ID, &server, &Pubkey::new_unique(), proofs);
+
+            let message = Message::new(&[instruction], Some(&server));
+            // One signature and its compact length prefix precede the message
+            let len = 1 + 64 + message.serialize().len();
+            assert!(len <= PACKET_DATA_SIZE, "{} leaves at depth {} take {} bytes", challenge_size, depth, len);
+        }
+
+        // A commitment whose single-leaf answer doesn't fit is refused
+        assert!(merkle_answer_len(1, 1 << 29) <= MAX_MERKLE_ANSWER_LEN);
+        assert!(merkle_answer_len(1, (1 << 29) + 1) > MAX_MERKLE_ANSWER_LEN);
+    }
+
+    #[test]
+    fn rejects_incomplete_answers_and_empty_trees() {
+        let leaves = file_leaves(&[1u8; MERKLE_LEAF_SIZE * 5]);
+        let tree = tree_from_blocks(&leaves);
+        let seed = [4u8; 32];
+        let proofs = prove_challenge(&tree, &leaves, &seed, 3).unwrap();
+
+        assert!(!verify_challenge(&tree.root(), 5, &seed, 3, &[]));
+        assert!(!verify_challenge(&tree.root(), 5, &seed, 3, &proofs[..2]));
+        assert!(!verify_challenge(&tree.root(), 5, &seed, 2, &proofs));
+        assert!(!verify_challenge(&tree.root(), 0, &seed, 3, &proofs));
+        assert!(!verify_challenge(&[0u8; 32], 0, &seed, 0, &[]));
+        assert!(challenge_indices(&seed, 3, 0).is_empty());
+        assert!(prove_challenge(&tree_from_blocks::<Vec<u8>>(&[]), &[] as &[Vec<u8>], &seed, 3).is_none());
+    }
+}
@@ -0,0 +1,112 @@
+//! Proofs of possession (PoP) of PoR secret keys.
+//!
+//! A client proves it knows the `α` behind its `v = g^α` by signing its own public key:
+//! `π = H_pop(g || v)^α`, checked with `e(π, g) == e(H_pop(g || v), v)`. Without it, a client
+//! could register a rogue `v` crafted from other clients' keys, which breaks any verification
+//! that combines several keys. `H_pop` uses its own domain separation tag so a PoP can never
+//! be mistaken for a block tag.
+
+use crate::por::PorKeyPair;
+use bls12_381::hash_to_curve::{ExpandMsgXmd, HashToCurve};
+use bls12_381::{pairing, G1Affine, G1Projective, G2Affine};
+use solana_smart_contract::ProgramContext;
+
+/// Domain separation tag for hashing public keys to G1.
+pub const POP_DST: &[u8] = b"POR-V01-CS01-with-BLS12381G1_XMD:SHA-256_SSWU_RO_POP_";
+
+fn hash_public_key(g: &G2Affine, v: &G2Affine) -> G1Projective {
+    let mut message = Vec::with_capacity(192);
+    message.extend_from_slice(&g.to_compressed());
+    message.extend_from_slice(&v.to_compressed());
+
+    <G1Projective as HashToCurve<ExpandMsgXmd<sha2::Sha256>>>::hash_to_curve(&message, POP_DST)
+}
+
+/// Signs the public key of `keys` with its own secret key.
+pub fn prove_possession(keys: &PorKeyPair) -> G1Affine {
+    keys.secret.sign(hash_public_key(&keys.public.g, &keys.public.v))
+}
+
+/// Checks a proof of possession for the key `(g, v)`.
+pub fn verify_possession(g: &G2Affine, v: &G2Affine, pop: &G1Affine) -> bool {
+    if bool::from(pop.is_identity()) {
+        return false;
+    }
+
+    pairing(pop, g) == pairing(&G1Affine::from(hash_public_key(g, v)), v)
+}
+
+/// Checks a proof of possession given as compressed points, as passed to `set_client_curve_points`.
+pub fn verify_possession_compressed(g_norm: &[u8; 96], v_norm: &[u8; 96], pop: &[u8; 48]) -> bool {
+    let g = Option::<G2Affine>::from(G2Affine::from_compressed(g_norm));
+    let v = Option::<G2Affine>::from(G2Affine::from_compressed(v_norm));
+    let pop = Option::<G1Affine>::from(G1Affine::from_compressed(pop));
+
+    match (g, v, pop) {
+        (Some(g), Some(v), Some(pop)) => verify_possession(&g, &v, &pop),
+        _ => false,
+    }
+}
+
+/// Checks the proof of possession stored with a subscription. The program only checks that it
+/// is a well-formed encoding, so this is the only place the PoP is enforced: verifiers must
+/// reject subscriptions failing this check before trusting any proof made with their key.
+pub fn verify_program_context(program_context: &ProgramContext) -> bool {
+    verify_possession_compressed(&program_context.g_norm, &program_context.v_norm, &program_context.pop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::por::PorParams;
+    use bls12_381::G2Projective;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    fn keys(seed: u64) -> PorKeyPair {
+        PorKeyPair::generate(PorParams::new(1), &mut ChaCha20Rng::seed_from_u64(seed))
+    }
+
+    #[test]
+    fn accepts_the_proof_of_the_key_owner() {
+        let keys = keys(1);
+        let pop = prove_possession(&keys);
+
+        assert!(verify_possession(&keys.public.g, &keys.public.v, &pop));
+        assert!(verify_possession_compressed(
+            &keys.public.g.to_compressed(),
+            &keys.public.v.to_compressed(),
+            &pop.to_compressed()
+        ));
+    }
+
+    #[test]
+    fn rejects_a_proof_made_for_another_key() {
+        let (owner, other) = (keys(1), keys(2));
+        let pop = prove_possession(&other);
+
+        assert!(!verify_possession(&owner.public.g, &owner.public.v, &pop));
+        assert!(!verify_possession(&owner.public.g, &owner.public.v, &G1Affine::identity()));
+    }
+
+    #[test]
+    fn rejects_a_rogue_key_without_its_secret() {
+        // v' = v_victim - v_attacker cancels the victim's key once aggregated, but the attacker
+        // doesn't know its exponent and can only copy a proof it holds
+        let (victim, attacker) = (keys(1), keys(2));
+        let rogue_v = G2Affine::from(G2Projective::from(victim.public.v) - attacker.public.v);
+
+        assert!(!verify_possession(&victim.public.g, &rogue_v, &prove_possession(&victim)));
+        assert!(!verify_possession(&victim.public.g, &rogue_v, &prove_possession(&attacker)));
+    }
+
+    #[test]
+    fn rejects_encodings_that_do_not_decode() {
+        let keys = keys(1);
+        let mut pop = prove_possession(&keys).to_compressed();
+        pop[47] ^= 1;
+
+        assert!(!verify_possession_compressed(&keys.public.g.to_compressed(), &keys.public.v.to_compressed(), &pop));
+        assert!(!verify_possession_compressed(&[0; 96], &keys.public.v.to_compressed(), &prove_possession(&keys).to_compressed()));
+    }
+}
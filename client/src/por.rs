@@ -0,0 +1,317 @@
+//! Core of the publicly verifiable Compact PoR scheme (Shacham–Waters) over BLS12-381.
+//!
+//! A file is split into blocks of `s` sectors. Each sector holds `SECTOR_SIZE` bytes so it
+//! always fits in a scalar of `Z_p`. `s` trades storage for proof size: every block costs one
+//! 48-byte tag and every proof carries one 32-byte `μ_j` per sector, so a large `s` suits
+//! archival data and a small `s` keeps proofs short (see `PorParams`).
+//!
+//! With the secret key `α`, the public generator `g ∈ G2`, `v = g^α` and the public points
+//! `u_1..u_s ∈ G1`, the tag of block `i` is
+//!
+//! `σ_i = (H(name || i || version_i) · ∏_j u_j^{m_ij})^α`
+//!
+//! which the verifier checks through `e(σ, g) == e(multiplication_sum, v)`. Statically tagged
+//! files use version 0 for every block; see `dynamic` for files that change after tagging.
+
+use bls12_381::hash_to_curve::{ExpandMsgXmd, HashToCurve};
+use bls12_381::{G1Affine, G1Projective, G2Affine, G2Projective, Scalar};
+use rand::RngCore;
+use std::fmt;
+use subtle::ConstantTimeEq;
+use zeroize::{DefaultIsZeroes, Zeroize, Zeroizing};
+
+/// Bytes stored in one sector; 31 bytes always stay below the BLS12-381 scalar modulus.
+pub const SECTOR_SIZE: usize = 31;
+
+/// Size in bytes of a compressed tag `σ_i`.
+pub const TAG_SIZE: usize = 48;
+
+/// Domain separation tag for hashing block indices to G1.
+pub const HASH_TO_G1_DST: &[u8] = b"POR-V01-CS01-with-BLS12381G1_XMD:SHA-256_SSWU_RO_";
+
+// Lets `zeroize` wipe a scalar. bls12_381 implements it behind its `zeroize` feature, which
+// needs zeroize 1.4 while the Solana crates pin it below
+#[derive(Clone, Copy, Default)]
+struct SecretScalar(Scalar);
+
+impl DefaultIsZeroes for SecretScalar {}
+
+/// The secret exponent `α`. Wiped on drop, compared in constant time and redacted in `Debug`.
+/// It never leaves this type: callers raise points to `α` or serialize it for a key file.
+pub struct PorSecretKey {
+    alpha: SecretScalar,
+}
+
+impl PorSecretKey {
+    /// Reads `α` from its canonical encoding; `None` if it isn't a scalar.
+    pub fn from_bytes(bytes: &[u8; 32]) -> Option<Self> {
+        Option::<Scalar>::from(Scalar::from_bytes(bytes)).map(|alpha| PorSecretKey { alpha: SecretScalar(alpha) })
+    }
+
+    /// Canonical encoding of `α`, wiped when dropped.
+    pub fn to_bytes(&self) -> Zeroizing<[u8; 32]> {
+        Zeroizing::new(self.alpha.0.to_bytes())
+    }
+
+    /// Raises an already hashed message to `α`, as for block tags and proofs of possession.
+    pub fn sign(&self, message: G1Projective) -> G1Affine {
+        G1Affine::from(message * self.alpha.0)
+    }
+
+    /// Checks that `public` was derived from this key: `v = g^α`.
+    pub fn matches(&self, public: &PorPublicKey) -> bool {
+        G2Affine::from(public.g * self.alpha.0) == public.v
+    }
+}
+
+impl Zeroize for PorSecretKey {
+    fn zeroize(&mut self) {
+        self.alpha.zeroize();
+    }
+}
+
+impl Drop for PorSecretKey {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl fmt::Debug for PorSecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PorSecretKey(<redacted>)")
+    }
+}
+
+impl PartialEq for PorSecretKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.alpha.0.ct_eq(&other.alpha.0).into()
+    }
+}
+
+impl Eq for PorSecretKey {}
+
+/// Block layout: `s` sectors per block, and the block size in bytes. The last sector of a
+/// block may be partial, so any block size in `((s - 1) · SECTOR_SIZE, s · SECTOR_SIZE]` works.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PorParams {
+    pub sectors_per_block: usize,
+    pub block_size: usize,
+}
+
+impl PorParams {
+    /// Blocks of `sectors_per_block` full sectors.
+    pub fn new(sectors_per_block: usize) -> Self {
+        PorParams {
+            sectors_per_block,
+            block_size: sectors_per_block * SECTOR_SIZE,
+        }
+    }
+
+    /// Blocks of `block_size` bytes, with as few sectors as possible.
+    pub fn with_block_size(block_size: usize) -> Self {
+        PorParams {
+            sectors_per_block: block_size.div_ceil(SECTOR_SIZE),
+            block_size,
+        }
+    }
+
+    /// Smallest `s` whose tags take at most `target_overhead` (e.g. 0.01 for 1%) of the
+    /// stored data, which also gives the smallest proofs for that overhead.
+    pub fn recommend(target_overhead: f64) -> Option<Self> {
+        if !target_overhead.is_finite() || target_overhead <= 0.0 {
+            return None;
+        }
+
+        let sectors_per_block = (TAG_SIZE as f64 / (target_overhead * SECTOR_SIZE as f64)).ceil();
+        if sectors_per_block > u32::MAX as f64 {
+            return None;
+        }
+
+        Some(PorParams::new((sectors_per_block as usize).max(1)))
+    }
+
+    /// Checks that the block size needs exactly `sectors_per_block` sectors, as the program does.
+    pub fn is_valid(&self) -> bool {
+        match (u32::try_from(self.sectors_per_block), u32::try_from(self.block_size)) {
+            (Ok(sectors_per_block), Ok(block_size)) => {
+                solana_smart_contract::is_valid_por_params(sectors_per_block, block_size)
+            }
+            _ => false,
+        }
+    }
+
+    /// Tag storage relative to the data: `TAG_SIZE / block_size`.
+    pub fn storage_overhead(&self) -> f64 {
+        TAG_SIZE as f64 / self.block_size as f64
+    }
+
+    /// Size in bytes of a compressed proof: `σ` plus one `μ_j` per sector.
+    pub fn proof_size(&self) -> usize {
+        TAG_SIZE + 32 * self.sectors_per_block
+    }
+}
+
+/// Public parameters needed by the prover and the verifier.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PorPublicKey {
+    pub g: G2Affine,
+    pub v: G2Affine,
+    pub u: Vec<G1Affine>,
+    pub block_size: usize,
+}
+
+impl PorPublicKey {
+    /// Number of sectors per block `s`.
+    pub fn sectors_per_block(&self) -> usize {
+        self.u.len()
+    }
+
+    /// Size in bytes of a block covered by a single tag.
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn params(&self) -> PorParams {
+        PorParams {
+            sectors_per_block: self.sectors_per_block(),
+            block_size: self.block_size,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PorKeyPair {
+    pub secret: PorSecretKey,
+    pub public: PorPublicKey,
+}
+
+impl PorKeyPair {
+    /// Generates a fresh key pair for the block layout `params`.
+    ///
+    /// Panics if `params` is not valid.
+    pub fn generate<R: RngCore>(params: PorParams, rng: &mut R) -> Self {
+        assert!(params.is_valid(), "Invalid PoR parameters: {:?}", params);
+
+        let alpha = random_scalar(rng);
+        let g = G2Projective::generator() * random_scalar(rng);
+        let v = g * alpha;
+        let u = (0..params.sectors_per_block)
+            .map(|_| G1Affine::from(G1Projective::generator() * random_scalar(rng)))
+            .collect();
+
+        PorKeyPair {
+            secret: PorSecretKey { alpha: SecretScalar(alpha) },
+            public: PorPublicKey {
+                g: G2Affine::from(g),
+                v: G2Affine::from(v),
+                u,
+                block_size: params.block_size,
+            },
+        }
+    }
+
+    /// Computes the tag `σ_i` of version `version` of block `index` of the file `file_name`.
+    pub fn tag_block(&self, file_name: &[u8], index: u64, version: u64, block: &[u8]) -> G1Affine {
+        let sectors = block_to_sectors(block, self.public.sectors_per_block());
+        let mut tag = hash_index(file_name, index, version);
+        for (u_j, m_ij) in self.public.u.iter().zip(sectors.iter()) {
+            tag += u_j * m_ij;
+        }
+
+        self.secret.sign(tag)
+    }
+}
+
+/// Draws a uniformly random non-zero scalar.
+pub fn random_scalar<R: RngCore>(rng: &mut R) -> Scalar {
+    loop {
+        let mut bytes = [0u8; 64];
+        rng.fill_bytes(&mut bytes);
+        let scalar = Scalar::from_bytes_wide(&bytes);
+        if scalar != Scalar::zero() {
+            return scalar;
+        }
+    }
+}
+
+/// Hashes `file_name || index || version` to a point of G1 (`H(name || i)` in the paper).
+pub fn hash_index(file_name: &[u8], index: u64, version: u64) -> G1Projective {
+    let mut message = Vec::with_capacity(file_name.len() + 16);
+    message.extend_from_slice(file_name);
+    message.extend_from_slice(&index.to_be_bytes());
+    message.extend_from_slice(&version.to_be_bytes());
+
+    <G1Projective as HashToCurve<ExpandMsgXmd<sha2::Sha256>>>::hash_to_curve(&message, HASH_TO_G1_DST)
+}
+
+/// Splits a block into `sectors_per_block` scalars. Missing bytes are treated as zeros.
+pub fn block_to_sectors(block: &[u8], sectors_per_block: usize) -> Vec<Scalar> {
+    (0..sectors_per_block)
+        .map(|j| {
+            let start = (j * SECTOR_SIZE).min(block.len());
+            let end = ((j + 1) * SECTOR_SIZE).min(block.len());
+            sector_to_scalar(&block[start..end])
+        })
+        .collect()
+}
+
+/// Interprets up to `SECTOR_SIZE` bytes as a little-endian scalar.
+pub fn sector_to_scalar(sector: &[u8]) -> Scalar {
+    let mut bytes = [0u8; 32];
+    bytes[..sector.len()].copy_from_slice(sector);
+
+    // The top byte is always zero, so the value is below the modulus
+    Scalar::from_bytes(&bytes).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    fn keys(seed: u64) -> PorKeyPair {
+        PorKeyPair::generate(PorParams::new(2), &mut ChaCha20Rng::seed_from_u64(seed))
+    }
+
+    #[test]
+    fn secret_key_is_wiped_and_never_printed() {
+        let keys = keys(1);
+        let mut secret = PorSecretKey::from_bytes(&keys.secret.to_bytes()).unwrap();
+        assert_eq!(secret, keys.secret);
+        assert!(secret.matches(&keys.public));
+        assert_eq!(format!("{:?}", secret), "PorSecretKey(<redacted>)");
+
+        secret.zeroize();
+        assert_eq!(*secret.to_bytes(), [0; 32]);
+        assert_ne!(secret, keys.secret);
+        assert!(!secret.matches(&keys.public));
+    }
+
+    #[test]
+    fn tags_bind_the_block_its_index_and_version() {
+        let (keys, other) = (keys(1), keys(2));
+        let block = [7u8; 62];
+        let tag = keys.tag_block(b"file", 3, 0, &block);
+
+        assert_ne!(tag, keys.tag_block(b"file", 4, 0, &block));
+        assert_ne!(tag, keys.tag_block(b"file", 3, 1, &block));
+        assert_ne!(tag, keys.tag_block(b"other", 3, 0, &block));
+        assert_ne!(tag, keys.tag_block(b"file", 3, 0, &[8u8; 62]));
+        assert_ne!(tag, other.tag_block(b"file", 3, 0, &block));
+    }
+
+    #[test]
+    fn params_match_the_program_rules() {
+        assert!(PorParams::new(4).is_valid());
+        assert!(PorParams::with_block_size(4096).is_valid());
+        assert_eq!(PorParams::with_block_size(4096).sectors_per_block, 133);
+        assert!(!PorParams { sectors_per_block: 2, block_size: 31 }.is_valid());
+        assert!(!PorParams { sectors_per_block: 0, block_size: 0 }.is_valid());
+
+        // 48-byte tags over 1% of the data take 155 sectors
+        assert_eq!(PorParams::recommend(0.01), Some(PorParams::new(155)));
+        assert_eq!(PorParams::recommend(0.0), None);
+        assert_eq!(PorParams::new(4).proof_size(), 48 + 4 * 32);
+    }
+}
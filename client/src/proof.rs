@@ -0,0 +1,208 @@
+//! Challenges, proofs and the prover/verifier of the Compact PoR scheme.
+//!
+//! For a challenge `Q = {(i, ν_i)}` the server answers with
+//! `σ = ∏ σ_i^{ν_i}` and `μ_j = Σ ν_i · m_ij` for every sector `j`, and the verifier checks
+//! `e(σ, g) == e(∏ H(name || i || version_i)^{ν_i} · ∏ u_j^{μ_j}, v)`.
+
+use crate::por::{block_to_sectors, hash_index, PorKeyPair, PorPublicKey};
+use crate::verification::{verify_proof, ProofInstance};
+use bls12_381::{G1Affine, G1Projective, Scalar};
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum ProofError {
+    /// The server doesn't hold the challenged block or its tag.
+    MissingBlock(u64),
+    /// A stored tag is not a valid G1 point.
+    InvalidTag(u64),
+    /// The proof doesn't have one `μ_j` per sector.
+    SectorCountMismatch { expected: usize, actual: usize },
+    /// Proofs or challenges that can't be combined.
+    InvalidAggregation(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProofError::MissingBlock(index) => write!(f, "Block {} is not stored", index),
+            ProofError::InvalidTag(index) => write!(f, "Tag of block {} is not a valid G1 point", index),
+            ProofError::SectorCountMismatch { expected, actual } => write!(
+                f,
+                "Invalid sector count: expected {} values of mu, got {}",
+                expected, actual
+            ),
+            ProofError::InvalidAggregation(reason) => write!(f, "Invalid aggregation: {}", reason),
+            ProofError::Io(err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ProofError {}
+
+impl From<io::Error> for ProofError {
+    fn from(err: io::Error) -> Self {
+        ProofError::Io(err)
+    }
+}
+
+/// One challenged block with its random coefficient `ν_i`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChallengeItem {
+    pub index: u64,
+    /// Committed version of the block, 0 for statically tagged files.
+    pub version: u64,
+    pub coefficient: Scalar,
+}
+
+/// A challenge over one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    pub file_name: Vec<u8>,
+    pub items: Vec<ChallengeItem>,
+}
+
+/// The server's answer `(σ, μ_1..μ_s)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proof {
+    pub sigma: G1Affine,
+    pub mu: Vec<Scalar>,
+}
+
+/// Access to the blocks and tags held by the server.
+pub trait StoredFile {
+    fn block(&self, index: u64) -> Result<Vec<u8>, ProofError>;
+    fn tag(&self, index: u64) -> Result<G1Affine, ProofError>;
+}
+
+/// Blocks and tags held in memory.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFile {
+    pub blocks: Vec<Vec<u8>>,
+    pub tags: Vec<G1Affine>,
+}
+
+impl MemoryFile {
+    /// Tags every block of `file_name`, at version 0.
+    pub fn tag(keys: &PorKeyPair, file_name: &[u8], blocks: Vec<Vec<u8>>) -> Self {
+        let tags = blocks
+            .iter()
+            .enumerate()
+            .map(|(index, block)| keys.tag_block(file_name, index as u64, 0, block))
+            .collect();
+        MemoryFile { blocks, tags }
+    }
+}
+
+impl StoredFile for MemoryFile {
+    fn block(&self, index: u64) -> Result<Vec<u8>, ProofError> {
+        self.blocks.get(index as usize).cloned().ok_or(ProofError::MissingBlock(index))
+    }
+
+    fn tag(&self, index: u64) -> Result<G1Affine, ProofError> {
+        self.tags.get(index as usize).copied().ok_or(ProofError::MissingBlock(index))
+    }
+}
+
+/// Computes the server's answer to `challenge`.
+pub fn prove<F: StoredFile + ?Sized>(public: &PorPublicKey, challenge: &Challenge, file: &F) -> Result<Proof, ProofError> {
+    let sectors_per_block = public.sectors_per_block();
+    let mut sigma = G1Projective::identity();
+    let mut mu = vec![Scalar::zero(); sectors_per_block];
+
+    for item in &challenge.items {
+        let block = file.block(item.index)?;
+        let tag = file.tag(item.index)?;
+
+        sigma += tag * item.coefficient;
+        for (mu_j, m_ij) in mu.iter_mut().zip(block_to_sectors(&block, sectors_per_block)) {
+            *mu_j += m_ij * item.coefficient;
+        }
+    }
+
+    Ok(Proof {
+        sigma: G1Affine::from(sigma),
+        mu,
+    })
+}
+
+/// Computes `∏ H(name || i || version_i)^{ν_i}` over the challenged blocks.
+pub fn challenge_hash_sum(challenge: &Challenge) -> G1Projective {
+    challenge
+        .items
+        .iter()
+        .fold(G1Projective::identity(), |sum, item| {
+            sum + hash_index(&challenge.file_name, item.index, item.version) * item.coefficient
+        })
+}
+
+/// Computes the verifier side product `hash_sum · ∏ u_j^{μ_j}`.
+pub fn multiplication_sum(public: &PorPublicKey, hash_sum: G1Projective, mu: &[Scalar]) -> Result<G1Affine, ProofError> {
+    if mu.len() != public.sectors_per_block() {
+        return Err(ProofError::SectorCountMismatch {
+            expected: public.sectors_per_block(),
+            actual: mu.len(),
+        });
+    }
+
+    let sum = public
+        .u
+        .iter()
+        .zip(mu)
+        .fold(hash_sum, |sum, (u_j, mu_j)| sum + u_j * mu_j);
+
+    Ok(G1Affine::from(sum))
+}
+
+/// Builds the pairing check of a proof, ready for single or batch verification.
+pub fn proof_instance(public: &PorPublicKey, challenge: &Challenge, proof: &Proof) -> Result<ProofInstance, ProofError> {
+    Ok(ProofInstance {
+        g: public.g,
+        v: public.v,
+        sigma: proof.sigma,
+        multiplication_sum: multiplication_sum(public, challenge_hash_sum(challenge), &proof.mu)?,
+    })
+}
+
+/// Checks the server's answer to `challenge`.
+pub fn verify(public: &PorPublicKey, challenge: &Challenge, proof: &Proof) -> bool {
+    proof_instance(public, challenge, proof)
+        .map(|instance| verify_proof(&instance))
+        .unwrap_or(false)
+}
+
+/// Fixtures shared by the tests of the PoR modules.
+#[cfg(test)]
+pub(crate) mod test_support {
+    use super::*;
+    use crate::por::PorParams;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    /// Keys of two sectors (62 bytes) per block.
+    pub fn keys(seed: u64) -> PorKeyPair {
+        PorKeyPair::generate(PorParams::new(2), &mut ChaCha20Rng::seed_from_u64(seed))
+    }
+
+    /// A tagged file of `block_count` blocks, block `i` filled with the byte `fill + i`.
+    pub fn tagged_file(keys: &PorKeyPair, file_name: &[u8], block_count: u8, fill: u8) -> MemoryFile {
+        let blocks = (0..block_count).map(|index| vec![fill + index; keys.public.block_size()]).collect();
+        MemoryFile::tag(keys, file_name, blocks)
+    }
+
+    /// Challenges the blocks `indices` of `file_name`, at version 0.
+    pub fn challenge(file_name: &[u8], indices: impl IntoIterator<Item = u64>) -> Challenge {
+        Challenge {
+            file_name: file_name.to_vec(),
+            items: indices
+                .into_iter()
+                .map(|index| ChallengeItem {
+                    index,
+                    version: 0,
+                    coefficient: Scalar::from(index + 5),
+                })
+                .collect(),
+        }
+    }
+}
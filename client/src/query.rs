@@ -0,0 +1,366 @@
+//! Read-only queries of the subscriptions of the mediator program.
+//!
+//! Subscriptions are found with `getProgramAccounts`, filtered on the account discriminator
+//! and on the `client` field of `ProgramContext`. The program stores neither the server nor
+//! the mediator of a subscription, so both are found in transaction history instead: the
+//! mediator in the `initialize` instruction that created the program context, and the
+//! subscriptions of a server in the instructions of the transactions it signed.
+
+use crate::transactions::TransactionError;
+use anchor_client::solana_client::nonblocking::rpc_client::RpcClient;
+use anchor_client::solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use anchor_client::solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use anchor_client::solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::solana_sdk::signature::Signature;
+use anchor_lang::{AccountDeserialize, Discriminator};
+use solana_account_decoder::UiAccountEncoding;
+use solana_smart_contract::{instruction as ix, Mediator, ProgramContext};
+use solana_transaction_status::UiTransactionEncoding;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Offset of `ProgramContext::client` in the account data: the discriminator, then
+/// `is_subscription_ended`, `is_server_turn`, `subscription_duration`, `mediator_balance`,
+/// `g_norm` and `v_norm`.
+pub const PROGRAM_CONTEXT_CLIENT_OFFSET: usize = 8 + 1 + 1 + 8 + 8 + 96 + 96;
+
+/// Offset of `ProgramContext::server`, the last field: after `client`, `file_version`,
+/// `block_count`, `file_state_root`, `proof_kind`, `challenge_seed`, `challenge_size`,
+/// `is_challenge_pending`, `last_proof_digest`, `proof_count`, `pop`, `sectors_per_block` and
+/// `block_size`.
+pub const PROGRAM_CONTEXT_SERVER_OFFSET: usize =
+    PROGRAM_CONTEXT_CLIENT_OFFSET + 32 + 8 + 8 + 32 + 1 + 32 + 1 + 1 + 32 + 8 + 48 + 4 + 4;
+
+/// Most signatures returned by one `getSignaturesForAddress` call.
+pub const MAX_SIGNATURES: usize = 1000;
+
+/// Instructions of the program, by discriminator.
+const INSTRUCTIONS: [(&str, [u8; 8]); 10] = [
+    ("initialize", ix::Initialize::DISCRIMINATOR),
+    ("set_client_curve_points", ix::SetClientCurvePoints::DISCRIMINATOR),
+    ("set_merkle_commitment", ix::SetMerkleCommitment::DISCRIMINATOR),
+    ("issue_merkle_challenge", ix::IssueMerkleChallenge::DISCRIMINATOR),
+    ("submit_merkle_proof", ix::SubmitMerkleProof::DISCRIMINATOR),
+    ("submit_aggregated_proof", ix::SubmitAggregatedProof::DISCRIMINATOR),
+    ("extend_subscription", ix::ExtendSubscription::DISCRIMINATOR),
+    ("update_file_state", ix::UpdateFileState::DISCRIMINATOR),
+    ("end_subscription", ix::EndSubscription::DISCRIMINATOR),
+    ("retrieve", ix::Retrieve::DISCRIMINATOR),
+];
+
+/// Name of the program instruction encoded in `data`.
+pub fn instruction_name(data: &[u8]) -> Option<&'static str> {
+    let discriminator = data.get(..8)?;
+    INSTRUCTIONS
+        .iter()
+        .find(|(_, instruction)| instruction == discriminator)
+        .map(|(name, _)| *name)
+}
+
+/// One instruction of the program in a transaction.
+#[derive(Debug, Clone)]
+pub struct ProgramCall {
+    /// Name of the instruction, `None` if this build of the client doesn't know it.
+    pub name: Option<&'static str>,
+    pub accounts: Vec<Pubkey>,
+}
+
+/// A transaction that involved an account.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub signature: Signature,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    /// Why the transaction failed, `None` if it succeeded.
+    pub error: Option<String>,
+    /// Instructions of the program in the transaction.
+    pub calls: Vec<ProgramCall>,
+}
+
+/// A transaction of the program with its effects on the balances of its accounts.
+#[derive(Debug, Clone)]
+pub struct ProgramTransaction {
+    pub signature: Signature,
+    pub slot: u64,
+    /// Why the transaction failed, `None` if it succeeded.
+    pub error: Option<String>,
+    /// Instructions of the program in the transaction.
+    pub calls: Vec<ProgramCall>,
+    /// Lamports gained, or lost when negative, by each account of the transaction, fees included.
+    pub lamport_changes: HashMap<Pubkey, i64>,
+}
+
+/// Fetches and decodes a `Mediator` account of the program deployed at `program_id`.
+pub async fn fetch_mediator(rpc: &RpcClient, program_id: &Pubkey, pubkey: &Pubkey) -> Result<Mediator, TransactionError> {
+    let account = rpc
+        .get_account_with_commitment(pubkey, rpc.commitment())
+        .await?
+        .value
+        .filter(|account| account.owner == *program_id)
+        .ok_or(TransactionError::InvalidMediator(*pubkey))?;
+
+    Mediator::try_deserialize(&mut account.data.as_slice()).map_err(|_| TransactionError::InvalidMediator(*pubkey))
+}
+
+// `getProgramAccounts` filters matching the program contexts, only those of `client` and `server` when given
+fn program_context_filters(client: Option<&Pubkey>, server: Option<&Pubkey>) -> Vec<RpcFilterType> {
+    let mut filters = vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, ProgramContext::DISCRIMINATOR.to_vec()))];
+    if let Some(client) = client {
+        filters.push(RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+            PROGRAM_CONTEXT_CLIENT_OFFSET,
+            client.to_bytes().to_vec(),
+        )));
+    }
+    if let Some(server) = server {
+        filters.push(RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+            PROGRAM_CONTEXT_SERVER_OFFSET,
+            server.to_bytes().to_vec(),
+        )));
+    }
+    filters
+}
+
+/// Every program context of the program, or only those of `client` and `server`.
+pub async fn find_program_contexts(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    client: Option<&Pubkey>,
+    server: Option<&Pubkey>,
+) -> Result<Vec<(Pubkey, ProgramContext)>, TransactionError> {
+    let config = RpcProgramAccountsConfig {
+        filters: Some(program_context_filters(client, server)),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    let accounts = rpc.get_program_accounts_with_config(program_id, config).await?;
+
+    // Accounts of an older layout don't decode and are left out
+    Ok(accounts
+        .into_iter()
+        .filter_map(|(pubkey, account)| {
+            ProgramContext::try_deserialize(&mut account.data.as_slice())
+                .ok()
+                .map(|program_context| (pubkey, program_context))
+        })
+        .collect())
+}
+
+/// The `limit` latest transactions involving `address`, older than `before` if set, newest first.
+pub async fn history(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    address: &Pubkey,
+    before: Option<Signature>,
+    limit: usize,
+) -> Result<Vec<HistoryEntry>, TransactionError> {
+    let config = GetConfirmedSignaturesForAddress2Config {
+        before,
+        until: None,
+        limit: Some(limit.min(MAX_SIGNATURES)),
+        commitment: Some(rpc.commitment()),
+    };
+    let statuses = rpc.get_signatures_for_address_with_config(address, config).await?;
+
+    let mut entries = Vec::with_capacity(statuses.len());
+    for status in statuses {
+        let signature = Signature::from_str(&status.signature)
+            .map_err(|err| TransactionError::InvalidTransaction(format!("{}: {}", status.signature, err)))?;
+        entries.push(HistoryEntry {
+            calls: program_calls(rpc, program_id, &signature).await?,
+            signature,
+            slot: status.slot,
+            block_time: status.block_time,
+            error: status.err.map(|err| err.to_string()),
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Instructions of the program in the transaction `signature`.
+pub async fn program_calls(rpc: &RpcClient, program_id: &Pubkey, signature: &Signature) -> Result<Vec<ProgramCall>, TransactionError> {
+    Ok(program_transaction(rpc, program_id, signature).await?.calls)
+}
+
+/// Fetches the transaction `signature` with its instructions of the program and balance changes.
+pub async fn program_transaction(rpc: &RpcClient, program_id: &Pubkey, signature: &Signature) -> Result<ProgramTransaction, TransactionError> {
+    let confirmed = rpc.get_transaction(signature, UiTransactionEncoding::Base64).await?;
+    let transaction = confirmed
+        .transaction
+        .transaction
+        .decode()
+        .ok_or_else(|| TransactionError::InvalidTransaction(signature.to_string()))?;
+
+    // Accounts of address lookup tables aren't resolved; the program's transactions don't use them
+    let account_keys = transaction.message.static_account_keys();
+    let calls = transaction
+        .message
+        .instructions()
+        .iter()
+        .filter(|instruction| account_keys.get(instruction.program_id_index as usize) == Some(program_id))
+        .map(|instruction| ProgramCall {
+            name: instruction_name(&instruction.data),
+            accounts: instruction
+                .accounts
+                .iter()
+                .filter_map(|index| account_keys.get(*index as usize).copied())
+                .collect(),
+        })
+        .collect();
+
+    // Balances are listed in the order of the account keys
+    let (error, lamport_changes) = match confirmed.transaction.meta {
+        Some(meta) => {
+            let changes = account_keys
+                .iter()
+                .zip(meta.pre_balances.iter().zip(&meta.post_balances))
+                .map(|(pubkey, (pre, post))| (*pubkey, *post as i64 - *pre as i64))
+                .collect();
+            (meta.err.map(|err| err.to_string()), changes)
+        }
+        None => (None, HashMap::new()),
+    };
+
+    Ok(ProgramTransaction {
+        signature: *signature,
+        slot: confirmed.slot,
+        error,
+        calls,
+        lamport_changes,
+    })
+}
+
+/// Mediator of `program_context`, read from the `initialize` instruction that created it.
+pub async fn find_mediator(rpc: &RpcClient, program_id: &Pubkey, program_context: &Pubkey) -> Result<Option<Pubkey>, TransactionError> {
+    // The creation is the oldest transaction of the account
+    let mut before = None;
+    let mut oldest = None;
+    loop {
+        let config = GetConfirmedSignaturesForAddress2Config {
+            before,
+            until: None,
+            limit: Some(MAX_SIGNATURES),
+            commitment: Some(rpc.commitment()),
+        };
+        let statuses = rpc.get_signatures_for_address_with_config(program_context, config).await?;
+        let Some(last) = statuses.last() else { break };
+        let signature = Signature::from_str(&last.signature)
+            .map_err(|err| TransactionError::InvalidTransaction(format!("{}: {}", last.signature, err)))?;
+        oldest = Some(signature);
+        if statuses.len() < MAX_SIGNATURES {
+            break;
+        }
+        before = Some(signature);
+    }
+
+    let Some(creation) = oldest else { return Ok(None) };
+    let calls = program_calls(rpc, program_id, &creation).await?;
+
+    // Accounts of `initialize`: payer, mediator, program context, system program
+    Ok(calls
+        .iter()
+        .find(|call| call.name == Some("initialize") && call.accounts.get(2) == Some(program_context))
+        .and_then(|call| call.accounts.get(1).copied()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::AccountSerialize;
+    use solana_smart_contract::ProofKind;
+
+    fn program_context_data(client: Pubkey, server: Pubkey) -> Vec<u8> {
+        let program_context = ProgramContext {
+            is_subscription_ended: false,
+            is_server_turn: true,
+            subscription_duration: 30,
+            mediator_balance: 1_000,
+            g_norm: [1; 96],
+            v_norm: [2; 96],
+            client,
+            file_version: 0,
+            block_count: 16,
+            file_state_root: [3; 32],
+            proof_kind: ProofKind::Bls,
+            challenge_seed: [0; 32],
+            challenge_size: 0,
+            is_challenge_pending: false,
+            last_proof_digest: [0; 32],
+            proof_count: 0,
+            pop: [4; 48],
+            sectors_per_block: 16,
+            block_size: 496,
+            server,
+        };
+        let mut data = Vec::new();
+        program_context.try_serialize(&mut data).unwrap();
+        data
+    }
+
+    // Whether an account with `data` passes every filter, as the RPC node applies them
+    fn matches(filters: &[RpcFilterType], data: &[u8]) -> bool {
+        filters.iter().all(|filter| match filter {
+            RpcFilterType::Memcmp(memcmp) => memcmp.bytes_match(data),
+            other => panic!("unexpected filter {:?}", other),
+        })
+    }
+
+    #[test]
+    fn the_client_and_server_offsets_match_the_account_layout() {
+        let (client, server) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let data = program_context_data(client, server);
+
+        assert_eq!(&data[PROGRAM_CONTEXT_CLIENT_OFFSET..PROGRAM_CONTEXT_CLIENT_OFFSET + 32], client.as_ref());
+        assert_eq!(&data[PROGRAM_CONTEXT_SERVER_OFFSET..], server.as_ref());
+    }
+
+    #[test]
+    fn filters_program_contexts_by_client_and_server() {
+        let (client, server) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let filters = program_context_filters(Some(&client), None);
+        assert_eq!(filters.len(), 2);
+        assert!(matches(&filters, &program_context_data(client, server)));
+        assert!(!matches(&filters, &program_context_data(Pubkey::new_unique(), server)));
+
+        let filters = program_context_filters(None, Some(&server));
+        assert_eq!(filters.len(), 2);
+        assert!(matches(&filters, &program_context_data(client, server)));
+        assert!(!matches(&filters, &program_context_data(client, Pubkey::new_unique())));
+
+        let filters = program_context_filters(Some(&client), Some(&server));
+        assert_eq!(filters.len(), 3);
+        assert!(matches(&filters, &program_context_data(client, server)));
+        assert!(!matches(&filters, &program_context_data(server, client)));
+    }
+
+    #[test]
+    fn only_program_contexts_pass_the_discriminator_filter() {
+        let filters = program_context_filters(None, None);
+
+        assert_eq!(filters.len(), 1);
+        assert!(matches(&filters, &program_context_data(Pubkey::new_unique(), Pubkey::new_unique())));
+
+        let mut mediator = Vec::new();
+        Mediator { balance: 1_000 }.try_serialize(&mut mediator).unwrap();
+        assert!(!matches(&filters, &mediator));
+        assert!(!matches(&filters, &[]));
+    }
+
+    #[test]
+    fn names_the_instructions_of_the_program() {
+        for (name, discriminator) in INSTRUCTIONS {
+            let mut data = discriminator.to_vec();
+            data.extend_from_slice(&[7; 40]);
+            assert_eq!(instruction_name(&data), Some(name));
+        }
+
+        assert_eq!(instruction_name(&ix::Retrieve::DISCRIMINATOR[..7]), None);
+        assert_eq!(instruction_name(&[0; 8]), None);
+        assert_eq!(instruction_name(&Mediator::DISCRIMINATOR), None);
+    }
+}
@@ -0,0 +1,439 @@
+//! Signers of the Solana transactions sent on behalf of API users.
+//!
+//! Private keys never cross the HTTP API. Requests name their signers by keystore id or by
+//! public key, and the keystore resolves them to a `TransactionSigner`: either a keypair file
+//! on the local disk, or a remote signer service holding the key.
+//!
+//! The keystore is a directory:
+//!
+//! - `<id>.json`: a Solana keypair file, as written by `solana-keygen`,
+//! - `remote_signers.json`: `[{"id", "pubkey", "url"}]`, the keys held by remote signers.
+//!
+//! Whoever reaches the HTTP API can spend from every key of the keystore, and `POST /signers`
+//! has no authentication of its own: the API must stay bound to a loopback address, or behind
+//! a proxy that authenticates its users.
+//!
+//! A remote signer receives `POST <url>` with `{"pubkey", "message"}`, the message being the
+//! hex encoded serialized transaction message, and answers `{"signature"}` in base58. Its
+//! signatures are checked against the expected public key before use.
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, write_keypair, Keypair, Signature};
+use solana_sdk::signer::Signer;
+use solana_sdk::transaction::Transaction;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+const REMOTE_SIGNERS_FILE: &str = "remote_signers.json";
+
+#[derive(Debug)]
+pub enum SignerError {
+    /// No signer has this id or public key.
+    UnknownSigner(String),
+    /// The id can't be used as a keypair file name, or is already taken.
+    InvalidId(String),
+    InvalidKeystore(String),
+    /// The remote signer failed or answered with an invalid signature.
+    Remote(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for SignerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignerError::UnknownSigner(reference) => write!(f, "Unknown signer {}", reference),
+            SignerError::InvalidId(id) => write!(f, "Invalid signer id {}", id),
+            SignerError::InvalidKeystore(reason) => write!(f, "Invalid keystore: {}", reason),
+            SignerError::Remote(reason) => write!(f, "Remote signer error: {}", reason),
+            SignerError::Io(err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SignerError {}
+
+impl From<io::Error> for SignerError {
+    fn from(err: io::Error) -> Self {
+        SignerError::Io(err)
+    }
+}
+
+pub type SignFuture<'a> = Pin<Box<dyn Future<Output = Result<Signature, SignerError>> + Send + 'a>>;
+
+/// Signs transaction messages for one public key.
+///
+/// Signing is asynchronous so that remote signers don't block the runtime.
+pub trait TransactionSigner: Send + Sync {
+    fn pubkey(&self) -> Pubkey;
+
+    fn sign_message<'a>(&'a self, message: &'a [u8]) -> SignFuture<'a>;
+}
+
+impl TransactionSigner for Keypair {
+    fn pubkey(&self) -> Pubkey {
+        Signer::pubkey(self)
+    }
+
+    fn sign_message<'a>(&'a self, message: &'a [u8]) -> SignFuture<'a> {
+        Box::pin(async move { Ok(Signer::sign_message(self, message)) })
+    }
+}
+
+/// A keypair held in memory, loaded from a keypair file of the keystore.
+pub struct FileSigner {
+    keypair: Keypair,
+}
+
+impl FileSigner {
+    pub fn new(keypair: Keypair) -> Self {
+        FileSigner { keypair }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, SignerError> {
+        let keypair = read_keypair_file(path)
+            .map_err(|err| SignerError::InvalidKeystore(format!("{}: {}", path.display(), err)))?;
+        Ok(FileSigner { keypair })
+    }
+}
+
+impl TransactionSigner for FileSigner {
+    fn pubkey(&self) -> Pubkey {
+        Signer::pubkey(&self.keypair)
+    }
+
+    fn sign_message<'a>(&'a self, message: &'a [u8]) -> SignFuture<'a> {
+        Box::pin(async move { Ok(Signer::sign_message(&self.keypair, message)) })
+    }
+}
+
+#[derive(Serialize)]
+struct RemoteSignRequest {
+    pubkey: String,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct RemoteSignResponse {
+    signature: String,
+}
+
+/// A key held by a remote signer service.
+pub struct RemoteSigner {
+    pubkey: Pubkey,
+    url: String,
+    http: reqwest::Client,
+}
+
+impl RemoteSigner {
+    pub fn new(pubkey: Pubkey, url: String, timeout: Duration) -> Result<Self, SignerError> {
+        let http = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|err| SignerError::Remote(err.to_string()))?;
+        Ok(RemoteSigner { pubkey, url, http })
+    }
+
+    async fn request_signature(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        let request = RemoteSignRequest {
+            pubkey: self.pubkey.to_string(),
+            message: hex::encode(message),
+        };
+        let body = serde_json::to_vec(&request).map_err(|err| SignerError::Remote(err.to_string()))?;
+
+        let response = self
+            .http
+            .post(&self.url)
+            .header("content-type", "application/json")
+            .body(body)
+            .send()
+            .await
+            .map_err(|err| SignerError::Remote(err.to_string()))?;
+        if !response.status().is_success() {
+            return Err(SignerError::Remote(format!("the signer answered {}", response.status())));
+        }
+
+        let body = response.bytes().await.map_err(|err| SignerError::Remote(err.to_string()))?;
+        let response: RemoteSignResponse =
+            serde_json::from_slice(&body).map_err(|err| SignerError::Remote(err.to_string()))?;
+        let signature = Signature::from_str(&response.signature).map_err(|err| SignerError::Remote(err.to_string()))?;
+
+        // Never put a signature from another key, or over another message, in a transaction
+        if !signature.verify(self.pubkey.as_ref(), message) {
+            return Err(SignerError::Remote(format!("invalid signature for {}", self.pubkey)));
+        }
+
+        Ok(signature)
+    }
+}
+
+impl TransactionSigner for RemoteSigner {
+    fn pubkey(&self) -> Pubkey {
+        self.pubkey
+    }
+
+    fn sign_message<'a>(&'a self, message: &'a [u8]) -> SignFuture<'a> {
+        Box::pin(self.request_signature(message))
+    }
+}
+
+/// One entry of `remote_signers.json`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RemoteSignerConfig {
+    pub id: String,
+    pub pubkey: String,
+    pub url: String,
+    #[serde(default = "default_remote_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_remote_timeout_secs() -> u64 {
+    10
+}
+
+/// Signers known to the API, by id.
+pub struct Keystore {
+    dir: PathBuf,
+    signers: BTreeMap<String, Arc<dyn TransactionSigner>>,
+}
+
+impl Keystore {
+    /// Loads every keypair file and remote signer of the keystore directory, creating it if needed.
+    pub fn open(dir: &Path) -> Result<Self, SignerError> {
+        fs::create_dir_all(dir)?;
+        let mut keystore = Keystore {
+            dir: dir.to_path_buf(),
+            signers: BTreeMap::new(),
+        };
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("json")
+                || path.file_name().and_then(|name| name.to_str()) == Some(REMOTE_SIGNERS_FILE)
+            {
+                continue;
+            }
+
+            if let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) {
+                keystore.signers.insert(id.to_string(), Arc::new(FileSigner::from_file(&path)?));
+            }
+        }
+
+        let remote_signers_path = dir.join(REMOTE_SIGNERS_FILE);
+        if remote_signers_path.exists() {
+            let configs: Vec<RemoteSignerConfig> = serde_json::from_slice(&fs::read(&remote_signers_path)?)
+                .map_err(|err| SignerError::InvalidKeystore(format!("{}: {}", REMOTE_SIGNERS_FILE, err)))?;
+            for config in configs {
+                let pubkey = Pubkey::from_str(&config.pubkey)
+                    .map_err(|err| SignerError::InvalidKeystore(format!("remote signer {}: {}", config.id, err)))?;
+                let signer = RemoteSigner::new(pubkey, config.url, Duration::from_secs(config.timeout_secs))?;
+                if keystore.signers.insert(config.id.clone(), Arc::new(signer)).is_some() {
+                    return Err(SignerError::InvalidKeystore(format!("duplicate signer id {}", config.id)));
+                }
+            }
+        }
+
+        Ok(keystore)
+    }
+
+    /// Generates a new keypair, saved in the keystore under `id`.
+    pub fn generate(&mut self, id: &str) -> Result<Pubkey, SignerError> {
+        let path = self.new_keypair_path(id)?;
+        let keypair = create_keypair_file(id, &path)?;
+        self.insert_generated(id, keypair)
+    }
+
+    /// Path of the keypair file of a new signer `id`, once checked to be a valid and free id.
+    ///
+    /// Followed by `create_keypair_file` and `insert_generated`, generates a signer without
+    /// holding the keystore while its file is written.
+    pub fn new_keypair_path(&self, id: &str) -> Result<PathBuf, SignerError> {
+        let is_valid_id = !id.is_empty()
+            && id.len() <= 64
+            && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            && format!("{}.json", id) != REMOTE_SIGNERS_FILE;
+        if !is_valid_id || self.signers.contains_key(id) {
+            return Err(SignerError::InvalidId(id.to_string()));
+        }
+        Ok(self.dir.join(format!("{}.json", id)))
+    }
+
+    /// Adds a keypair written by `create_keypair_file` under `id`.
+    pub fn insert_generated(&mut self, id: &str, keypair: Keypair) -> Result<Pubkey, SignerError> {
+        if self.signers.contains_key(id) {
+            return Err(SignerError::InvalidId(id.to_string()));
+        }
+        let pubkey = Signer::pubkey(&keypair);
+        self.signers.insert(id.to_string(), Arc::new(FileSigner::new(keypair)));
+        Ok(pubkey)
+    }
+
+    /// Finds a signer by id, or by public key in base58.
+    pub fn get(&self, reference: &str) -> Result<Arc<dyn TransactionSigner>, SignerError> {
+        if let Some(signer) = self.signers.get(reference) {
+            return Ok(Arc::clone(signer));
+        }
+
+        let pubkey = Pubkey::from_str(reference).map_err(|_| SignerError::UnknownSigner(reference.to_string()))?;
+        self.signers
+            .values()
+            .find(|signer| signer.pubkey() == pubkey)
+            .cloned()
+            .ok_or_else(|| SignerError::UnknownSigner(reference.to_string()))
+    }
+
+    /// Public key of a signer reference, or of any account given by its public key.
+    pub fn resolve_pubkey(&self, reference: &str) -> Result<Pubkey, SignerError> {
+        match self.signers.get(reference) {
+            Some(signer) => Ok(signer.pubkey()),
+            None => Pubkey::from_str(reference).map_err(|_| SignerError::UnknownSigner(reference.to_string())),
+        }
+    }
+
+    /// Ids and public keys of every signer.
+    pub fn list(&self) -> Vec<(String, Pubkey)> {
+        self.signers.iter().map(|(id, signer)| (id.clone(), signer.pubkey())).collect()
+    }
+}
+
+/// Generates a keypair and writes it to `path`, a new file only its owner can read.
+///
+/// An existing file is never overwritten: two concurrent generations of `id` fail with
+/// `InvalidId` instead of losing a key.
+pub fn create_keypair_file(id: &str, path: &Path) -> Result<Keypair, SignerError> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path).map_err(|err| match err.kind() {
+        io::ErrorKind::AlreadyExists => SignerError::InvalidId(id.to_string()),
+        _ => SignerError::Io(err),
+    })?;
+
+    let keypair = Keypair::new();
+    write_keypair(&keypair, &mut file).map_err(|err| SignerError::Io(io::Error::other(err.to_string())))?;
+    Ok(keypair)
+}
+
+/// Signs `transaction` with `signers`, which must cover every required signature.
+pub async fn sign_transaction(transaction: &mut Transaction, signers: &[&dyn TransactionSigner]) -> Result<(), SignerError> {
+    let message = transaction.message_data();
+    let required = transaction.message.header.num_required_signatures as usize;
+
+    for (position, pubkey) in transaction.message.account_keys[..required].iter().enumerate() {
+        let signer = signers
+            .iter()
+            .find(|signer| signer.pubkey() == *pubkey)
+            .ok_or_else(|| SignerError::UnknownSigner(pubkey.to_string()))?;
+        transaction.signatures[position] = signer.sign_message(&message).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::message::Message;
+    use solana_sdk::system_instruction;
+
+    // A fresh keystore directory for one test
+    fn keystore_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("keystore-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn generated_signers_are_found_and_reloaded() {
+        let dir = keystore_dir("reload");
+        let mut keystore = Keystore::open(&dir).unwrap();
+        let pubkey = keystore.generate("client").unwrap();
+
+        assert_eq!(keystore.get("client").unwrap().pubkey(), pubkey);
+        assert_eq!(keystore.get(&pubkey.to_string()).unwrap().pubkey(), pubkey);
+        assert_eq!(keystore.resolve_pubkey("client").unwrap(), pubkey);
+
+        let reopened = Keystore::open(&dir).unwrap();
+        assert_eq!(reopened.list(), vec![("client".to_string(), pubkey)]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_and_taken_ids() {
+        let dir = keystore_dir("ids");
+        let mut keystore = Keystore::open(&dir).unwrap();
+        keystore.generate("server").unwrap();
+
+        for id in ["", "../escape", "with space", "remote_signers", &"x".repeat(65), "server"] {
+            assert!(matches!(keystore.generate(id), Err(SignerError::InvalidId(_))), "{:?}", id);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn never_overwrites_a_keypair_file() {
+        let dir = keystore_dir("overwrite");
+        let mut keystore = Keystore::open(&dir).unwrap();
+
+        // Two requests for the same id, both checked before either file is written
+        let path = keystore.new_keypair_path("client").unwrap();
+        let keypair = create_keypair_file("client", &path).unwrap();
+        assert!(matches!(create_keypair_file("client", &path), Err(SignerError::InvalidId(_))));
+
+        let pubkey = keystore.insert_generated("client", keypair.insecure_clone()).unwrap();
+        assert!(matches!(keystore.insert_generated("client", keypair), Err(SignerError::InvalidId(_))));
+        assert_eq!(Signer::pubkey(&read_keypair_file(&path).unwrap()), pubkey);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn loads_remote_signers_and_resolves_unknown_accounts() {
+        let dir = keystore_dir("remote");
+        fs::create_dir_all(&dir).unwrap();
+        let remote = Pubkey::new_unique();
+        let configs = vec![RemoteSignerConfig {
+            id: "hsm".to_string(),
+            pubkey: remote.to_string(),
+            url: "http://127.0.0.1:1/sign".to_string(),
+            timeout_secs: 1,
+        }];
+        fs::write(dir.join(REMOTE_SIGNERS_FILE), serde_json::to_vec(&configs).unwrap()).unwrap();
+
+        let keystore = Keystore::open(&dir).unwrap();
+        assert_eq!(keystore.get("hsm").unwrap().pubkey(), remote);
+
+        // Accounts that don't sign can be given by public key only
+        let other = Pubkey::new_unique();
+        assert_eq!(keystore.resolve_pubkey(&other.to_string()).unwrap(), other);
+        assert!(matches!(keystore.get(&other.to_string()), Err(SignerError::UnknownSigner(_))));
+        assert!(matches!(keystore.resolve_pubkey("nobody"), Err(SignerError::UnknownSigner(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn signs_every_required_signature() {
+        let payer = Keypair::new();
+        let sender = Keypair::new();
+        let instruction = system_instruction::transfer(&Signer::pubkey(&sender), &Signer::pubkey(&payer), 1);
+        let message = Message::new(&[instruction], Some(&Signer::pubkey(&payer)));
+
+        let mut transaction = Transaction::new_unsigned(message.clone());
+        let missing = sign_transaction(&mut transaction, &[&payer as &dyn TransactionSigner]).await;
+        assert!(matches!(missing, Err(SignerError::UnknownSigner(_))));
+
+        let mut transaction = Transaction::new_unsigned(message);
+        transaction.message.recent_blockhash = Hash::new_unique();
+        sign_transaction(&mut transaction, &[&sender as &dyn TransactionSigner, &payer]).await.unwrap();
+        assert!(transaction.verify().is_ok());
+    }
+}
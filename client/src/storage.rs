@@ -0,0 +1,231 @@
+//! On-disk storage of the files held by a storage server.
+//!
+//! Every file lives in its own directory under the store root, named after the hex encoded
+//! file name:
+//!
+//! - `params`: the PoR public parameters of the file, as a `WirePublicParams` message,
+//! - `blocks`: the blocks, concatenated, `block_size` bytes each,
+//! - `tags`: the 48-byte compressed tags, in block order.
+//!
+//! Blocks and tags are read on demand, so proving never loads a whole file in memory. In
+//! Merkle proof mode the tree is built by streaming the blocks, and answers read only the
+//! challenged leaves.
+
+use crate::merkle::{leaf_hash, MerkleTree, MERKLE_LEAF_SIZE};
+use crate::por::{PorPublicKey, TAG_SIZE};
+use crate::proof::{ProofError, StoredFile};
+use crate::wire::{WireError, WireMessage, WirePublicParams, WireTags};
+use bls12_381::G1Affine;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const PARAMS_FILE: &str = "params";
+const BLOCKS_FILE: &str = "blocks";
+const TAGS_FILE: &str = "tags";
+
+#[derive(Debug)]
+pub enum StorageError {
+    /// No file, or no public parameters, were uploaded under this name.
+    UnknownFile(String),
+    /// The uploaded blocks are not a whole number of blocks.
+    InvalidBlocks { block_size: usize, len: usize },
+    Wire(WireError),
+    Io(io::Error),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::UnknownFile(name) => write!(f, "Unknown file {}", name),
+            StorageError::InvalidBlocks { block_size, len } => write!(
+                f,
+                "Invalid blocks: {} bytes is not a multiple of the block size {}",
+                len, block_size
+            ),
+            StorageError::Wire(err) => write!(f, "{}", err),
+            StorageError::Io(err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<io::Error> for StorageError {
+    fn from(err: io::Error) -> Self {
+        StorageError::Io(err)
+    }
+}
+
+impl From<WireError> for StorageError {
+    fn from(err: WireError) -> Self {
+        StorageError::Wire(err)
+    }
+}
+
+/// Directory holding every stored file.
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    root: PathBuf,
+}
+
+impl FileStore {
+    pub fn open(root: &Path) -> Result<Self, StorageError> {
+        fs::create_dir_all(root)?;
+        Ok(FileStore { root: root.to_path_buf() })
+    }
+
+    fn file_dir(&self, file_name: &[u8]) -> PathBuf {
+        self.root.join(hex::encode(file_name))
+    }
+
+    /// Stores the public parameters of a file; must come before its blocks.
+    pub fn put_params(&self, file_name: &[u8], params: &WirePublicParams) -> Result<(), StorageError> {
+        PorPublicKey::try_from(params)?;
+
+        let dir = self.file_dir(file_name);
+        fs::create_dir_all(&dir)?;
+        write_atomically(&dir.join(PARAMS_FILE), &params.to_wire())
+    }
+
+    /// Replaces the blocks of a file.
+    pub fn put_blocks(&self, file_name: &[u8], blocks: &[u8]) -> Result<(), StorageError> {
+        let public = self.public_key(file_name)?;
+        if !blocks.len().is_multiple_of(public.block_size()) {
+            return Err(StorageError::InvalidBlocks {
+                block_size: public.block_size(),
+                len: blocks.len(),
+            });
+        }
+
+        write_atomically(&self.file_dir(file_name).join(BLOCKS_FILE), blocks)
+    }
+
+    /// Writes tags starting at `tags.first_index`, so tags can be uploaded in chunks.
+    pub fn put_tags(&self, tags: &WireTags) -> Result<(), StorageError> {
+        self.public_key(&tags.file_name)?;
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.file_dir(&tags.file_name).join(TAGS_FILE))?;
+        file.seek(SeekFrom::Start(tags.first_index * TAG_SIZE as u64))?;
+        for tag in &tags.tags {
+            file.write_all(tag)?;
+        }
+        file.sync_all()?;
+
+        Ok(())
+    }
+
+    pub fn public_key(&self, file_name: &[u8]) -> Result<PorPublicKey, StorageError> {
+        let params = match fs::read(self.file_dir(file_name).join(PARAMS_FILE)) {
+            Ok(params) => params,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(StorageError::UnknownFile(String::from_utf8_lossy(file_name).into_owned()))
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(PorPublicKey::try_from(&WirePublicParams::from_wire(&params)?)?)
+    }
+
+    /// Opens a stored file for proving.
+    pub fn file(&self, file_name: &[u8]) -> Result<DiskFile, StorageError> {
+        let public = self.public_key(file_name)?;
+        let dir = self.file_dir(file_name);
+
+        Ok(DiskFile {
+            block_size: public.block_size(),
+            blocks_path: dir.join(BLOCKS_FILE),
+            tags_path: dir.join(TAGS_FILE),
+        })
+    }
+}
+
+/// A stored file, read block by block.
+#[derive(Debug, Clone)]
+pub struct DiskFile {
+    block_size: usize,
+    blocks_path: PathBuf,
+    tags_path: PathBuf,
+}
+
+impl DiskFile {
+    pub fn block_count(&self) -> Result<u64, ProofError> {
+        let len = fs::metadata(&self.blocks_path).map_err(ProofError::Io)?.len();
+        Ok(len / self.block_size as u64)
+    }
+
+    /// Builds the Merkle tree over the blocks cut into leaves, as `merkle::file_leaves` does.
+    pub fn merkle_tree(&self) -> Result<MerkleTree, ProofError> {
+        let mut reader = BufReader::new(File::open(&self.blocks_path)?);
+        let mut leaf_hashes = Vec::new();
+        let mut leaf = Vec::with_capacity(MERKLE_LEAF_SIZE);
+        loop {
+            leaf.clear();
+            (&mut reader).take(MERKLE_LEAF_SIZE as u64).read_to_end(&mut leaf)?;
+            if leaf.is_empty() {
+                break;
+            }
+            leaf.resize(MERKLE_LEAF_SIZE, 0);
+            leaf_hashes.push(leaf_hash(&leaf));
+        }
+
+        Ok(MerkleTree::from_leaf_hashes(leaf_hashes))
+    }
+
+    /// Reads Merkle leaf `index`, the last one padded with zeros.
+    pub fn merkle_leaf(&self, index: u64) -> Result<Vec<u8>, ProofError> {
+        let mut file = File::open(&self.blocks_path)?;
+        file.seek(SeekFrom::Start(index * MERKLE_LEAF_SIZE as u64))?;
+
+        let mut leaf = Vec::with_capacity(MERKLE_LEAF_SIZE);
+        file.take(MERKLE_LEAF_SIZE as u64).read_to_end(&mut leaf)?;
+        if leaf.is_empty() {
+            return Err(ProofError::MissingBlock(index));
+        }
+        leaf.resize(MERKLE_LEAF_SIZE, 0);
+        Ok(leaf)
+    }
+}
+
+impl StoredFile for DiskFile {
+    fn block(&self, index: u64) -> Result<Vec<u8>, ProofError> {
+        let mut block = vec![0u8; self.block_size];
+        read_at(&self.blocks_path, index * self.block_size as u64, &mut block).map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound | io::ErrorKind::UnexpectedEof => ProofError::MissingBlock(index),
+            _ => ProofError::Io(err),
+        })?;
+        Ok(block)
+    }
+
+    fn tag(&self, index: u64) -> Result<G1Affine, ProofError> {
+        let mut tag = [0u8; TAG_SIZE];
+        read_at(&self.tags_path, index * TAG_SIZE as u64, &mut tag).map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound | io::ErrorKind::UnexpectedEof => ProofError::MissingBlock(index),
+            _ => ProofError::Io(err),
+        })?;
+        Option::from(G1Affine::from_compressed(&tag)).ok_or(ProofError::InvalidTag(index))
+    }
+}
+
+fn read_at(path: &Path, offset: u64, buffer: &mut [u8]) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buffer)
+}
+
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), StorageError> {
+    let temp_path = path.with_extension("tmp");
+    {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+    }
+    fs::rename(&temp_path, path)?;
+
+    Ok(())
+}
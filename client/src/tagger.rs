@@ -0,0 +1,327 @@
+//! Streaming tagging pipeline for files larger than memory.
+//!
+//! The input is read through `std::io::Read` one chunk at a time. Every chunk is optionally
+//! erasure-coded, split into blocks, tagged on a thread pool and written out before the next
+//! chunk is read, so memory use is bounded by the chunk size whatever the file size.
+//! Encoded blocks and their tags (48-byte compressed G1 points, in block order) are emitted
+//! to two separate writers. All blocks are tagged at version 0.
+
+use crate::erasure::{ErasureCoder, ErasureError};
+use crate::por::{PorKeyPair, TAG_SIZE};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::fmt;
+use std::io::{self, Read, Write};
+
+/// Number of blocks tagged per chunk when no erasure coder is configured.
+pub const DEFAULT_CHUNK_BLOCKS: usize = 1024;
+
+#[derive(Debug)]
+pub enum TaggerError {
+    /// The tagger configuration is inconsistent.
+    InvalidParameters(String),
+    Io(io::Error),
+    Erasure(ErasureError),
+}
+
+impl fmt::Display for TaggerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaggerError::InvalidParameters(reason) => write!(f, "Invalid tagger parameters: {}", reason),
+            TaggerError::Io(err) => write!(f, "I/O error: {}", err),
+            TaggerError::Erasure(err) => write!(f, "Erasure coding error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for TaggerError {}
+
+impl From<io::Error> for TaggerError {
+    fn from(err: io::Error) -> Self {
+        TaggerError::Io(err)
+    }
+}
+
+impl From<ErasureError> for TaggerError {
+    fn from(err: ErasureError) -> Self {
+        TaggerError::Erasure(err)
+    }
+}
+
+/// Progress reported after every chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaggingProgress {
+    pub bytes_read: u64,
+    pub blocks_tagged: u64,
+}
+
+/// Result of a complete tagging run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaggingSummary {
+    /// Length of the original (non encoded) input.
+    pub original_len: u64,
+    /// Number of blocks written, parity blocks included.
+    pub block_count: u64,
+}
+
+/// Reads, encodes and tags a file chunk by chunk.
+pub struct StreamingTagger<'a> {
+    keys: &'a PorKeyPair,
+    file_name: Vec<u8>,
+    coder: Option<ErasureCoder>,
+    chunk_blocks: usize,
+    pool: ThreadPool,
+}
+
+impl<'a> StreamingTagger<'a> {
+    /// Creates a tagger using `threads` worker threads (0 lets rayon pick one per core).
+    pub fn new(keys: &'a PorKeyPair, file_name: &[u8], threads: usize) -> Result<Self, TaggerError> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(|err| TaggerError::InvalidParameters(err.to_string()))?;
+
+        Ok(StreamingTagger {
+            keys,
+            file_name: file_name.to_vec(),
+            coder: None,
+            chunk_blocks: DEFAULT_CHUNK_BLOCKS,
+            pool,
+        })
+    }
+
+    /// Erasure-codes the input before tagging. The coder block size must match the key block size.
+    pub fn with_erasure_coder(mut self, coder: ErasureCoder) -> Result<Self, TaggerError> {
+        if coder.block_size() != self.keys.public.block_size() {
+            return Err(TaggerError::InvalidParameters(format!(
+                "erasure coder block size {} does not match the key block size {}",
+                coder.block_size(),
+                self.keys.public.block_size()
+            )));
+        }
+
+        self.coder = Some(coder);
+        Ok(self)
+    }
+
+    /// Sets how many blocks are read and tagged at once (rounded up to whole stripes when encoding).
+    pub fn with_chunk_blocks(mut self, chunk_blocks: usize) -> Result<Self, TaggerError> {
+        if chunk_blocks == 0 {
+            return Err(TaggerError::InvalidParameters("chunk size must be positive".to_string()));
+        }
+
+        self.chunk_blocks = chunk_blocks;
+        Ok(self)
+    }
+
+    /// Number of input bytes consumed per chunk.
+    fn chunk_len(&self) -> usize {
+        match &self.coder {
+            Some(coder) => self.chunk_blocks.div_ceil(coder.data_shards()) * coder.stripe_data_len(),
+            None => self.chunk_blocks * self.keys.public.block_size(),
+        }
+    }
+
+    /// Streams `input` through the pipeline, writing blocks to `blocks_out` and tags to `tags_out`.
+    pub fn run<R, B, T, P>(
+        &self,
+        mut input: R,
+        mut blocks_out: B,
+        mut tags_out: T,
+        mut progress: P,
+    ) -> Result<TaggingSummary, TaggerError>
+    where
+        R: Read,
+        B: Write,
+        T: Write,
+        P: FnMut(&TaggingProgress),
+    {
+        let mut buffer = vec![0u8; self.chunk_len()];
+        let mut status = TaggingProgress { bytes_read: 0, blocks_tagged: 0 };
+
+        loop {
+            let read = read_chunk(&mut input, &mut buffer)?;
+            // An empty input still produces one (all padding) stripe when encoding
+            if read == 0 && (status.bytes_read > 0 || self.coder.is_none()) {
+                break;
+            }
+
+            let blocks = self.split_chunk(&buffer[..read])?;
+            let tags = self.tag_blocks(status.blocks_tagged, &blocks);
+
+            for block in &blocks {
+                blocks_out.write_all(block)?;
+            }
+            for tag in &tags {
+                tags_out.write_all(tag)?;
+            }
+
+            status.bytes_read += read as u64;
+            status.blocks_tagged += blocks.len() as u64;
+            progress(&status);
+
+            if read < buffer.len() {
+                break;
+            }
+        }
+
+        blocks_out.flush()?;
+        tags_out.flush()?;
+
+        Ok(TaggingSummary {
+            original_len: status.bytes_read,
+            block_count: status.blocks_tagged,
+        })
+    }
+
+    /// Turns a chunk of input into full-size blocks, erasure-coding it when configured.
+    fn split_chunk(&self, chunk: &[u8]) -> Result<Vec<Vec<u8>>, TaggerError> {
+        match &self.coder {
+            Some(coder) => {
+                if chunk.is_empty() {
+                    return Ok(coder.encode_stripe(chunk)?);
+                }
+
+                let mut blocks = Vec::new();
+                for stripe in chunk.chunks(coder.stripe_data_len()) {
+                    blocks.extend(coder.encode_stripe(stripe)?);
+                }
+                Ok(blocks)
+            }
+            None => {
+                let block_size = self.keys.public.block_size();
+                Ok(chunk
+                    .chunks(block_size)
+                    .map(|block| {
+                        let mut padded = block.to_vec();
+                        padded.resize(block_size, 0);
+                        padded
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    /// Tags blocks in parallel; `first_index` is the index of the first block in the file.
+    fn tag_blocks(&self, first_index: u64, blocks: &[Vec<u8>]) -> Vec<[u8; TAG_SIZE]> {
+        self.pool.install(|| {
+            blocks
+                .par_iter()
+                .enumerate()
+                .map(|(offset, block)| {
+                    self.keys
+                        .tag_block(&self.file_name, first_index + offset as u64, 0, block)
+                        .to_compressed()
+                })
+                .collect()
+        })
+    }
+}
+
+/// Fills `buffer` as far as the reader allows, returning fewer bytes only at end of input.
+fn read_chunk<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::test_support::{challenge, keys};
+    use crate::proof::{prove, verify, MemoryFile};
+    use bls12_381::G1Affine;
+
+    fn tag(tagger: &StreamingTagger, input: &[u8]) -> (TaggingSummary, MemoryFile) {
+        let (mut blocks, mut tags) = (Vec::new(), Vec::new());
+        let summary = tagger.run(input, &mut blocks, &mut tags, |_| {}).unwrap();
+
+        let block_size = tagger.keys.public.block_size();
+        let file = MemoryFile {
+            blocks: blocks.chunks(block_size).map(<[u8]>::to_vec).collect(),
+            tags: tags
+                .chunks(TAG_SIZE)
+                .map(|tag| G1Affine::from_compressed(tag.try_into().unwrap()).unwrap())
+                .collect(),
+        };
+        (summary, file)
+    }
+
+    #[test]
+    fn tagged_blocks_prove_and_verify() {
+        let keys = keys(7);
+        let tagger = StreamingTagger::new(&keys, b"file", 2).unwrap().with_chunk_blocks(2).unwrap();
+        let input: Vec<u8> = (0..62 * 5 + 10).map(|byte| byte as u8).collect();
+
+        let (summary, file) = tag(&tagger, &input);
+        assert_eq!(summary, TaggingSummary { original_len: input.len() as u64, block_count: 6 });
+        assert_eq!(file.tags[5], keys.tag_block(b"file", 5, 0, &file.blocks[5]));
+        // The last block is padded with zeros
+        assert_eq!(file.blocks[5][10..], [0; 52]);
+
+        let challenge = challenge(b"file", 0..summary.block_count);
+        let proof = prove(&keys.public, &challenge, &file).unwrap();
+        assert!(verify(&keys.public, &challenge, &proof));
+    }
+
+    #[test]
+    fn tags_the_parity_blocks_of_encoded_files() {
+        let keys = keys(7);
+        let coder = ErasureCoder::new(2, 1, keys.public.block_size()).unwrap();
+        let tagger = StreamingTagger::new(&keys, b"file", 2)
+            .unwrap()
+            .with_erasure_coder(coder)
+            .unwrap()
+            .with_chunk_blocks(2)
+            .unwrap();
+        let input = vec![9u8; 62 * 5];
+
+        let mut reported = Vec::new();
+        let (mut blocks, mut tags) = (Vec::new(), Vec::new());
+        let summary = tagger.run(input.as_slice(), &mut blocks, &mut tags, |progress| reported.push(*progress)).unwrap();
+        // Three stripes of two data blocks and one parity block, one stripe per chunk
+        assert_eq!(summary.block_count, 9);
+        assert_eq!(reported.len(), 3);
+        assert_eq!(reported[2], TaggingProgress { bytes_read: 62 * 5, blocks_tagged: 9 });
+
+        let (_, file) = tag(&tagger, &input);
+        let challenge = challenge(b"file", 0..summary.block_count);
+        let proof = prove(&keys.public, &challenge, &file).unwrap();
+        assert!(verify(&keys.public, &challenge, &proof));
+    }
+
+    #[test]
+    fn a_corrupted_block_fails_verification() {
+        let keys = keys(7);
+        let tagger = StreamingTagger::new(&keys, b"file", 1).unwrap();
+        let (summary, mut file) = tag(&tagger, &[1u8; 62 * 3]);
+        file.blocks[1][0] ^= 1;
+
+        let challenge = challenge(b"file", 0..summary.block_count);
+        let proof = prove(&keys.public, &challenge, &file).unwrap();
+        assert!(!verify(&keys.public, &challenge, &proof));
+    }
+
+    #[test]
+    fn rejects_inconsistent_settings() {
+        let keys = keys(7);
+        let coder = ErasureCoder::new(2, 1, 31).unwrap();
+
+        assert!(matches!(
+            StreamingTagger::new(&keys, b"file", 1).unwrap().with_erasure_coder(coder),
+            Err(TaggerError::InvalidParameters(_))
+        ));
+        assert!(matches!(
+            StreamingTagger::new(&keys, b"file", 1).unwrap().with_chunk_blocks(0),
+            Err(TaggerError::InvalidParameters(_))
+        ));
+    }
+}
@@ -0,0 +1,269 @@
+//! Instructions of the mediator program, sent through the nonblocking RPC client.
+//!
+//! anchor-client's request builder holds `&dyn Signer`, which is not `Sync`, so its futures
+//! can't be awaited inside warp handlers or spawned tasks. These helpers build the
+//! instructions from the program's `accounts` and `instruction` types instead, and sign and
+//! send them with `RpcClient`, whose futures are `Send`.
+
+use crate::proof::Proof;
+use crate::signer::{sign_transaction, SignerError, TransactionSigner};
+use crate::wire::WireProof;
+use anchor_client::solana_client::client_error::{ClientError as RpcError, ClientErrorKind as RpcErrorKind};
+use anchor_client::solana_client::nonblocking::rpc_client::RpcClient;
+use anchor_client::solana_sdk::instruction::{AccountMeta, Instruction};
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anchor_client::solana_sdk::signature::{Keypair, Signature};
+use anchor_client::solana_sdk::transaction::Transaction;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_smart_contract::{accounts, instruction as ix, MerkleLeafProof, ProgramContext};
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum TransactionError {
+    Rpc(Box<RpcError>),
+    Signer(SignerError),
+    /// The account is missing, not owned by the program, or not a `ProgramContext`.
+    InvalidAccount(Pubkey),
+    /// The account is missing, not owned by the program, or not a `Mediator`.
+    InvalidMediator(Pubkey),
+    /// The node returned a transaction or signature that doesn't decode.
+    InvalidTransaction(String),
+    /// The transaction was neither confirmed nor expired in time; it may still land.
+    Timeout(Signature),
+    /// The blockhash of the transaction expired before it was confirmed, on every attempt.
+    Expired(Signature),
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionError::Rpc(err) => write!(f, "RPC error: {}", err),
+            TransactionError::Signer(err) => write!(f, "{}", err),
+            TransactionError::InvalidAccount(pubkey) => write!(f, "{} is not a program context", pubkey),
+            TransactionError::InvalidMediator(pubkey) => write!(f, "{} is not a mediator", pubkey),
+            TransactionError::InvalidTransaction(reason) => write!(f, "Invalid transaction: {}", reason),
+            TransactionError::Timeout(signature) => write!(f, "Transaction {} not confirmed in time", signature),
+            TransactionError::Expired(signature) => write!(f, "Transaction {} expired before its confirmation", signature),
+        }
+    }
+}
+
+impl std::error::Error for TransactionError {}
+
+impl From<RpcError> for TransactionError {
+    fn from(err: RpcError) -> Self {
+        TransactionError::Rpc(Box::new(err))
+    }
+}
+
+impl From<SignerError> for TransactionError {
+    fn from(err: SignerError) -> Self {
+        TransactionError::Signer(err)
+    }
+}
+
+/// How transactions are confirmed, and resent when their blockhash expires.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Sends of one transaction, each with a fresh blockhash, before giving up.
+    pub max_attempts: u32,
+    /// Delay before the first resend, doubled after every failed attempt.
+    pub initial_backoff: Duration,
+    /// Longest wait for the confirmation of one attempt.
+    pub confirm_timeout: Duration,
+    /// Delay between two polls of the signature status.
+    pub poll_interval: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            confirm_timeout: Duration::from_secs(90),
+            poll_interval: Duration::from_millis(400),
+        }
+    }
+}
+
+/// Instruction of the mediator program deployed at `program_id`, with the given accounts and arguments.
+pub fn program_instruction(program_id: &Pubkey, accounts: impl ToAccountMetas, args: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: accounts.to_account_metas(None),
+        data: args.data(),
+    }
+}
+
+/// `submit_aggregated_proof` covering `program_contexts`, which all share the proof's key.
+pub fn submit_aggregated_proof_instruction(program_id: &Pubkey, server: &Pubkey, program_contexts: &[Pubkey], proof: &Proof) -> Instruction {
+    let wire = WireProof::from(proof);
+
+    let mut account_metas = accounts::SubmitAggregatedProof { server: *server }.to_account_metas(None);
+    account_metas.extend(program_contexts.iter().map(|program_context| AccountMeta::new(*program_context, false)));
+
+    Instruction {
+        program_id: *program_id,
+        accounts: account_metas,
+        data: ix::SubmitAggregatedProof {
+            sigma: wire.sigma,
+            mu: wire.mu,
+        }
+        .data(),
+    }
+}
+
+/// `submit_merkle_proof` answering the pending challenge of `program_context`.
+pub fn submit_merkle_proof_instruction(program_id: &Pubkey, server: &Pubkey, program_context: &Pubkey, proofs: Vec<MerkleLeafProof>) -> Instruction {
+    program_instruction(
+        program_id,
+        accounts::SubmitMerkleProof {
+            server: *server,
+            program_context: *program_context,
+        },
+        ix::SubmitMerkleProof { proofs },
+    )
+}
+
+/// Signs `instruction` with `payer` and waits for its confirmation.
+pub async fn send_instruction(rpc: &RpcClient, payer: &Keypair, instruction: Instruction) -> Result<Signature, TransactionError> {
+    send_instructions(rpc, payer, &[instruction], &[]).await
+}
+
+/// Sends `instructions` in one transaction paid by `payer` and also signed by `signers`, and
+/// waits for its confirmation.
+pub async fn send_instructions(
+    rpc: &RpcClient,
+    payer: &Keypair,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<Signature, TransactionError> {
+    let signers: Vec<&dyn TransactionSigner> = signers.iter().map(|signer| *signer as &dyn TransactionSigner).collect();
+    send_signed_instructions(rpc, payer, instructions, &signers, &RetryPolicy::default()).await
+}
+
+/// Like `send_instructions`, signed through keystore signers instead of local keypairs.
+///
+/// A transaction whose blockhash expires before its confirmation is signed again with a fresh
+/// blockhash and resent, after a backoff, up to `policy.max_attempts` times. It is never resent
+/// while the previous attempt may still land.
+pub async fn send_signed_instructions(
+    rpc: &RpcClient,
+    payer: &dyn TransactionSigner,
+    instructions: &[Instruction],
+    signers: &[&dyn TransactionSigner],
+    policy: &RetryPolicy,
+) -> Result<Signature, TransactionError> {
+    let mut all_signers = vec![payer];
+    for signer in signers {
+        if all_signers.iter().all(|other| other.pubkey() != signer.pubkey()) {
+            all_signers.push(*signer);
+        }
+    }
+
+    let mut backoff = policy.initial_backoff;
+    let mut attempt = 1;
+    loop {
+        match send_attempt(rpc, payer, instructions, &all_signers, policy).await {
+            Err(err) if attempt < policy.max_attempts && is_retryable(&err) => {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Signs the transaction with a fresh blockhash, sends it and waits for its confirmation
+async fn send_attempt(
+    rpc: &RpcClient,
+    payer: &dyn TransactionSigner,
+    instructions: &[Instruction],
+    signers: &[&dyn TransactionSigner],
+    policy: &RetryPolicy,
+) -> Result<Signature, TransactionError> {
+    let (blockhash, last_valid_block_height) = rpc.get_latest_blockhash_with_commitment(rpc.commitment()).await?;
+
+    let mut transaction = Transaction::new_with_payer(instructions, Some(&payer.pubkey()));
+    transaction.message.recent_blockhash = blockhash;
+    sign_transaction(&mut transaction, signers).await?;
+    let signature = transaction.signatures[0];
+
+    if let Err(err) = rpc.send_transaction(&transaction).await {
+        // A transaction rejected by the node never lands, but one lost in transit may have
+        if !is_transient(&err) {
+            return Err(err.into());
+        }
+    }
+
+    confirm_signature(rpc, &signature, Some(last_valid_block_height), policy).await?;
+    Ok(signature)
+}
+
+/// Polls the status of `signature` until it is confirmed, fails, or its blockhash, valid up to
+/// `last_valid_block_height`, expires.
+pub async fn confirm_signature(
+    rpc: &RpcClient,
+    signature: &Signature,
+    last_valid_block_height: Option<u64>,
+    policy: &RetryPolicy,
+) -> Result<(), TransactionError> {
+    let deadline = tokio::time::Instant::now() + policy.confirm_timeout;
+    loop {
+        match rpc.get_signature_status_with_commitment(signature, rpc.commitment()).await {
+            Ok(Some(Ok(()))) => return Ok(()),
+            Ok(Some(Err(err))) => return Err(RpcError::from(RpcErrorKind::TransactionError(err)).into()),
+            Ok(None) => {
+                if let Some(last_valid_block_height) = last_valid_block_height {
+                    // Checked after the status, so that a transaction landing in its last block isn't resent
+                    if rpc.get_block_height().await.is_ok_and(|height| height > last_valid_block_height) {
+                        return Err(TransactionError::Expired(*signature));
+                    }
+                }
+            }
+            Err(err) if is_transient(&err) => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        if tokio::time::Instant::now() >= deadline {
+            return Err(TransactionError::Timeout(*signature));
+        }
+        tokio::time::sleep(policy.poll_interval).await;
+    }
+}
+
+// Network errors, after which the request may succeed if made again
+fn is_transient(err: &RpcError) -> bool {
+    matches!(err.kind(), RpcErrorKind::Io(_) | RpcErrorKind::Reqwest(_)) && err.get_transaction_error().is_none()
+}
+
+fn is_retryable(err: &TransactionError) -> bool {
+    match err {
+        TransactionError::Expired(_) => true,
+        TransactionError::Rpc(err) => is_transient(err),
+        _ => false,
+    }
+}
+
+/// Slot in which the transaction `signature` was processed.
+pub async fn transaction_slot(rpc: &RpcClient, signature: &Signature) -> Result<u64, TransactionError> {
+    let statuses = rpc.get_signature_statuses_with_history(&[*signature]).await?;
+    match statuses.value.into_iter().next().flatten() {
+        Some(status) => Ok(status.slot),
+        None => Err(RpcError::from(RpcErrorKind::Custom(format!("no status for transaction {}", signature))).into()),
+    }
+}
+
+/// Fetches and decodes a `ProgramContext` account of the program deployed at `program_id`.
+pub async fn fetch_program_context(rpc: &RpcClient, program_id: &Pubkey, pubkey: &Pubkey) -> Result<ProgramContext, TransactionError> {
+    let account = rpc
+        .get_account_with_commitment(pubkey, rpc.commitment())
+        .await?
+        .value
+        .filter(|account| account.owner == *program_id)
+        .ok_or(TransactionError::InvalidAccount(*pubkey))?;
+
+    ProgramContext::try_deserialize(&mut account.data.as_slice()).map_err(|_| TransactionError::InvalidAccount(*pubkey))
+}
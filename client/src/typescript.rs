@@ -0,0 +1,298 @@
+//! TypeScript client of the HTTP API, generated from its OpenAPI document.
+//!
+//! `client typescript` prints it, and the output is checked in to the explorer as
+//! `solana_explorer/app/utils/mediator-api/client.ts`. Every schema becomes a type and every
+//! operation a method of `MediatorApi`, named after its operation id without the `_handler`
+//! suffix. Operations answering `text/event-stream` open an `EventSource` instead of a request.
+
+use serde_json::{Map, Value};
+use std::fmt::Write;
+
+const HEADER: &str = "\
+/* eslint-disable */
+// Generated by `cargo run -- typescript` in `client/` from the OpenAPI document of the API.
+// Do not edit: change the Rust types and regenerate instead.
+";
+
+const ERROR_CLASS: &str = "
+/** Error answered by the API, with its stable `code`. */
+export class MediatorApiError extends Error {
+    constructor(readonly status: number, readonly body: ErrorBody) {
+        super(body.message);
+        this.name = 'MediatorApiError';
+    }
+}
+
+type Query = Record<string, string | number | boolean | null | undefined>;
+
+export class MediatorApi {
+    constructor(readonly baseUrl: string) {}
+";
+
+const HELPERS: &str = "
+    private url(path: string, query: Query = {}): string {
+        const params = new URLSearchParams();
+        for (const [name, value] of Object.entries(query)) {
+            if (value !== undefined && value !== null) {
+                params.append(name, String(value));
+            }
+        }
+        const search = params.toString();
+        return `${this.baseUrl.replace(/\\/$/, '')}${path}${search ? `?${search}` : ''}`;
+    }
+
+    private async request<T>(method: string, path: string, body?: unknown, query?: Query): Promise<T> {
+        const response = await fetch(this.url(path, query), {
+            body: body === undefined ? undefined : JSON.stringify(body),
+            headers: body === undefined ? undefined : { 'Content-Type': 'application/json' },
+            method,
+        });
+        const payload = await response.json();
+        if (!response.ok) {
+            throw new MediatorApiError(response.status, payload as ErrorBody);
+        }
+        return payload as T;
+    }
+}
+";
+
+/// Renders the TypeScript client of the OpenAPI `document`.
+pub fn render(document: &Value) -> String {
+    let mut out = String::from(HEADER);
+
+    if let Some(schemas) = document["components"]["schemas"].as_object() {
+        for (name, schema) in schemas {
+            out.push('\n');
+            write_doc(&mut out, schema, "");
+            if is_plain_object(schema) {
+                let _ = writeln!(out, "export interface {} {}", name, object_type(schema, ""));
+            } else {
+                // Unions start with a line break
+                let ts = ts_type(schema, "");
+                let separator = if ts.starts_with('\n') { "" } else { " " };
+                let _ = writeln!(out, "export type {} ={}{};", name, separator, ts);
+            }
+        }
+    }
+
+    out.push_str(ERROR_CLASS);
+    if let Some(paths) = document["paths"].as_object() {
+        for (path, operations) in paths {
+            let Some(operations) = operations.as_object() else { continue };
+            for (method, operation) in operations {
+                write_operation(&mut out, document, path, method, operation);
+            }
+        }
+    }
+    out.push_str(HELPERS);
+
+    out
+}
+
+fn write_operation(out: &mut String, document: &Value, path: &str, method: &str, operation: &Value) {
+    let operation_id = operation["operationId"].as_str().unwrap_or(path);
+    let name = camel_case(operation_id.trim_end_matches("_handler"));
+
+    let parameters = operation["parameters"].as_array().cloned().unwrap_or_default();
+    let in_location = |location: &str| -> Vec<Value> {
+        parameters
+            .iter()
+            .filter(|parameter| parameter["in"] == location)
+            .cloned()
+            .collect()
+    };
+    let path_parameters = in_location("path");
+    let query_parameters = in_location("query");
+
+    let mut arguments = Vec::new();
+    let mut path_expression = path.to_string();
+    for parameter in &path_parameters {
+        let parameter_name = parameter["name"].as_str().unwrap_or_default();
+        arguments.push(format!("{}: string", camel_case(parameter_name)));
+        path_expression = path_expression.replace(
+            &format!("{{{}}}", parameter_name),
+            &format!("${{encodeURIComponent({})}}", camel_case(parameter_name)),
+        );
+    }
+    let path_expression = if path_parameters.is_empty() {
+        format!("'{}'", path_expression)
+    } else {
+        format!("`{}`", path_expression)
+    };
+
+    let body = first_content_schema(&operation["requestBody"]);
+    if let Some((_, schema)) = &body {
+        arguments.push(format!("body: {}", ts_type(schema, "    ")));
+    }
+    if !query_parameters.is_empty() {
+        let fields: Vec<String> = query_parameters
+            .iter()
+            .map(|parameter| {
+                let optional = if parameter["required"] == true { "" } else { "?" };
+                format!("{}{}: {}", parameter["name"].as_str().unwrap_or_default(), optional, ts_type(&parameter["schema"], "    "))
+            })
+            .collect();
+        arguments.push(format!("query: {{ {} }} = {{}}", fields.join("; ")));
+    }
+    let query = if query_parameters.is_empty() { "undefined" } else { "query" };
+
+    let response = first_content_schema(&operation["responses"]["200"]);
+    let description = operation["responses"]["200"]["description"].as_str().unwrap_or_default();
+    let _ = writeln!(out, "\n    /** `{} {}`: {} */", method.to_uppercase(), path, description);
+
+    match response {
+        Some((content_type, schema)) if content_type == "text/event-stream" => {
+            let event_type = ts_type(&schema, "    ");
+            let event_names = discriminator_values(document, &schema);
+            // Before the query, whose default makes it optional
+            let position = arguments.len() - usize::from(!query_parameters.is_empty());
+            arguments.insert(position, format!("onEvent: (event: {}) => void", event_type));
+            let _ = writeln!(out, "    {}({}): EventSource {{", name, arguments.join(", "));
+            let _ = writeln!(out, "        const source = new EventSource(this.url({}, {}));", path_expression, query);
+            let quoted: Vec<String> = event_names.iter().map(|event_name| format!("'{}'", event_name)).collect();
+            let _ = writeln!(out, "        for (const type of [{}]) {{", quoted.join(", "));
+            let _ = writeln!(
+                out,
+                "            source.addEventListener(type, message => onEvent(JSON.parse((message as MessageEvent).data)));"
+            );
+            let _ = writeln!(out, "        }}");
+            let _ = writeln!(out, "        return source;");
+        }
+        response => {
+            let response_type = response.map(|(_, schema)| ts_type(&schema, "    ")).unwrap_or_else(|| "void".to_string());
+            let _ = writeln!(out, "    {}({}): Promise<{}> {{", name, arguments.join(", "), response_type);
+            let call_arguments = match (body.is_some(), query_parameters.is_empty()) {
+                (false, true) => String::new(),
+                (true, true) => ", body".to_string(),
+                (false, false) => ", undefined, query".to_string(),
+                (true, false) => ", body, query".to_string(),
+            };
+            let _ = writeln!(
+                out,
+                "        return this.request('{}', {}{});",
+                method.to_uppercase(),
+                path_expression,
+                call_arguments
+            );
+        }
+    }
+    let _ = writeln!(out, "    }}");
+}
+
+// Content type and schema of the first content of a request body or response
+fn first_content_schema(value: &Value) -> Option<(String, Value)> {
+    let (content_type, media) = value["content"].as_object()?.iter().next()?;
+    Some((content_type.clone(), media["schema"].clone()))
+}
+
+// Values of the discriminator property of the variants of a tagged schema
+fn discriminator_values(document: &Value, schema: &Value) -> Vec<String> {
+    let schema = resolve(document, schema);
+    if let Some(parts) = schema["allOf"].as_array() {
+        return parts.iter().flat_map(|part| discriminator_values(document, part)).collect();
+    }
+
+    let Some(property) = schema["discriminator"]["propertyName"].as_str() else { return Vec::new() };
+    schema["oneOf"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|variant| resolve(document, variant)["properties"][property]["enum"].as_array().cloned().unwrap_or_default())
+        .filter_map(|value| value.as_str().map(str::to_string))
+        .collect()
+}
+
+fn resolve<'a>(document: &'a Value, schema: &'a Value) -> &'a Value {
+    match schema["$ref"].as_str().and_then(|reference| reference.strip_prefix("#/components/schemas/")) {
+        Some(name) => &document["components"]["schemas"][name],
+        None => schema,
+    }
+}
+
+fn is_plain_object(schema: &Value) -> bool {
+    schema["type"] == "object" && schema["properties"].is_object() && schema["nullable"] != true
+}
+
+fn ts_type(schema: &Value, indent: &str) -> String {
+    let ts = if let Some(reference) = schema["$ref"].as_str() {
+        reference.rsplit('/').next().unwrap_or(reference).to_string()
+    } else if let Some(parts) = schema["allOf"].as_array() {
+        let parts: Vec<String> = parts.iter().map(|part| ts_type(part, indent)).collect();
+        parts.join(" & ")
+    } else if let Some(variants) = schema["oneOf"].as_array() {
+        let variants: Vec<String> = variants
+            .iter()
+            .map(|variant| format!("\n{}    | {}", indent, ts_type(variant, &format!("{}    ", indent))))
+            .collect();
+        variants.concat()
+    } else if let Some(values) = schema["enum"].as_array() {
+        let values: Vec<String> = values.iter().map(|value| format!("'{}'", value.as_str().unwrap_or_default())).collect();
+        values.join(" | ")
+    } else {
+        match schema["type"].as_str() {
+            Some("string") => "string".to_string(),
+            Some("integer" | "number") => "number".to_string(),
+            Some("boolean") => "boolean".to_string(),
+            Some("array") => {
+                let items = ts_type(&schema["items"], indent);
+                if items.contains(' ') {
+                    format!("({})[]", items)
+                } else {
+                    format!("{}[]", items)
+                }
+            }
+            Some("object") if schema["properties"].is_object() => object_type(schema, indent),
+            Some("object") => "Record<string, unknown>".to_string(),
+            _ => "unknown".to_string(),
+        }
+    };
+
+    if schema["nullable"] == true {
+        format!("{} | null", ts)
+    } else {
+        ts
+    }
+}
+
+// Object literal type of a schema with properties
+fn object_type(schema: &Value, indent: &str) -> String {
+    let empty = Map::new();
+    let properties = schema["properties"].as_object().unwrap_or(&empty);
+    let required: Vec<&str> = schema["required"]
+        .as_array()
+        .map(|required| required.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+
+    let field_indent = format!("{}    ", indent);
+    let mut out = String::from("{\n");
+    for (name, property) in properties {
+        write_doc(&mut out, property, &field_indent);
+        let optional = if required.contains(&name.as_str()) { "" } else { "?" };
+        let _ = writeln!(out, "{}{}{}: {};", field_indent, name, optional, ts_type(property, &field_indent));
+    }
+    out.push_str(indent);
+    out.push('}');
+    out
+}
+
+fn write_doc(out: &mut String, schema: &Value, indent: &str) {
+    if let Some(description) = schema["description"].as_str() {
+        let _ = writeln!(out, "{}/** {} */", indent, description.replace('\n', " "));
+    }
+}
+
+fn camel_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
@@ -0,0 +1,165 @@
+//! Verification of PoR proofs, one at a time or in batches.
+//!
+//! A proof is accepted when `e(σ, g) == e(multiplication_sum, v)`, where `multiplication_sum`
+//! is the verifier side product `∏ H(i)^{ν_i} · ∏ u_j^{μ_j}`.
+//!
+//! Batch verification draws a random 128-bit weight `r_k` per proof and checks
+//! `∏ e(r_k·σ_k, g_k) · e(-r_k·multiplication_sum_k, v_k) == 1` with a single multi Miller
+//! loop and a single final exponentiation. Terms sharing the same G2 point (typically many
+//! subscriptions of the same client) are folded together before the Miller loop.
+//! If the batch fails, every proof is checked on its own to find the culprits; when none of
+//! them fails on its own, the whole batch is reported invalid.
+
+use bls12_381::{multi_miller_loop, pairing, G1Affine, G1Projective, G2Affine, G2Prepared, Gt, Scalar};
+use rand::RngCore;
+use std::collections::HashMap;
+
+/// Everything needed to check one proof.
+#[derive(Debug, Clone, Copy)]
+pub struct ProofInstance {
+    pub g: G2Affine,
+    pub v: G2Affine,
+    pub sigma: G1Affine,
+    pub multiplication_sum: G1Affine,
+}
+
+/// Outcome of a batch verification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchVerification {
+    /// Every proof in the batch is valid.
+    AllValid,
+    /// The batch check failed; holds the indices of the proofs that do not verify, never empty.
+    Invalid(Vec<usize>),
+}
+
+impl BatchVerification {
+    pub fn is_valid(&self) -> bool {
+        matches!(self, BatchVerification::AllValid)
+    }
+}
+
+/// Checks a single proof with two pairings.
+pub fn verify_proof(proof: &ProofInstance) -> bool {
+    let left_pairing = pairing(&proof.sigma, &proof.g);
+    let right_pairing = pairing(&proof.multiplication_sum, &proof.v);
+
+    left_pairing == right_pairing
+}
+
+/// Checks all proofs at once, falling back to per-proof checks to report the invalid ones.
+pub fn verify_batch<R: RngCore>(proofs: &[ProofInstance], rng: &mut R) -> BatchVerification {
+    if proofs.is_empty() || verify_batch_combined(proofs, rng) {
+        return BatchVerification::AllValid;
+    }
+
+    let invalid: Vec<usize> = proofs
+        .iter()
+        .enumerate()
+        .filter(|(_, proof)| !verify_proof(proof))
+        .map(|(index, _)| index)
+        .collect();
+
+    // A random linear combination of valid proofs always passes, so a batch failing with no
+    // culprit can't be trusted: none of its proofs is reported verified
+    if invalid.is_empty() {
+        return BatchVerification::Invalid((0..proofs.len()).collect());
+    }
+    BatchVerification::Invalid(invalid)
+}
+
+/// Runs the randomized batch check without identifying failing proofs.
+pub fn verify_batch_combined<R: RngCore>(proofs: &[ProofInstance], rng: &mut R) -> bool {
+    // Accumulated G1 term per distinct G2 point, keyed by the compressed G2 point.
+    let mut terms: HashMap<[u8; 96], (G2Affine, G1Projective)> = HashMap::new();
+
+    for proof in proofs {
+        let weight = random_weight(rng);
+
+        terms
+            .entry(proof.g.to_compressed())
+            .or_insert_with(|| (proof.g, G1Projective::identity()))
+            .1 += proof.sigma * weight;
+        terms
+            .entry(proof.v.to_compressed())
+            .or_insert_with(|| (proof.v, G1Projective::identity()))
+            .1 -= proof.multiplication_sum * weight;
+    }
+
+    let prepared: Vec<(G1Affine, G2Prepared)> = terms
+        .into_values()
+        .map(|(g2, g1)| (G1Affine::from(g1), G2Prepared::from(g2)))
+        .collect();
+    let pairs: Vec<(&G1Affine, &G2Prepared)> = prepared.iter().map(|(g1, g2)| (g1, g2)).collect();
+
+    multi_miller_loop(&pairs).final_exponentiation() == Gt::identity()
+}
+
+/// Draws a non-zero 128-bit scalar, enough to make a forged batch pass with probability 2^-128.
+fn random_weight<R: RngCore>(rng: &mut R) -> Scalar {
+    loop {
+        let weight = Scalar::from_raw([rng.next_u64(), rng.next_u64(), 0, 0]);
+        if weight != Scalar::zero() {
+            return weight;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bls12_381::{G1Projective, G2Projective};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    // A valid instance: σ = M^α checks against v = g^α for any point M
+    fn instance(rng: &mut ChaCha20Rng) -> ProofInstance {
+        let alpha = crate::por::random_scalar(rng);
+        let g = G2Projective::generator() * crate::por::random_scalar(rng);
+        let multiplication_sum = G1Projective::generator() * crate::por::random_scalar(rng);
+
+        ProofInstance {
+            g: G2Affine::from(g),
+            v: G2Affine::from(g * alpha),
+            sigma: G1Affine::from(multiplication_sum * alpha),
+            multiplication_sum: G1Affine::from(multiplication_sum),
+        }
+    }
+
+    #[test]
+    fn accepts_a_batch_of_valid_proofs() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let proofs: Vec<ProofInstance> = (0..4).map(|_| instance(&mut rng)).collect();
+
+        assert!(proofs.iter().all(verify_proof));
+        assert_eq!(verify_batch(&proofs, &mut rng), BatchVerification::AllValid);
+        assert_eq!(verify_batch(&[], &mut rng), BatchVerification::AllValid);
+    }
+
+    #[test]
+    fn blames_the_one_bad_proof_of_a_batch() {
+        let mut rng = ChaCha20Rng::seed_from_u64(2);
+        let mut proofs: Vec<ProofInstance> = (0..4).map(|_| instance(&mut rng)).collect();
+        proofs[2].sigma = G1Affine::from(G1Projective::from(proofs[2].sigma) + G1Projective::generator());
+
+        assert!(!verify_proof(&proofs[2]));
+        assert!(!verify_batch_combined(&proofs, &mut rng));
+        assert_eq!(verify_batch(&proofs, &mut rng), BatchVerification::Invalid(vec![2]));
+    }
+
+    #[test]
+    fn folds_proofs_sharing_the_same_key() {
+        let mut rng = ChaCha20Rng::seed_from_u64(3);
+        let first = instance(&mut rng);
+
+        // Same g and v, so the batch has two G2 points for three proofs
+        let shift = G1Projective::generator() * crate::por::random_scalar(&mut rng);
+        let mut proofs = vec![first];
+        for _ in 0..2 {
+            let mut proof = first;
+            proof.multiplication_sum = G1Affine::from(shift + G1Projective::from(proof.multiplication_sum));
+            proofs.push(proof);
+        }
+        // Only the first is valid: the others kept σ while their multiplication sum moved
+        assert_eq!(verify_batch(&proofs, &mut rng), BatchVerification::Invalid(vec![1, 2]));
+    }
+}
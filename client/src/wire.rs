@@ -0,0 +1,422 @@
+//! Wire formats for challenges, proofs, tags and public parameters.
+//!
+//! The binary format is a small header followed by a Borsh payload:
+//!
+//! `[version: u8][kind: u8][payload length: u32 LE][payload: Borsh]`
+//!
+//! Payloads are plain Borsh (the same encoding as Anchor instruction arguments), so a
+//! `WireProof` payload can be passed straight into `submit_aggregated_proof`. Curve points are
+//! compressed and scalars are 32 bytes little-endian.
+//!
+//! The JSON path encodes fixed-size byte arrays as hex through `HexArray` / `hex_array`.
+
+use crate::por::{PorParams, PorPublicKey};
+use crate::proof::{Challenge, ChallengeItem, Proof};
+use anchor_lang::prelude::borsh;
+use anchor_lang::{AnchorDeserialize, AnchorSerialize};
+use bls12_381::{G1Affine, G2Affine, Scalar};
+use serde::de::{Deserializer, Error as DeError};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Current version of the binary wire format.
+pub const WIRE_VERSION: u8 = 1;
+
+/// Size of the header preceding every payload.
+pub const HEADER_SIZE: usize = 6;
+
+#[derive(Debug)]
+pub enum WireError {
+    UnsupportedVersion(u8),
+    UnexpectedKind { expected: WireKind, actual: u8 },
+    /// The message is shorter or longer than its header claims.
+    InvalidLength { expected: usize, actual: usize },
+    /// The payload doesn't decode or holds an invalid point or scalar.
+    InvalidPayload(String),
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported wire version {}, expected {}",
+                version, WIRE_VERSION
+            ),
+            WireError::UnexpectedKind { expected, actual } => write!(
+                f,
+                "Unexpected message kind {}, expected {:?}",
+                actual, expected
+            ),
+            WireError::InvalidLength { expected, actual } => write!(
+                f,
+                "Invalid message length: expected {} bytes, got {} bytes",
+                expected, actual
+            ),
+            WireError::InvalidPayload(reason) => write!(f, "Invalid payload: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for WireError {}
+
+/// Type of the payload carried by a binary message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum WireKind {
+    Challenge = 1,
+    Proof = 2,
+    Tags = 3,
+    PublicParams = 4,
+}
+
+/// A payload that can be sent as a binary message.
+pub trait WireMessage: AnchorSerialize + AnchorDeserialize {
+    const KIND: WireKind;
+
+    /// Encodes the message with its versioned, length-prefixed header.
+    fn to_wire(&self) -> Vec<u8> {
+        let payload = self.try_to_vec().expect("Serializing to a Vec never fails");
+
+        let mut message = Vec::with_capacity(HEADER_SIZE + payload.len());
+        message.push(WIRE_VERSION);
+        message.push(Self::KIND as u8);
+        message.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        message.extend_from_slice(&payload);
+        message
+    }
+
+    /// Decodes a message produced by `to_wire`.
+    fn from_wire(message: &[u8]) -> Result<Self, WireError> {
+        if message.len() < HEADER_SIZE {
+            return Err(WireError::InvalidLength {
+                expected: HEADER_SIZE,
+                actual: message.len(),
+            });
+        }
+        if message[0] != WIRE_VERSION {
+            return Err(WireError::UnsupportedVersion(message[0]));
+        }
+        if message[1] != Self::KIND as u8 {
+            return Err(WireError::UnexpectedKind {
+                expected: Self::KIND,
+                actual: message[1],
+            });
+        }
+
+        let payload_len = u32::from_le_bytes([message[2], message[3], message[4], message[5]]) as usize;
+        if message.len() != HEADER_SIZE + payload_len {
+            return Err(WireError::InvalidLength {
+                expected: HEADER_SIZE + payload_len,
+                actual: message.len(),
+            });
+        }
+
+        Self::try_from_slice(&message[HEADER_SIZE..]).map_err(|err| WireError::InvalidPayload(err.to_string()))
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WireChallengeItem {
+    pub index: u64,
+    pub version: u64,
+    pub coefficient: [u8; 32],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WireChallenge {
+    pub file_name: Vec<u8>,
+    pub items: Vec<WireChallengeItem>,
+}
+
+/// Same layout as the arguments of the `submit_aggregated_proof` instruction.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WireProof {
+    pub sigma: [u8; 48],
+    pub mu: Vec<[u8; 32]>,
+}
+
+/// Tags of consecutive blocks, starting at `first_index`.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WireTags {
+    pub file_name: Vec<u8>,
+    pub first_index: u64,
+    pub tags: Vec<[u8; 48]>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WirePublicParams {
+    pub g: [u8; 96],
+    pub v: [u8; 96],
+    pub u: Vec<[u8; 48]>,
+    pub block_size: u32,
+}
+
+impl WireMessage for WireChallenge {
+    const KIND: WireKind = WireKind::Challenge;
+}
+
+impl WireMessage for WireProof {
+    const KIND: WireKind = WireKind::Proof;
+}
+
+impl WireMessage for WireTags {
+    const KIND: WireKind = WireKind::Tags;
+}
+
+impl WireMessage for WirePublicParams {
+    const KIND: WireKind = WireKind::PublicParams;
+}
+
+impl From<&Challenge> for WireChallenge {
+    fn from(challenge: &Challenge) -> Self {
+        WireChallenge {
+            file_name: challenge.file_name.clone(),
+            items: challenge
+                .items
+                .iter()
+                .map(|item| WireChallengeItem {
+                    index: item.index,
+                    version: item.version,
+                    coefficient: item.coefficient.to_bytes(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<&WireChallenge> for Challenge {
+    type Error = WireError;
+
+    fn try_from(challenge: &WireChallenge) -> Result<Self, Self::Error> {
+        Ok(Challenge {
+            file_name: challenge.file_name.clone(),
+            items: challenge
+                .items
+                .iter()
+                .map(|item| {
+                    Ok(ChallengeItem {
+                        index: item.index,
+                        version: item.version,
+                        coefficient: decode_scalar(&item.coefficient)?,
+                    })
+                })
+                .collect::<Result<_, WireError>>()?,
+        })
+    }
+}
+
+impl From<&Proof> for WireProof {
+    fn from(proof: &Proof) -> Self {
+        WireProof {
+            sigma: proof.sigma.to_compressed(),
+            mu: proof.mu.iter().map(|mu_j| mu_j.to_bytes()).collect(),
+        }
+    }
+}
+
+impl TryFrom<&WireProof> for Proof {
+    type Error = WireError;
+
+    fn try_from(proof: &WireProof) -> Result<Self, Self::Error> {
+        Ok(Proof {
+            sigma: decode_g1(&proof.sigma)?,
+            mu: proof.mu.iter().map(decode_scalar).collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl WireTags {
+    pub fn from_tags(file_name: &[u8], first_index: u64, tags: &[G1Affine]) -> Self {
+        WireTags {
+            file_name: file_name.to_vec(),
+            first_index,
+            tags: tags.iter().map(|tag| tag.to_compressed()).collect(),
+        }
+    }
+
+    pub fn to_tags(&self) -> Result<Vec<G1Affine>, WireError> {
+        self.tags.iter().map(decode_g1).collect()
+    }
+}
+
+impl From<&PorPublicKey> for WirePublicParams {
+    fn from(public: &PorPublicKey) -> Self {
+        WirePublicParams {
+            g: public.g.to_compressed(),
+            v: public.v.to_compressed(),
+            u: public.u.iter().map(|u_j| u_j.to_compressed()).collect(),
+            block_size: public.block_size as u32,
+        }
+    }
+}
+
+impl TryFrom<&WirePublicParams> for PorPublicKey {
+    type Error = WireError;
+
+    fn try_from(params: &WirePublicParams) -> Result<Self, Self::Error> {
+        let por_params = PorParams {
+            sectors_per_block: params.u.len(),
+            block_size: params.block_size as usize,
+        };
+        if !por_params.is_valid() {
+            return Err(WireError::InvalidPayload("invalid block size".to_string()));
+        }
+
+        Ok(PorPublicKey {
+            g: decode_g2(&params.g)?,
+            v: decode_g2(&params.v)?,
+            u: params.u.iter().map(decode_g1).collect::<Result<_, _>>()?,
+            block_size: por_params.block_size,
+        })
+    }
+}
+
+pub fn decode_scalar(bytes: &[u8; 32]) -> Result<Scalar, WireError> {
+    Option::from(Scalar::from_bytes(bytes)).ok_or_else(|| WireError::InvalidPayload("invalid scalar".to_string()))
+}
+
+pub fn decode_g1(bytes: &[u8; 48]) -> Result<G1Affine, WireError> {
+    Option::from(G1Affine::from_compressed(bytes)).ok_or_else(|| WireError::InvalidPayload("invalid G1 point".to_string()))
+}
+
+pub fn decode_g2(bytes: &[u8; 96]) -> Result<G2Affine, WireError> {
+    Option::from(G2Affine::from_compressed(bytes)).ok_or_else(|| WireError::InvalidPayload("invalid G2 point".to_string()))
+}
+
+/// Fixed-size byte array encoded as a hex string in JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HexArray<const N: usize>(pub [u8; N]);
+
+impl<const N: usize> Serialize for HexArray<N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(self.0))
+    }
+}
+
+impl<'de, const N: usize> Deserialize<'de> for HexArray<N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s: &str = Deserialize::deserialize(deserializer)?;
+        let bytes = hex::decode(s).map_err(DeError::custom)?;
+        if bytes.len() != N {
+            return Err(DeError::custom(format!(
+                "Invalid length: expected {} bytes, got {} bytes",
+                N,
+                bytes.len()
+            )));
+        }
+        let mut array = [0u8; N];
+        array.copy_from_slice(&bytes);
+        Ok(HexArray(array))
+    }
+}
+
+/// Serde adapter for `[u8; N]` fields: `#[serde(with = "client::wire::hex_array")]`.
+pub mod hex_array {
+    use super::HexArray;
+    use serde::{Deserialize, Serialize};
+
+    pub fn serialize<S, const N: usize>(value: &[u8; N], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        HexArray(*value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D, const N: usize>(deserializer: D) -> Result<[u8; N], D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let array = HexArray::<N>::deserialize(deserializer)?;
+        Ok(array.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::por::PorKeyPair;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    fn keys() -> PorKeyPair {
+        PorKeyPair::generate(PorParams::with_block_size(40), &mut ChaCha20Rng::seed_from_u64(9))
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let keys = keys();
+        let challenge = Challenge {
+            file_name: b"file".to_vec(),
+            items: vec![ChallengeItem { index: 4, version: 2, coefficient: Scalar::from(77) }],
+        };
+        let proof = Proof {
+            sigma: keys.tag_block(b"file", 4, 2, &[1; 40]),
+            mu: vec![Scalar::from(5), Scalar::from(6)],
+        };
+        let tags = [keys.tag_block(b"file", 0, 0, &[1; 40]), keys.tag_block(b"file", 1, 0, &[2; 40])];
+
+        let decoded = WireChallenge::from_wire(&WireChallenge::from(&challenge).to_wire()).unwrap();
+        assert_eq!(Challenge::try_from(&decoded).unwrap(), challenge);
+
+        let decoded = WireProof::from_wire(&WireProof::from(&proof).to_wire()).unwrap();
+        assert_eq!(Proof::try_from(&decoded).unwrap(), proof);
+
+        let decoded = WireTags::from_wire(&WireTags::from_tags(b"file", 0, &tags).to_wire()).unwrap();
+        assert_eq!(decoded.to_tags().unwrap(), tags);
+
+        let decoded = WirePublicParams::from_wire(&WirePublicParams::from(&keys.public).to_wire()).unwrap();
+        assert_eq!(PorPublicKey::try_from(&decoded).unwrap(), keys.public);
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let message = WireProof { sigma: [0; 48], mu: vec![] }.to_wire();
+
+        assert!(matches!(WireProof::from_wire(&message[..3]), Err(WireError::InvalidLength { expected: 6, actual: 3 })));
+        assert!(matches!(
+            WireChallenge::from_wire(&message),
+            Err(WireError::UnexpectedKind { expected: WireKind::Challenge, actual: 2 })
+        ));
+
+        let mut future = message.clone();
+        future[0] = WIRE_VERSION + 1;
+        assert!(matches!(WireProof::from_wire(&future), Err(WireError::UnsupportedVersion(_))));
+
+        let mut trailing = message;
+        trailing.push(0);
+        assert!(matches!(WireProof::from_wire(&trailing), Err(WireError::InvalidLength { .. })));
+    }
+
+    #[test]
+    fn rejects_invalid_points_and_scalars() {
+        let proof = WireProof { sigma: [0; 48], mu: vec![[0xff; 32]] };
+        assert!(matches!(Proof::try_from(&proof), Err(WireError::InvalidPayload(_))));
+
+        let mut params = WirePublicParams::from(&keys().public);
+        params.block_size = 100;
+        assert!(matches!(PorPublicKey::try_from(&params), Err(WireError::InvalidPayload(_))));
+    }
+
+    #[test]
+    fn proof_payload_is_the_instruction_arguments() {
+        let proof = WireProof { sigma: [3; 48], mu: vec![[4; 32], [5; 32]] };
+        let args = solana_smart_contract::instruction::SubmitAggregatedProof {
+            sigma: proof.sigma,
+            mu: proof.mu.clone(),
+        };
+
+        assert_eq!(proof.to_wire()[HEADER_SIZE..], args.try_to_vec().unwrap());
+    }
+
+    #[test]
+    fn hex_arrays_round_trip_through_json() {
+        let json = serde_json::to_string(&HexArray([0xab, 0x01])).unwrap();
+        assert_eq!(json, "\"ab01\"");
+        assert_eq!(serde_json::from_str::<HexArray<2>>(&json).unwrap(), HexArray([0xab, 0x01]));
+
+        assert!(serde_json::from_str::<HexArray<3>>(&json).is_err());
+        assert!(serde_json::from_str::<HexArray<2>>("\"zz01\"").is_err());
+    }
+}
@@ -0,0 +1,27 @@
+//! The OpenAPI document and the TypeScript client checked in to the explorer must match the
+//! ones generated from the current Rust types. Regenerate them with
+//! `pnpm generate:mediator-api` in `solana_explorer/` when this fails.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+fn generated(command: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_client"))
+        .arg(command)
+        .output()
+        .expect("The client binary runs");
+    assert!(output.status.success(), "`client {}` failed: {}", command, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).expect("The output is UTF-8")
+}
+
+fn checked_in(file: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../solana_explorer/app/utils/mediator-api").join(file);
+    fs::read_to_string(&path).unwrap_or_else(|err| panic!("{}: {}", path.display(), err))
+}
+
+#[test]
+fn explorer_client_is_up_to_date() {
+    assert!(generated("openapi") == checked_in("openapi.json"), "openapi.json is stale");
+    assert!(generated("typescript") == checked_in("client.ts"), "client.ts is stale");
+}
@@ -0,0 +1,552 @@
+//! End-to-end scenarios of the three-party protocol, run offline against an in-process bank.
+//!
+//! The mediator program is loaded natively with `solana-program-test`; the client and the
+//! storage server are scripted with the `client` library. Every step asserts the balances of
+//! the client, the server and the mediator account.
+//!
+//! The program has no notion of time, so a server that never answers is modelled by an
+//! unanswered Merkle challenge, which blocks its payment until it answers.
+
+use anchor_lang::prelude::AccountInfo;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use client::challenge::{sample_challenge, ChallengePolicy};
+use client::events::{events_of, EventKind};
+use client::merkle::{file_leaves, prove_challenge, tree_from_blocks};
+use client::pop::{prove_possession, verify_program_context};
+use client::por::{PorKeyPair, PorParams};
+use client::proof::{prove, verify, Challenge, MemoryFile, Proof};
+use client::query::{instruction_name, ProgramCall, ProgramTransaction, PROGRAM_CONTEXT_CLIENT_OFFSET};
+use client::transactions::submit_aggregated_proof_instruction;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::account::Account;
+use solana_sdk::instruction::{Instruction, InstructionError};
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature};
+use solana_sdk::signer::Signer;
+use solana_sdk::system_program;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use solana_smart_contract::{accounts, instruction as ix, ErrorCode, ProgramContext};
+use std::collections::HashMap;
+
+const BLOCK_COUNT: usize = 16;
+
+// Anchor's entry ties the accounts slice to the lifetime of the account infos, which the
+// `processor!` signature can't express
+fn process_instruction(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    solana_smart_contract::entry(program_id, accounts, data)
+}
+
+fn instruction(accounts: impl ToAccountMetas, args: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: solana_smart_contract::ID,
+        accounts: accounts.to_account_metas(None),
+        data: args.data(),
+    }
+}
+
+fn file_blocks(block_size: usize) -> Vec<Vec<u8>> {
+    (0..BLOCK_COUNT)
+        .map(|index| (0..block_size).map(|byte| (index * 31 + byte) as u8).collect())
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Balances {
+    client: u64,
+    server: u64,
+    mediator: u64,
+}
+
+struct Scenario {
+    context: ProgramTestContext,
+    client: Keypair,
+    server: Keypair,
+    mediator: Keypair,
+    program_context: Keypair,
+}
+
+impl Scenario {
+    async fn start() -> Self {
+        let mut program_test = ProgramTest::new(
+            "solana_smart_contract",
+            solana_smart_contract::ID,
+            processor!(process_instruction),
+        );
+
+        let client = Keypair::new();
+        let server = Keypair::new();
+        for party in [&client, &server] {
+            program_test.add_account(
+                party.pubkey(),
+                Account::new(100 * LAMPORTS_PER_SOL, 0, &system_program::ID),
+            );
+        }
+
+        Scenario {
+            context: program_test.start_with_context().await,
+            client,
+            server,
+            mediator: Keypair::new(),
+            program_context: Keypair::new(),
+        }
+    }
+
+    /// Sends `instruction` paid by the client or the server, and returns the fee paid.
+    async fn send(&mut self, payer_is_server: bool, instruction: Instruction, extra_signers: &[&Keypair]) -> Result<u64, BanksClientError> {
+        let payer = if payer_is_server { &self.server } else { &self.client };
+        let mut signers = vec![payer];
+        signers.extend_from_slice(extra_signers);
+
+        // A fresh blockhash each time, so repeating an instruction isn't a duplicate transaction
+        let blockhash = self.context.get_new_latest_blockhash().await?;
+        let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &signers, blockhash);
+        let fee = self
+            .context
+            .banks_client
+            .get_fee_for_message(transaction.message.clone())
+            .await?
+            .expect("The blockhash is recent");
+
+        // Rejected at preflight, a failing transaction never lands and charges no fee
+        self.context.banks_client.process_transaction_with_preflight(transaction).await?;
+        Ok(fee)
+    }
+
+    async fn send_as_client(&mut self, instruction: Instruction) -> Result<u64, BanksClientError> {
+        self.send(false, instruction, &[]).await
+    }
+
+    async fn send_as_server(&mut self, instruction: Instruction) -> Result<u64, BanksClientError> {
+        self.send(true, instruction, &[]).await
+    }
+
+    async fn balance(&mut self, pubkey: Pubkey) -> u64 {
+        self.context.banks_client.get_balance(pubkey).await.unwrap()
+    }
+
+    async fn balances(&mut self) -> Balances {
+        Balances {
+            client: self.balance(self.client.pubkey()).await,
+            server: self.balance(self.server.pubkey()).await,
+            mediator: self.balance(self.mediator.pubkey()).await,
+        }
+    }
+
+    async fn program_context(&mut self) -> ProgramContext {
+        let account = self
+            .context
+            .banks_client
+            .get_account(self.program_context.pubkey())
+            .await
+            .unwrap()
+            .expect("The program context exists");
+        ProgramContext::try_deserialize(&mut account.data.as_slice()).unwrap()
+    }
+
+    /// Creates the mediator and program context accounts, paid by the client.
+    async fn initialize(&mut self) {
+        let before = self.balances().await;
+        let instruction = instruction(
+            accounts::Initialize {
+                payer: self.client.pubkey(),
+                mediator: self.mediator.pubkey(),
+                program_context: self.program_context.pubkey(),
+                system_program: system_program::ID,
+            },
+            ix::Initialize,
+        );
+        let mediator = self.mediator.insecure_clone();
+        let program_context = self.program_context.insecure_clone();
+        let fee = self.send(false, instruction, &[&mediator, &program_context]).await.unwrap();
+
+        let after = self.balances().await;
+        let program_context_rent = self.balance(self.program_context.pubkey()).await;
+        assert!(after.mediator > 0, "The mediator account holds its rent");
+        assert_eq!(after.client, before.client - fee - after.mediator - program_context_rent);
+        assert_eq!(after.server, before.server);
+    }
+
+    fn extend_subscription_instruction(&self) -> Instruction {
+        instruction(
+            accounts::ExtendSubscription {
+                payer: self.client.pubkey(),
+                mediator: self.mediator.pubkey(),
+                program_context: self.program_context.pubkey(),
+                system_program: system_program::ID,
+            },
+            ix::ExtendSubscription,
+        )
+    }
+
+    fn update_file_state_instruction(&self, file_version: u64, block_count: u64, file_state_root: [u8; 32]) -> Instruction {
+        instruction(
+            accounts::UpdateFileState {
+                payer: self.client.pubkey(),
+                program_context: self.program_context.pubkey(),
+            },
+            ix::UpdateFileState { file_version, block_count, file_state_root },
+        )
+    }
+
+    fn retrieve_instruction(&self) -> Instruction {
+        instruction(
+            accounts::Retrieve {
+                server: self.server.pubkey(),
+                mediator: self.mediator.pubkey(),
+                program_context: self.program_context.pubkey(),
+                system_program: system_program::ID,
+            },
+            ix::Retrieve,
+        )
+    }
+
+    /// The server collects its payment, which the program only releases above 5 SOL.
+    async fn retrieve(&mut self) {
+        let before = self.balances().await;
+        let payment = if before.mediator > 5 * LAMPORTS_PER_SOL { LAMPORTS_PER_SOL } else { 0 };
+
+        let fee = self.send_as_server(self.retrieve_instruction()).await.unwrap();
+
+        let after = self.balances().await;
+        assert_eq!(after.server, before.server - fee + payment);
+        assert_eq!(after.mediator, before.mediator - payment);
+        assert_eq!(after.client, before.client);
+        assert!(!self.program_context().await.is_server_turn);
+    }
+
+    /// The client pays the next period.
+    async fn extend(&mut self) {
+        let before = self.balances().await;
+
+        let fee = self.send_as_client(self.extend_subscription_instruction()).await.unwrap();
+
+        let after = self.balances().await;
+        assert_eq!(after.client, before.client - fee - LAMPORTS_PER_SOL);
+        assert_eq!(after.mediator, before.mediator + LAMPORTS_PER_SOL);
+        assert_eq!(after.server, before.server);
+        assert!(self.program_context().await.is_server_turn);
+    }
+}
+
+fn assert_program_error<T: std::fmt::Debug>(result: Result<T, BanksClientError>, error: ErrorCode) {
+    let code = anchor_lang::error::ERROR_CODE_OFFSET + error as u32;
+    match result.as_ref().map_err(BanksClientError::unwrap) {
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(actual))) => {
+            assert_eq!(actual, code, "Expected the program to fail with {:?}", error)
+        }
+        other => panic!("Expected the program to fail with {:?}, got {:?}", error, other),
+    }
+}
+
+/// A BLS subscription: PoR keys, their registration, and the tagged file given to the server.
+async fn start_bls_subscription(scenario: &mut Scenario) -> (PorKeyPair, MemoryFile) {
+    scenario.initialize().await;
+
+    let mut rng = ChaCha20Rng::from_seed([1; 32]);
+    let keys = PorKeyPair::generate(PorParams::new(4), &mut rng);
+    let file = MemoryFile::tag(&keys, b"archive", file_blocks(keys.public.block_size()));
+
+    let before = scenario.balances().await;
+    let fee = scenario
+        .send_as_client(instruction(
+            accounts::SetClientCurvePoints {
+                payer: scenario.client.pubkey(),
+                mediator: scenario.mediator.pubkey(),
+                program_context: scenario.program_context.pubkey(),
+                system_program: system_program::ID,
+            },
+            ix::SetClientCurvePoints {
+                g_norm: keys.public.g.to_compressed(),
+                v_norm: keys.public.v.to_compressed(),
+                pop: prove_possession(&keys).to_compressed(),
+                sectors_per_block: keys.public.sectors_per_block() as u32,
+                block_size: keys.public.block_size() as u32,
+                server: scenario.server.pubkey(),
+            },
+        ))
+        .await
+        .unwrap();
+
+    let after = scenario.balances().await;
+    assert_eq!(after.client, before.client - fee - LAMPORTS_PER_SOL);
+    assert_eq!(after.mediator, before.mediator + LAMPORTS_PER_SOL);
+    assert_eq!(after.server, before.server);
+
+    let program_context = scenario.program_context().await;
+    assert!(verify_program_context(&program_context));
+    assert_eq!(program_context.sectors_per_block, 4);
+
+    (keys, file)
+}
+
+/// One BLS round: the client challenges, the server proves and records its proof on-chain,
+/// and the client verifies it off-chain.
+async fn bls_round(scenario: &mut Scenario, keys: &PorKeyPair, file: &MemoryFile, round: u8) -> (Challenge, Proof) {
+    let policy = ChallengePolicy::Detection { epsilon: 0.25, delta: 0.05 };
+    let sampled = sample_challenge(&[round; 32], b"archive", BLOCK_COUNT as u64, &policy).unwrap();
+    assert!(sampled.confidence >= 0.95);
+
+    let proof = prove(&keys.public, &sampled.challenge, file).unwrap();
+    let proof_count = scenario.program_context().await.proof_count;
+
+    let before = scenario.balances().await;
+    let instruction = submit_aggregated_proof_instruction(&solana_smart_contract::ID, &scenario.server.pubkey(), &[scenario.program_context.pubkey()], &proof);
+    let fee = scenario.send_as_server(instruction).await.unwrap();
+
+    let after = scenario.balances().await;
+    assert_eq!(after.server, before.server - fee);
+    assert_eq!(after.mediator, before.mediator);
+    assert_eq!(scenario.program_context().await.proof_count, proof_count + 1);
+
+    (sampled.challenge, proof)
+}
+
+#[tokio::test]
+async fn bls_rounds_and_settlement() {
+    let mut scenario = Scenario::start().await;
+    let (keys, file) = start_bls_subscription(&mut scenario).await;
+
+    // Subscriptions are listed by client with a memcmp filter on the raw account data
+    let account = scenario
+        .context
+        .banks_client
+        .get_account(scenario.program_context.pubkey())
+        .await
+        .unwrap()
+        .expect("The program context exists");
+    let client_field = &account.data[PROGRAM_CONTEXT_CLIENT_OFFSET..PROGRAM_CONTEXT_CLIENT_OFFSET + 32];
+    assert_eq!(client_field, scenario.client.pubkey().as_ref());
+
+    // Only the server of the subscription may record proofs for it
+    let sampled = sample_challenge(&[0; 32], b"archive", BLOCK_COUNT as u64, &ChallengePolicy::Fixed { count: 4, epsilon: 0.25 }).unwrap();
+    let proof = prove(&keys.public, &sampled.challenge, &file).unwrap();
+    let submit_as_client = submit_aggregated_proof_instruction(&solana_smart_contract::ID, &scenario.client.pubkey(), &[scenario.program_context.pubkey()], &proof);
+    let result = scenario.send_as_client(submit_as_client).await;
+    assert_program_error(result, ErrorCode::NotSubscriptionServer);
+    assert_eq!(scenario.program_context().await.proof_count, 0);
+
+    // The mediator starts with 1 SOL; the server is paid once it holds more than 5 SOL
+    for round in 1..=5 {
+        let (challenge, proof) = bls_round(&mut scenario, &keys, &file, round).await;
+        assert!(verify(&keys.public, &challenge, &proof), "Round {} should verify", round);
+
+        scenario.retrieve().await;
+        scenario.extend().await;
+    }
+
+    // The client ends the subscription and the server settles: it takes the whole balance
+    let before = scenario.balances().await;
+    let end_subscription = instruction(
+        accounts::EndSubscription {
+            program_context: scenario.program_context.pubkey(),
+        },
+        ix::EndSubscription,
+    );
+    assert_eq!(instruction_name(&end_subscription.data), Some("end_subscription"));
+    let fee = scenario.send_as_client(end_subscription).await.unwrap();
+    let after = scenario.balances().await;
+    assert_eq!(after.client, before.client - fee);
+    assert_eq!(after.mediator, before.mediator);
+    assert!(scenario.program_context().await.is_subscription_ended);
+
+    let before = scenario.balances().await;
+    let retrieve = scenario.retrieve_instruction();
+    let fee = scenario.send_as_server(retrieve.clone()).await.unwrap();
+    let after = scenario.balances().await;
+    assert_eq!(after.mediator, 0);
+    assert_eq!(after.server, before.server - fee + before.mediator);
+    assert_eq!(after.client, before.client);
+
+    // The event listener reports the settlement as a release of the whole balance
+    let transaction = ProgramTransaction {
+        signature: Signature::default(),
+        slot: 1,
+        error: None,
+        calls: vec![ProgramCall {
+            name: instruction_name(&retrieve.data),
+            accounts: retrieve.accounts.iter().map(|meta| meta.pubkey).collect(),
+        }],
+        lamport_changes: HashMap::from([(scenario.mediator.pubkey(), -(before.mediator as i64))]),
+    };
+    let kinds: Vec<EventKind> = events_of(&transaction).into_iter().map(|event| event.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            EventKind::FundsReleased {
+                server: scenario.server.pubkey().to_string(),
+                mediator: scenario.mediator.pubkey().to_string(),
+                lamports: before.mediator,
+            },
+            EventKind::TurnChanged { is_server_turn: false },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn bls_cheating_server_is_not_paid_again() {
+    let mut scenario = Scenario::start().await;
+    let (keys, mut file) = start_bls_subscription(&mut scenario).await;
+
+    let (challenge, proof) = bls_round(&mut scenario, &keys, &file, 1).await;
+    assert!(verify(&keys.public, &challenge, &proof));
+    scenario.retrieve().await;
+    scenario.extend().await;
+
+    // The server loses every block and answers from garbage
+    let before_cheating = scenario.balances().await;
+    for block in file.blocks.iter_mut() {
+        block.iter_mut().for_each(|byte| *byte = 0);
+    }
+    let (challenge, proof) = bls_round(&mut scenario, &keys, &file, 2).await;
+    assert!(!verify(&keys.public, &challenge, &proof), "A proof over lost blocks must not verify");
+
+    // The pairing check can't run on-chain, so the server can still collect the current period,
+    // but the client withholds the next one and the server can't collect again
+    scenario.retrieve().await;
+    let before = scenario.balances().await;
+    let result = scenario.send_as_server(scenario.retrieve_instruction()).await;
+    assert_program_error(result, ErrorCode::NotServerTurn);
+    assert_eq!(scenario.balances().await, before);
+
+    // The auditor doesn't end the subscription, which would release the escrow to the server:
+    // the cheater only paid fees since the failed audit, and the escrow stays put
+    let after = scenario.balances().await;
+    assert!(after.server < before_cheating.server);
+    assert_eq!(after.mediator, before_cheating.mediator);
+    assert!(!scenario.program_context().await.is_subscription_ended);
+}
+
+#[tokio::test]
+async fn merkle_cheating_and_unanswered_challenges() {
+    let mut scenario = Scenario::start().await;
+    scenario.initialize().await;
+
+    let leaves = file_leaves(&file_blocks(64).concat());
+    let tree = tree_from_blocks(&leaves);
+
+    let before = scenario.balances().await;
+    let fee = scenario
+        .send_as_client(instruction(
+            accounts::SetMerkleCommitment {
+                payer: scenario.client.pubkey(),
+                mediator: scenario.mediator.pubkey(),
+                program_context: scenario.program_context.pubkey(),
+                system_program: system_program::ID,
+            },
+            ix::SetMerkleCommitment {
+                block_count: leaves.len() as u64,
+                merkle_root: tree.root(),
+                server: scenario.server.pubkey(),
+            },
+        ))
+        .await
+        .unwrap();
+    let after = scenario.balances().await;
+    assert_eq!(after.client, before.client - fee - LAMPORTS_PER_SOL);
+    assert_eq!(after.mediator, before.mediator + LAMPORTS_PER_SOL);
+
+    // Six leaves and their 4-hash paths wouldn't fit in the answer transaction
+    let oversized_challenge = instruction(
+        accounts::IssueMerkleChallenge {
+            payer: scenario.client.pubkey(),
+            program_context: scenario.program_context.pubkey(),
+        },
+        ix::IssueMerkleChallenge { challenge_seed: [0; 32], challenge_size: 6 },
+    );
+    let result = scenario.send_as_client(oversized_challenge).await;
+    assert_program_error(result, ErrorCode::InvalidChallengeSize);
+
+    for round in 1..=2u8 {
+        let challenge_seed = [round; 32];
+        let challenge_size = 4;
+        let issue_challenge = instruction(
+            accounts::IssueMerkleChallenge {
+                payer: scenario.client.pubkey(),
+                program_context: scenario.program_context.pubkey(),
+            },
+            ix::IssueMerkleChallenge { challenge_seed, challenge_size },
+        );
+        let before = scenario.balances().await;
+        let fee = scenario.send_as_client(issue_challenge).await.unwrap();
+        let after = scenario.balances().await;
+        assert_eq!(after.client, before.client - fee);
+        assert_eq!(after.mediator, before.mediator);
+
+        // Until the challenge is answered the server can't be paid
+        let before = scenario.balances().await;
+        let result = scenario.send_as_server(scenario.retrieve_instruction()).await;
+        assert_program_error(result, ErrorCode::ChallengePending);
+        assert_eq!(scenario.balances().await, before);
+
+        // Nor can the client move the committed root under the challenge
+        let result = scenario.send_as_client(scenario.update_file_state_instruction(1, leaves.len() as u64, tree.root())).await;
+        assert_program_error(result, ErrorCode::ChallengePending);
+
+        // A server answering with altered blocks is rejected on-chain
+        let mut forged = prove_challenge(&tree, &leaves, &challenge_seed, challenge_size).unwrap();
+        forged[0].block[0] ^= 0xff;
+        let submit_forged = instruction(
+            accounts::SubmitMerkleProof {
+                server: scenario.server.pubkey(),
+                program_context: scenario.program_context.pubkey(),
+            },
+            ix::SubmitMerkleProof { proofs: forged },
+        );
+        let result = scenario.send_as_server(submit_forged).await;
+        assert_program_error(result, ErrorCode::InvalidMerkleProof);
+        assert!(scenario.program_context().await.is_challenge_pending);
+
+        // Anyone else holding the blocks can neither answer nor be paid in its place
+        let proofs = prove_challenge(&tree, &leaves, &challenge_seed, challenge_size).unwrap();
+        let submit_as_client = instruction(
+            accounts::SubmitMerkleProof {
+                server: scenario.client.pubkey(),
+                program_context: scenario.program_context.pubkey(),
+            },
+            ix::SubmitMerkleProof { proofs: proofs.clone() },
+        );
+        let result = scenario.send_as_client(submit_as_client).await;
+        assert_program_error(result, ErrorCode::NotSubscriptionServer);
+        assert!(scenario.program_context().await.is_challenge_pending);
+
+        // The honest answer clears the challenge and the server collects the period
+        let submit_proof = instruction(
+            accounts::SubmitMerkleProof {
+                server: scenario.server.pubkey(),
+                program_context: scenario.program_context.pubkey(),
+            },
+            ix::SubmitMerkleProof { proofs },
+        );
+        let before = scenario.balances().await;
+        let fee = scenario.send_as_server(submit_proof).await.unwrap();
+        let after = scenario.balances().await;
+        assert_eq!(after.server, before.server - fee);
+        assert!(!scenario.program_context().await.is_challenge_pending);
+
+        let retrieve_as_client = instruction(
+            accounts::Retrieve {
+                server: scenario.client.pubkey(),
+                mediator: scenario.mediator.pubkey(),
+                program_context: scenario.program_context.pubkey(),
+                system_program: system_program::ID,
+            },
+            ix::Retrieve,
+        );
+        let result = scenario.send_as_client(retrieve_as_client).await;
+        assert_program_error(result, ErrorCode::NotSubscriptionServer);
+
+        scenario.retrieve().await;
+        scenario.extend().await;
+    }
+
+    // A file state without blocks would leave no block to challenge
+    let empty_update = scenario.update_file_state_instruction(1, 0, tree.root());
+    let result = scenario.send_as_client(empty_update).await;
+    assert_program_error(result, ErrorCode::EmptyFileState);
+    assert_eq!(scenario.program_context().await.block_count, leaves.len() as u64);
+}
@@ -0,0 +1,347 @@
+/* eslint-disable */
+// Generated by `cargo run -- typescript` in `client/` from the OpenAPI document of the API.
+// Do not edit: change the Rust types and regenerate instead.
+
+export interface AirdropRequest {
+    amount_sol: number;
+    recipient: string;
+}
+
+export interface AirdropResponse {
+    message: string;
+    signature: string;
+}
+
+export interface ClientCurvePoints {
+    block_size: number;
+    g_norm: string;
+    pop: string;
+    sectors_per_block: number;
+    v_norm: string;
+}
+
+export interface CreateSignerRequest {
+    id: string;
+}
+
+export interface EndSubscriptionRequest {
+    payer: string;
+    program_context: string;
+}
+
+/** JSON body of every error response. */
+export interface ErrorBody {
+    code: string;
+    details?: Record<string, unknown> | null;
+    message: string;
+}
+
+export type EventKind =
+    | {
+        is_server_turn: boolean;
+        type: 'turn_changed';
+    }
+    | {
+        proof_kind: string;
+        server: string;
+        type: 'proof_submitted';
+    }
+    | {
+        lamports: number;
+        mediator: string;
+        server: string;
+        type: 'funds_released';
+    }
+    | {
+        type: 'subscription_ended';
+    };
+
+export interface ExtendSubscriptionRequest {
+    mediator: string;
+    payer: string;
+    program_context: string;
+}
+
+export interface HistoryEntryResponse {
+    block_time?: number | null;
+    calls: ProgramCallResponse[];
+    error?: string | null;
+    signature: string;
+    slot: number;
+}
+
+export interface InitializeSubscriptionRequest {
+    mediator: string;
+    payer: string;
+    program_context: string;
+}
+
+export interface InstructionResponse {
+    accounts: string[];
+    mediator_lamports?: number | null;
+    program_contexts: ProgramContextSnapshot[];
+    signature: string;
+    slot: number;
+}
+
+export interface IssueMerkleChallengeRequest {
+    challenge_seed?: string | null;
+    challenge_size: number;
+    payer: string;
+    program_context: string;
+}
+
+export interface LaggedEvent {
+    missed: number;
+}
+
+export interface MediatorSnapshot {
+    address: string;
+    balance: number;
+    lamports: number;
+}
+
+export interface MerkleLeafProofPayload {
+    block: string;
+    path: string[];
+}
+
+export interface ProgramCallResponse {
+    accounts: string[];
+    instruction?: string | null;
+}
+
+export interface ProgramContextSnapshot {
+    address: string;
+    block_count: number;
+    block_size: number;
+    challenge_seed: string;
+    challenge_size: number;
+    client: string;
+    file_state_root: string;
+    file_version: number;
+    g_norm: string;
+    is_challenge_pending: boolean;
+    is_server_turn: boolean;
+    is_subscription_ended: boolean;
+    last_proof_digest: string;
+    mediator_balance: number;
+    pop: string;
+    proof_count: number;
+    proof_kind: string;
+    sectors_per_block: number;
+    server: string;
+    subscription_duration: number;
+    v_norm: string;
+}
+
+export interface RequestPayload {
+    g_compressed: string;
+    multiplication_sum_compressed: string;
+    sigma_compressed: string;
+    v_compressed: string;
+}
+
+export interface RetrieveRequest {
+    mediator: string;
+    program_context: string;
+    server: string;
+}
+
+export interface SetClientCurvePointsRequest {
+    curve_points?: ClientCurvePoints | null;
+    mediator: string;
+    payer: string;
+    program_context: string;
+    server: string;
+}
+
+export interface SetMerkleCommitmentRequest {
+    block_count: number;
+    mediator: string;
+    merkle_root: string;
+    payer: string;
+    program_context: string;
+    server: string;
+}
+
+export interface SignerResponse {
+    id: string;
+    pubkey: string;
+}
+
+export interface SubmitAggregatedProofRequest {
+    mu: string[];
+    program_contexts: string[];
+    server: string;
+    sigma: string;
+}
+
+export interface SubmitMerkleProofRequest {
+    program_context: string;
+    proofs: MerkleLeafProofPayload[];
+    server: string;
+}
+
+/** Something that happened to one subscription, in a confirmed transaction. */
+export type SubscriptionEvent = EventKind & {
+    program_context: string;
+    signature: string;
+    slot: number;
+};
+
+export interface SubscriptionResponse {
+    mediator?: MediatorSnapshot | null;
+    program_context: ProgramContextSnapshot;
+    program_context_lamports: number;
+}
+
+export interface UpdateFileStateRequest {
+    block_count: number;
+    file_state_root: string;
+    file_version: number;
+    payer: string;
+    program_context: string;
+}
+
+export interface VerifyBatchResponse {
+    invalid_proofs: number[];
+    verified: boolean;
+}
+
+/** Error answered by the API, with its stable `code`. */
+export class MediatorApiError extends Error {
+    constructor(readonly status: number, readonly body: ErrorBody) {
+        super(body.message);
+        this.name = 'MediatorApiError';
+    }
+}
+
+type Query = Record<string, string | number | boolean | null | undefined>;
+
+export class MediatorApi {
+    constructor(readonly baseUrl: string) {}
+
+    /** `POST /airdrop`: The confirmed airdrop */
+    airdrop(body: AirdropRequest): Promise<AirdropResponse> {
+        return this.request('POST', '/airdrop', body);
+    }
+
+    /** `POST /endSubscription`: The confirmed transaction and the accounts it left behind */
+    endSubscription(body: EndSubscriptionRequest): Promise<InstructionResponse> {
+        return this.request('POST', '/endSubscription', body);
+    }
+
+    /** `GET /events`: Server-sent events named after their `type`, plus `lagged` events carrying a `LaggedEvent` */
+    events(onEvent: (event: SubscriptionEvent) => void, query: { program_context?: string | null } = {}): EventSource {
+        const source = new EventSource(this.url('/events', query));
+        for (const type of ['turn_changed', 'proof_submitted', 'funds_released', 'subscription_ended']) {
+            source.addEventListener(type, message => onEvent(JSON.parse((message as MessageEvent).data)));
+        }
+        return source;
+    }
+
+    /** `POST /extendSubscription`: The confirmed transaction and the accounts it left behind */
+    extendSubscription(body: ExtendSubscriptionRequest): Promise<InstructionResponse> {
+        return this.request('POST', '/extendSubscription', body);
+    }
+
+    /** `POST /initializeSubscription`: The confirmed transaction and the accounts it left behind */
+    initializeSubscription(body: InitializeSubscriptionRequest): Promise<InstructionResponse> {
+        return this.request('POST', '/initializeSubscription', body);
+    }
+
+    /** `POST /issueMerkleChallenge`: The confirmed transaction and the accounts it left behind */
+    issueMerkleChallenge(body: IssueMerkleChallengeRequest): Promise<InstructionResponse> {
+        return this.request('POST', '/issueMerkleChallenge', body);
+    }
+
+    /** `POST /retrieve`: The confirmed transaction and the accounts it left behind */
+    retrieve(body: RetrieveRequest): Promise<InstructionResponse> {
+        return this.request('POST', '/retrieve', body);
+    }
+
+    /** `POST /setClientCurvePoints`: The confirmed transaction and the accounts it left behind */
+    setClientCurvePoints(body: SetClientCurvePointsRequest): Promise<InstructionResponse> {
+        return this.request('POST', '/setClientCurvePoints', body);
+    }
+
+    /** `POST /setMerkleCommitment`: The confirmed transaction and the accounts it left behind */
+    setMerkleCommitment(body: SetMerkleCommitmentRequest): Promise<InstructionResponse> {
+        return this.request('POST', '/setMerkleCommitment', body);
+    }
+
+    /** `GET /signers`: Signers of the keystore */
+    listSigners(): Promise<SignerResponse[]> {
+        return this.request('GET', '/signers');
+    }
+
+    /** `POST /signers`: The generated signer */
+    createSigner(body: CreateSignerRequest): Promise<SignerResponse> {
+        return this.request('POST', '/signers', body);
+    }
+
+    /** `POST /submitAggregatedProof`: The confirmed transaction and the accounts it left behind */
+    submitAggregatedProof(body: SubmitAggregatedProofRequest): Promise<InstructionResponse> {
+        return this.request('POST', '/submitAggregatedProof', body);
+    }
+
+    /** `POST /submitMerkleProof`: The confirmed transaction and the accounts it left behind */
+    submitMerkleProof(body: SubmitMerkleProofRequest): Promise<InstructionResponse> {
+        return this.request('POST', '/submitMerkleProof', body);
+    }
+
+    /** `GET /subscriptions`: The matching subscriptions */
+    listSubscriptions(query: { client?: string | null; server?: string | null } = {}): Promise<ProgramContextSnapshot[]> {
+        return this.request('GET', '/subscriptions', undefined, query);
+    }
+
+    /** `GET /subscriptions/{program_context}`: The subscription and its balances */
+    getSubscription(programContext: string, query: { mediator?: string | null } = {}): Promise<SubscriptionResponse> {
+        return this.request('GET', `/subscriptions/${encodeURIComponent(programContext)}`, undefined, query);
+    }
+
+    /** `GET /subscriptions/{program_context}/history`: Transactions of the subscription, newest first */
+    subscriptionHistory(programContext: string, query: { before?: string | null; limit?: number | null } = {}): Promise<HistoryEntryResponse[]> {
+        return this.request('GET', `/subscriptions/${encodeURIComponent(programContext)}/history`, undefined, query);
+    }
+
+    /** `POST /updateFileState`: The confirmed transaction and the accounts it left behind */
+    updateFileState(body: UpdateFileStateRequest): Promise<InstructionResponse> {
+        return this.request('POST', '/updateFileState', body);
+    }
+
+    /** `POST /verify`: `Verified` or `Not Verified` */
+    verify(body: RequestPayload): Promise<string> {
+        return this.request('POST', '/verify', body);
+    }
+
+    /** `POST /verifyBatch`: Outcome of the batch verification */
+    verifyBatch(body: RequestPayload[]): Promise<VerifyBatchResponse> {
+        return this.request('POST', '/verifyBatch', body);
+    }
+
+    private url(path: string, query: Query = {}): string {
+        const params = new URLSearchParams();
+        for (const [name, value] of Object.entries(query)) {
+            if (value !== undefined && value !== null) {
+                params.append(name, String(value));
+            }
+        }
+        const search = params.toString();
+        return `${this.baseUrl.replace(/\/$/, '')}${path}${search ? `?${search}` : ''}`;
+    }
+
+    private async request<T>(method: string, path: string, body?: unknown, query?: Query): Promise<T> {
+        const response = await fetch(this.url(path, query), {
+            body: body === undefined ? undefined : JSON.stringify(body),
+            headers: body === undefined ? undefined : { 'Content-Type': 'application/json' },
+            method,
+        });
+        const payload = await response.json();
+        if (!response.ok) {
+            throw new MediatorApiError(response.status, payload as ErrorBody);
+        }
+        return payload as T;
+    }
+}
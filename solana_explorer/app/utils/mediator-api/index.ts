@@ -0,0 +1,27 @@
+import { useEffect, useState } from 'react';
+
+import { MediatorApi, SubscriptionEvent } from './client';
+
+export * from './client';
+
+// HTTP API of the mediator client in `client/`, regenerated with `pnpm generate:mediator-api`
+export const mediatorApi = new MediatorApi(process.env.NEXT_PUBLIC_MEDIATOR_API_URL ?? 'http://127.0.0.1:3030');
+
+// Live events of one subscription, oldest first, keeping the `limit` latest
+export function useSubscriptionEvents(programContext: string | undefined, limit = 50): SubscriptionEvent[] {
+    const [events, setEvents] = useState<SubscriptionEvent[]>([]);
+
+    useEffect(() => {
+        setEvents([]);
+        if (!programContext) {
+            return;
+        }
+
+        const source = mediatorApi.events(event => setEvents(previous => [...previous, event].slice(-limit)), {
+            program_context: programContext,
+        });
+        return () => source.close();
+    }, [programContext, limit]);
+
+    return events;
+}
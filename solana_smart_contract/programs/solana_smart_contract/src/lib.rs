@@ -1,8 +1,26 @@
 use anchor_lang::prelude::*;
 use anchor_lang::system_program;
+use anchor_lang::solana_program::hash::hashv;
 
 declare_id!("AS3mJ2X2HY6jrGD25QFHpFZWA5u3uFRBYEJgKDJWkmaZ");
 
+/// Maximum number of leaves a Merkle challenge may ask for
+pub const MAX_MERKLE_CHALLENGE_SIZE: u8 = 8;
+
+/// Bytes of file data in every leaf of a Merkle commitment, the last leaf padded with zeros
+pub const MERKLE_LEAF_SIZE: usize = 64;
+
+/// Bytes of leaves and paths a `submit_merkle_proof` transaction can carry: the 1232-byte
+/// packet minus the signature, the account keys, the blockhash and the instruction framing
+pub const MAX_MERKLE_ANSWER_LEN: usize = 1000;
+
+/// Domain separation prefixes, shared with the client's Merkle tree
+pub const MERKLE_LEAF_PREFIX: u8 = 0x00;
+pub const MERKLE_NODE_PREFIX: u8 = 0x01;
+
+/// Bytes per sector of a BLS block, shared with the client's tagger
+pub const POR_SECTOR_SIZE: u32 = 31;
+
 #[program]
 pub mod solana_smart_contract {
     use super::*;
@@ -19,7 +37,11 @@ pub mod solana_smart_contract {
     pub fn set_client_curve_points(
         ctx: Context<SetClientCurvePoints>,
         g_norm: [u8; 96],
-        v_norm: [u8; 96]
+        v_norm: [u8; 96],
+        pop: [u8; 48],
+        sectors_per_block: u32,
+        block_size: u32,
+        server: Pubkey
     ) -> Result<()> {
         let payer = &ctx.accounts.payer;
         let mediator = &ctx.accounts.mediator;
@@ -37,6 +59,23 @@ pub mod solana_smart_contract {
             return Err(ErrorCode::InvalidCurvePoints.into()); // Return error if v_norm is all zeros
         }
 
+        // Pairings don't fit in the compute budget, so the proof of possession is only verified
+        // off-chain, by `pop::verify_program_context` in the client library, before submission and
+        // by every verifier reading the context. Here we only reject malformed encodings
+        if !is_well_formed_g1_encoding(&pop) {
+            return Err(ErrorCode::MalformedProofOfPossession.into());
+        }
+
+        // Validate that the block size needs exactly `sectors_per_block` sectors
+        if !is_valid_por_params(sectors_per_block, block_size) {
+            return Err(ErrorCode::InvalidPorParameters.into());
+        }
+
+        // Validate that the subscription doesn't already use the Merkle proof mode
+        if program_context.proof_kind != ProofKind::Bls {
+            return Err(ErrorCode::ProofKindMismatch.into());
+        }
+
         // Validate that program_context.g_norm is not set
         if !program_context.g_norm.iter().all(|&x| x == 0) {
             return Err(ErrorCode::CurvePointsAlreadySet.into()); // Return error if g_norm is all zeros
@@ -77,12 +116,244 @@ pub mod solana_smart_contract {
 
         program_context.g_norm = g_norm;
         program_context.v_norm = v_norm;
+        program_context.client = payer.key();   // Only the client may later update the file state
+        program_context.pop = pop;
+        program_context.sectors_per_block = sectors_per_block;
+        program_context.block_size = block_size;
+        program_context.server = server;    // Only this server may prove and be paid
 
         msg!("Transferred 1 SOL from client {:?} to mediator {:?}", payer.key(), mediator.key());
         msg!("Initialized shared context for subscription: Duration: {}, Mediator Balance: {}",
              program_context.subscription_duration,
              program_context.mediator_balance);
-        msg!("Stored g_norm and v_norm in shared context");
+        msg!("Stored g_norm, v_norm and their proof of possession in shared context");
+        msg!("Blocks of {} bytes split into {} sectors", block_size, sectors_per_block);
+
+        Ok(())
+    }
+
+    pub fn set_merkle_commitment(
+        ctx: Context<SetMerkleCommitment>,
+        block_count: u64,
+        merkle_root: [u8; 32],
+        server: Pubkey
+    ) -> Result<()> {
+        let payer = &ctx.accounts.payer;
+        let mediator = &ctx.accounts.mediator;
+        let program_context = &mut ctx.accounts.program_context;
+
+        // Even a single leaf and its path must fit in the answer transaction
+        if block_count == 0 || merkle_answer_len(1, block_count) > MAX_MERKLE_ANSWER_LEN {
+            return Err(ErrorCode::InvalidMerkleCommitment.into());
+        }
+
+        // Validate that the subscription was not started in either proof mode
+        if program_context.subscription_duration > 0 {
+            return Err(ErrorCode::CurvePointsAlreadySet.into());
+        }
+
+        let transfer_amount = 1_000_000_000; // 1 SOL in lamports
+
+        // Ensure the client has at least 1 SOL (1_000_000_000 lamports)
+        if payer.lamports() < transfer_amount {
+            return Err(ErrorCode::InsufficientFunds.into());
+        }
+
+        // Perform the transfer of 1 SOL (1_000_000_000 lamports) from the client to the mediator
+        let ix = anchor_lang::solana_program::system_instruction::transfer(
+            &ctx.accounts.payer.key(),
+            &ctx.accounts.mediator.key(),
+            transfer_amount,
+        );
+
+        anchor_lang::solana_program::program::invoke(
+            &ix,
+            &[
+                ctx.accounts.payer.to_account_info(),
+                ctx.accounts.mediator.to_account_info(),
+            ],
+        ).expect("Transaction failed");
+
+        program_context.is_subscription_ended = false;  // Subscription is active at the start
+        program_context.is_server_turn = true;      // turn the turn to the server
+        program_context.subscription_duration = 1;
+        program_context.mediator_balance = transfer_amount;  // Set mediator balance to 1 SOL
+
+        // In Merkle mode the file state root is the root of the tree over the leaf hashes
+        program_context.proof_kind = ProofKind::Merkle;
+        program_context.client = payer.key();
+        program_context.file_version = 1;
+        program_context.block_count = block_count;
+        program_context.file_state_root = merkle_root;
+        program_context.server = server;    // Only this server may prove and be paid
+
+        msg!("Transferred 1 SOL from client {:?} to mediator {:?}", payer.key(), mediator.key());
+        msg!("Stored Merkle commitment over {} leaves in shared context", block_count);
+
+        Ok(())
+    }
+
+    pub fn issue_merkle_challenge(
+        ctx: Context<IssueMerkleChallenge>,
+        challenge_seed: [u8; 32],
+        challenge_size: u8
+    ) -> Result<()> {
+        let payer = &ctx.accounts.payer;
+        let program_context = &mut ctx.accounts.program_context;
+
+        if program_context.proof_kind != ProofKind::Merkle {
+            return Err(ErrorCode::ProofKindMismatch.into());
+        }
+
+        if program_context.is_subscription_ended {
+            return Err(ErrorCode::SubscriptionEnded.into());
+        }
+
+        if program_context.client != payer.key() {
+            return Err(ErrorCode::NotSubscriptionClient.into());
+        }
+
+        // The whole answer must fit in one transaction, so larger files get smaller challenges
+        if challenge_size == 0
+            || challenge_size > MAX_MERKLE_CHALLENGE_SIZE
+            || merkle_answer_len(challenge_size, program_context.block_count) > MAX_MERKLE_ANSWER_LEN
+        {
+            return Err(ErrorCode::InvalidChallengeSize.into());
+        }
+
+        program_context.challenge_seed = challenge_seed;
+        program_context.challenge_size = challenge_size;
+        program_context.is_challenge_pending = true;   // the server can't retrieve before answering
+
+        msg!("Merkle challenge issued for {} blocks", challenge_size);
+
+        Ok(())
+    }
+
+    pub fn submit_merkle_proof(
+        ctx: Context<SubmitMerkleProof>,
+        proofs: Vec<MerkleLeafProof>
+    ) -> Result<()> {
+        let server = &ctx.accounts.server;
+        let program_context = &mut ctx.accounts.program_context;
+
+        if program_context.proof_kind != ProofKind::Merkle {
+            return Err(ErrorCode::ProofKindMismatch.into());
+        }
+
+        if program_context.server != server.key() {
+            return Err(ErrorCode::NotSubscriptionServer.into());
+        }
+
+        if !program_context.is_challenge_pending {
+            return Err(ErrorCode::NoPendingChallenge.into());
+        }
+
+        if proofs.len() != program_context.challenge_size as usize {
+            return Err(ErrorCode::InvalidMerkleProof.into());
+        }
+
+        for (k, proof) in proofs.iter().enumerate() {
+            if proof.block.len() != MERKLE_LEAF_SIZE {
+                return Err(ErrorCode::InvalidMerkleProof.into());
+            }
+
+            let index = merkle_challenge_index(&program_context.challenge_seed, k as u8, program_context.block_count);
+            let leaf = hashv(&[&[MERKLE_LEAF_PREFIX], &proof.block]).to_bytes();
+
+            if !verify_merkle_path(&program_context.file_state_root, leaf, index, &proof.path) {
+                msg!("Merkle proof for block {} is invalid", index);
+                return Err(ErrorCode::InvalidMerkleProof.into());
+            }
+        }
+
+        program_context.is_challenge_pending = false;
+
+        msg!("Merkle proof for {} blocks verified", proofs.len());
+
+        Ok(())
+    }
+
+    pub fn submit_aggregated_proof<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SubmitAggregatedProof<'info>>,
+        sigma: [u8; 48],
+        mu: Vec<[u8; 32]>
+    ) -> Result<()> {
+        let server = &ctx.accounts.server;
+
+        // The aggregated proof covers the program contexts passed as remaining accounts
+        if ctx.remaining_accounts.is_empty() {
+            return Err(ErrorCode::NoProgramContexts.into());
+        }
+
+        // Pairings don't fit in the compute budget, so the proof is recorded here and
+        // verified off-chain by the client against the shared public key
+        let mut contexts = Vec::with_capacity(ctx.remaining_accounts.len());
+        for account_info in ctx.remaining_accounts.iter() {
+            if !account_info.is_writable {
+                return Err(ErrorCode::NoProgramContexts.into());
+            }
+
+            if contexts.iter().any(|context: &Account<'info, ProgramContext>| context.key() == account_info.key()) {
+                return Err(ErrorCode::DuplicateProgramContext.into());
+            }
+
+            let program_context: Account<'info, ProgramContext> = Account::try_from(account_info)?;
+
+            if program_context.proof_kind != ProofKind::Bls {
+                return Err(ErrorCode::ProofKindMismatch.into());
+            }
+
+            // A proof counts only for the subscriptions of the server that submits it
+            if program_context.server != server.key() {
+                return Err(ErrorCode::NotSubscriptionServer.into());
+            }
+
+            if !program_context.is_server_turn {
+                return Err(ErrorCode::NotServerTurn.into());
+            }
+
+            // Keys registered without a proof of possession could be rogue keys
+            if !is_well_formed_g1_encoding(&program_context.pop) {
+                return Err(ErrorCode::MalformedProofOfPossession.into());
+            }
+
+            // The proof holds one mu_j per sector
+            if mu.len() != program_context.sectors_per_block as usize {
+                return Err(ErrorCode::SectorCountMismatch.into());
+            }
+
+            // Aggregation is only sound for files tagged with the same key
+            if let Some(first) = contexts.first() {
+                if first.g_norm != program_context.g_norm || first.v_norm != program_context.v_norm {
+                    return Err(ErrorCode::PublicKeyMismatch.into());
+                }
+            }
+
+            contexts.push(program_context);
+        }
+
+        let mut proof_data = Vec::with_capacity(1 + mu.len() + contexts.len());
+        proof_data.push(sigma.as_ref());
+        proof_data.extend(mu.iter().map(|mu_j| mu_j.as_ref()));
+        let context_keys: Vec<Pubkey> = contexts.iter().map(|context| context.key()).collect();
+        proof_data.extend(context_keys.iter().map(|key| key.as_ref()));
+        let proof_digest = hashv(&proof_data).to_bytes();
+
+        for program_context in contexts.iter_mut() {
+            program_context.last_proof_digest = proof_digest;
+            program_context.proof_count += 1;
+            program_context.exit(&crate::ID)?;
+        }
+
+        emit!(AggregatedProofSubmitted {
+            server: server.key(),
+            program_contexts: context_keys,
+            sigma,
+            mu,
+        });
+
+        msg!("Aggregated proof recorded for {} subscriptions", contexts.len());
 
         Ok(())
     }
@@ -138,6 +409,56 @@ pub mod solana_smart_contract {
         Ok(())
     }
 
+    pub fn update_file_state(
+        ctx: Context<UpdateFileState>,
+        file_version: u64,
+        block_count: u64,
+        file_state_root: [u8; 32]
+    ) -> Result<()> {
+        let payer = &ctx.accounts.payer;
+        let program_context = &mut ctx.accounts.program_context;
+
+        if program_context.subscription_duration < 1 {
+            return Err(ErrorCode::SubscriptionDoesntStart.into());
+        }
+
+        if program_context.is_subscription_ended {
+            return Err(ErrorCode::SubscriptionEnded.into());
+        }
+
+        // Only the client that registered the curve points may change the committed file
+        if program_context.client != payer.key() {
+            return Err(ErrorCode::NotSubscriptionClient.into());
+        }
+
+        // The committed root can't change under a Merkle challenge being answered
+        if program_context.is_challenge_pending {
+            return Err(ErrorCode::ChallengePending.into());
+        }
+
+        // Challenges pick their blocks modulo the block count, so it can never drop to zero
+        if block_count == 0 {
+            return Err(ErrorCode::EmptyFileState.into());
+        }
+
+        if program_context.proof_kind == ProofKind::Merkle && merkle_answer_len(1, block_count) > MAX_MERKLE_ANSWER_LEN {
+            return Err(ErrorCode::InvalidMerkleCommitment.into());
+        }
+
+        // Every update must bump the version by exactly one, so an old state cannot be replayed
+        if file_version != program_context.file_version + 1 {
+            return Err(ErrorCode::StaleFileVersion.into());
+        }
+
+        program_context.file_version = file_version;
+        program_context.block_count = block_count;
+        program_context.file_state_root = file_state_root;
+
+        msg!("File state updated: Version: {}, Block count: {}", file_version, block_count);
+
+        Ok(())
+    }
+
     pub fn end_subscription(
         ctx: Context<EndSubscription>
     ) -> Result<()> {
@@ -161,25 +482,25 @@ pub mod solana_smart_contract {
 
         let mediator_lamports = mediator.to_account_info().lamports();  // Dereference to get lamports value
 
+        if program_context.server != server.key() {
+            return Err(ErrorCode::NotSubscriptionServer.into());
+        }
+
         if !program_context.is_server_turn {
             return Err(ErrorCode::NotServerTurn.into());
         }
 
+        // In Merkle mode the server must answer the pending challenge before being paid
+        if program_context.is_challenge_pending {
+            return Err(ErrorCode::ChallengePending.into());
+        }
+
+        // The mediator account is owned by this program, not by the system program, so its
+        // lamports are moved directly instead of through a system transfer
         if program_context.is_subscription_ended {
             // Perform the transfer from the mediator to the server
-            let ix = anchor_lang::solana_program::system_instruction::transfer(
-                &ctx.accounts.mediator.key(),
-                &ctx.accounts.server.key(),
-                mediator_lamports,
-            );
-
-            anchor_lang::solana_program::program::invoke(
-                &ix,
-                &[
-                    ctx.accounts.mediator.to_account_info(),
-                    ctx.accounts.server.to_account_info(),
-                ],
-            ).expect("Transaction failed");
+            **mediator.to_account_info().try_borrow_mut_lamports()? -= mediator_lamports;
+            **server.to_account_info().try_borrow_mut_lamports()? += mediator_lamports;
 
             msg!("Transferred {:?} SOL from mediator {:?} to server {:?}", mediator_lamports, mediator.key(), server.key());
         }
@@ -189,19 +510,8 @@ pub mod solana_smart_contract {
             // Ensure the mediator hold more than 5 SOL so will transfer SOL to server
             if mediator_lamports > 5 * transfer_amount {
                 // Perform the transfer from the mediator to the server
-                let ix = anchor_lang::solana_program::system_instruction::transfer(
-                    &ctx.accounts.mediator.key(),
-                    &ctx.accounts.server.key(),
-                    transfer_amount,
-                );
-
-                anchor_lang::solana_program::program::invoke(
-                    &ix,
-                    &[
-                        ctx.accounts.mediator.to_account_info(),
-                        ctx.accounts.server.to_account_info(),
-                    ],
-                ).expect("Transaction failed");
+                **mediator.to_account_info().try_borrow_mut_lamports()? -= transfer_amount;
+                **server.to_account_info().try_borrow_mut_lamports()? += transfer_amount;
             }
 
             msg!("Transferred 1 SOL from mediator {:?} to server {:?}", mediator.key(), server.key());
@@ -214,6 +524,56 @@ pub mod solana_smart_contract {
     }
 }
 
+/// Checks the flag bits of a compressed G1 point encoding: compressed, and not the point at
+/// infinity. Whether the bytes decode to a point of G1 is left to off-chain verifiers
+pub fn is_well_formed_g1_encoding(point: &[u8; 48]) -> bool {
+    let compression_flag = point[0] & 0x80 != 0;
+    let infinity_flag = point[0] & 0x40 != 0;
+    compression_flag && !infinity_flag
+}
+
+/// Checks that blocks of `block_size` bytes need exactly `sectors_per_block` sectors
+pub fn is_valid_por_params(sectors_per_block: u32, block_size: u32) -> bool {
+    sectors_per_block > 0
+        && block_size > (sectors_per_block - 1).saturating_mul(POR_SECTOR_SIZE)
+        && block_size <= sectors_per_block.saturating_mul(POR_SECTOR_SIZE)
+}
+
+/// Index of the `k`-th block asked by a Merkle challenge: `sha256(seed || k) mod block_count`
+pub fn merkle_challenge_index(challenge_seed: &[u8; 32], k: u8, block_count: u64) -> u64 {
+    let digest = hashv(&[challenge_seed, &[k]]).to_bytes();
+    let mut value = [0u8; 8];
+    value.copy_from_slice(&digest[..8]);
+    u64::from_le_bytes(value) % block_count
+}
+
+/// Number of siblings on the authentication path of a tree over `leaf_count` leaves
+pub fn merkle_depth(leaf_count: u64) -> usize {
+    (u64::BITS - leaf_count.saturating_sub(1).leading_zeros()) as usize
+}
+
+/// Bytes of instruction data taken by the answer to a challenge of `challenge_size` leaves:
+/// every leaf and its path, each with its 4-byte length prefix
+pub fn merkle_answer_len(challenge_size: u8, leaf_count: u64) -> usize {
+    challenge_size as usize * (4 + MERKLE_LEAF_SIZE + 4 + 32 * merkle_depth(leaf_count))
+}
+
+/// Recomputes the Merkle root from a leaf hash and its authentication path
+pub fn verify_merkle_path(root: &[u8; 32], leaf: [u8; 32], index: u64, path: &[[u8; 32]]) -> bool {
+    let mut node = leaf;
+    let mut position = index;
+    for sibling in path {
+        node = if position % 2 == 0 {
+            hashv(&[&[MERKLE_NODE_PREFIX], &node, sibling]).to_bytes()
+        } else {
+            hashv(&[&[MERKLE_NODE_PREFIX], sibling, &node]).to_bytes()
+        };
+        position /= 2;
+    }
+
+    position == 0 && node == *root
+}
+
 #[error_code]
 pub enum ErrorCode {
     #[msg("The curve points cannot be the default array of zeros.")]
@@ -233,6 +593,57 @@ pub enum ErrorCode {
 
     #[msg("This is not server turn.")]
     NotServerTurn,
+
+    #[msg("The subscription already ended.")]
+    SubscriptionEnded,
+
+    #[msg("Only the subscription client can perform this action.")]
+    NotSubscriptionClient,
+
+    #[msg("The file version must be exactly one more than the committed version.")]
+    StaleFileVersion,
+
+    #[msg("The instruction doesn't match the subscription proof kind.")]
+    ProofKindMismatch,
+
+    #[msg("The Merkle commitment must cover at least one block.")]
+    InvalidMerkleCommitment,
+
+    #[msg("The challenge size is out of range.")]
+    InvalidChallengeSize,
+
+    #[msg("There is no pending challenge.")]
+    NoPendingChallenge,
+
+    #[msg("The server must answer the pending challenge first.")]
+    ChallengePending,
+
+    #[msg("The Merkle proof doesn't match the committed root.")]
+    InvalidMerkleProof,
+
+    #[msg("The proof must cover at least one writable program context.")]
+    NoProgramContexts,
+
+    #[msg("Aggregated subscriptions must share the same curve points.")]
+    PublicKeyMismatch,
+
+    #[msg("A program context is covered more than once by the proof.")]
+    DuplicateProgramContext,
+
+    #[msg("The proof of possession is not a well-formed compressed G1 point encoding; it is verified off-chain.")]
+    MalformedProofOfPossession,
+
+    #[msg("The block size doesn't match the number of sectors per block.")]
+    InvalidPorParameters,
+
+    #[msg("The proof doesn't hold one value per sector.")]
+    SectorCountMismatch,
+
+    #[msg("Only the subscription server can perform this action.")]
+    NotSubscriptionServer,
+
+    #[msg("The file state must cover at least one block.")]
+    EmptyFileState,
 }
 
 #[derive(Accounts)]
@@ -250,7 +661,7 @@ pub struct Initialize<'info> {
     #[account(
         init,
         payer = payer,
-        space = 8 + 1 + 1 + 8 + 8 + 96 + 96
+        space = 8 + 1 + 1 + 8 + 8 + 96 + 96 + 32 + 8 + 8 + 32 + 1 + 32 + 1 + 1 + 32 + 8 + 48 + 4 + 4 + 32
     )]
     pub program_context: Account<'info, ProgramContext>,
 
@@ -271,6 +682,41 @@ pub struct SetClientCurvePoints<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct SetMerkleCommitment<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut)]
+    pub mediator: Account<'info, Mediator>,
+
+    #[account(mut)]
+    pub program_context: Account<'info, ProgramContext>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct IssueMerkleChallenge<'info> {
+    pub payer: Signer<'info>,
+
+    #[account(mut)]
+    pub program_context: Account<'info, ProgramContext>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitMerkleProof<'info> {
+    pub server: Signer<'info>,
+
+    #[account(mut)]
+    pub program_context: Account<'info, ProgramContext>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitAggregatedProof<'info> {
+    pub server: Signer<'info>,
+}
+
 #[derive(Accounts)]
 pub struct ExtendSubscription<'info> {
     #[account(mut)]
@@ -285,6 +731,14 @@ pub struct ExtendSubscription<'info> {
     pub system_program: Program<'info, System>, //todo: maybe not needed
 }
 
+#[derive(Accounts)]
+pub struct UpdateFileState<'info> {
+    pub payer: Signer<'info>,
+
+    #[account(mut)]
+    pub program_context: Account<'info, ProgramContext>,
+}
+
 #[derive(Accounts)]
 pub struct EndSubscription<'info> {
     #[account(mut)]
@@ -318,4 +772,38 @@ pub struct ProgramContext {
     pub mediator_balance: u64,          // Mediator's balance in lamports
     pub g_norm: [u8; 96],               // Array to store g_norm (96 bytes)
     pub v_norm: [u8; 96],               // Array to store v_norm (96 bytes)
+    pub client: Pubkey,                 // Client that registered the curve points
+    pub file_version: u64,              // Version of the committed file state, bumped on every update
+    pub block_count: u64,               // Number of blocks (BLS) or leaves (Merkle) in the committed file
+    pub file_state_root: [u8; 32],      // Merkle root over the block versions (BLS) or the blocks (Merkle)
+    pub proof_kind: ProofKind,          // How the server proves it still stores the file
+    pub challenge_seed: [u8; 32],       // Seed of the pending Merkle challenge
+    pub challenge_size: u8,             // Number of blocks asked by the pending Merkle challenge
+    pub is_challenge_pending: bool,     // Indicates if the server must answer a Merkle challenge
+    pub last_proof_digest: [u8; 32],    // Hash of the last (possibly aggregated) BLS proof submitted
+    pub proof_count: u64,               // Number of BLS proofs submitted for this subscription
+    pub pop: [u8; 48],                  // Proof of possession of the secret key behind v_norm
+    pub sectors_per_block: u32,         // Sectors s per BLS block, one u_j per sector
+    pub block_size: u32,                // Bytes per BLS block covered by a single tag
+    pub server: Pubkey,                 // Server storing the file, the only one allowed to prove and be paid
+}
+
+#[event]
+pub struct AggregatedProofSubmitted {
+    pub server: Pubkey,
+    pub program_contexts: Vec<Pubkey>,
+    pub sigma: [u8; 48],
+    pub mu: Vec<[u8; 32]>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProofKind {
+    Bls,        // Pairing based Compact PoR, verified off-chain
+    Merkle,     // Merkle authentication paths, verified on-chain
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct MerkleLeafProof {
+    pub block: Vec<u8>,         // Content of the challenged leaf, `MERKLE_LEAF_SIZE` bytes
+    pub path: Vec<[u8; 32]>,    // Sibling hashes from the leaf up to the root
 }